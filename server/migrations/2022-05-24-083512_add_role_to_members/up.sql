@@ -0,0 +1,10 @@
+-- Your SQL goes here
+alter table "members"
+	add column "role" text
+	check ("role" in ('owner', 'admin', 'moderator', 'member'))
+	default 'member' not null;
+
+update "members" m
+	set "role" = 'owner'
+	from "conversations" c
+	where c."id" = m."conversation_id" and c."admin_id" = m."user_id";
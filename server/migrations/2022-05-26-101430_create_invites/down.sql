@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+drop table if exists "join_requests" cascade;
+drop table if exists "invites" cascade;
+alter table "conversations" drop column "join_approval";
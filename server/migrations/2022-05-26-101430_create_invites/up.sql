@@ -0,0 +1,38 @@
+-- Your SQL goes here
+alter table "conversations" add column "join_approval" boolean not null default false;
+
+create table "invites" (
+	"id" uuid primary key default uuid_generate_v4(),
+	"code" varchar(32) unique not null,
+	"conversation_id" uuid not null,
+	"created_by" uuid not null,
+	"expires_at" timestamptz(0) null,
+	"max_uses" integer null,
+	"uses" integer not null default 0,
+	"is_revoked" boolean not null default false,
+	"created_at" timestamptz(0) not null default current_timestamp
+);
+
+alter table "invites"
+	add constraint "invites_conversation_id_foreign" foreign key ("conversation_id") references "conversations" ("id") on delete cascade;
+alter table "invites"
+	add constraint "invites_created_by_foreign" foreign key ("created_by") references "users" ("id") on delete cascade;
+
+create table "join_requests" (
+	"conversation_id" uuid not null,
+	"user_id" uuid not null,
+	"invite_id" uuid null,
+	"status" text check ("status" in ('pending', 'approved', 'denied')) default 'pending' not null,
+	"created_at" timestamptz(0) not null default current_timestamp,
+	"resolved_by" uuid null,
+	"resolved_at" timestamptz(0) null
+);
+
+alter table "join_requests"
+	add constraint "join_requests_pkey" primary key ("conversation_id", "user_id");
+alter table "join_requests"
+	add constraint "join_requests_conversation_id_foreign" foreign key ("conversation_id") references "conversations" ("id") on delete cascade;
+alter table "join_requests"
+	add constraint "join_requests_user_id_foreign" foreign key ("user_id") references "users" ("id") on delete cascade;
+alter table "join_requests"
+	add constraint "join_requests_invite_id_foreign" foreign key ("invite_id") references "invites" ("id") on delete set null;
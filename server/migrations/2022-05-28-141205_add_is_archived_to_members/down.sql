@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+alter table "members" drop column "is_archived";
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+alter table "members" add column "is_archived" boolean not null default false;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+alter table "message_edits" drop constraint "message_edits_message_id_foreign";
+
+drop table if exists "message_edits" cascade;
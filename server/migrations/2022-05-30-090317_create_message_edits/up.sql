@@ -0,0 +1,12 @@
+-- Your SQL goes here
+create table "message_edits" (
+	"id" uuid primary key default uuid_generate_v4(),
+	"message_id" uuid not null,
+	"content" text,
+	"edited_at" timestamptz(0) not null default current_timestamp
+);
+
+alter table "message_edits"
+	add constraint "message_edits_message_id_foreign" foreign key ("message_id") references "messages" ("id") on delete cascade;
+
+create index "message_edits_message_id_index" on "message_edits" ("message_id");
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+alter table "messages" drop constraint "messages_reply_to_id_foreign";
+alter table "messages" drop column "reply_to_id";
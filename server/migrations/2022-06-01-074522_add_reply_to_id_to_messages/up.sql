@@ -0,0 +1,5 @@
+-- Your SQL goes here
+alter table "messages" add column "reply_to_id" uuid null;
+
+alter table "messages"
+	add constraint "messages_reply_to_id_foreign" foreign key ("reply_to_id") references "messages" ("id") on delete set null;
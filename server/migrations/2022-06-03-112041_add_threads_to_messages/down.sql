@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+drop index if exists "messages_thread_root_id_created_at_index";
+alter table "messages" drop constraint "messages_thread_root_id_foreign";
+alter table "messages" drop column "thread_last_reply_at";
+alter table "messages" drop column "thread_reply_count";
+alter table "messages" drop column "thread_root_id";
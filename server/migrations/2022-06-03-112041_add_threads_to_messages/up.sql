@@ -0,0 +1,9 @@
+-- Your SQL goes here
+alter table "messages" add column "thread_root_id" uuid null;
+alter table "messages" add column "thread_reply_count" integer not null default 0;
+alter table "messages" add column "thread_last_reply_at" timestamptz(0) null;
+
+alter table "messages"
+	add constraint "messages_thread_root_id_foreign" foreign key ("thread_root_id") references "messages" ("id") on delete cascade;
+
+create index "messages_thread_root_id_created_at_index" on "messages" ("thread_root_id", "created_at");
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+alter table "message_mentions" drop constraint "message_mentions_conversation_id_foreign";
+alter table "message_mentions" drop constraint "message_mentions_user_id_foreign";
+alter table "message_mentions" drop constraint "message_mentions_message_id_foreign";
+
+drop table if exists "message_mentions" cascade;
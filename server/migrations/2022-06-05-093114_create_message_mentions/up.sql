@@ -0,0 +1,18 @@
+-- Your SQL goes here
+create table "message_mentions" (
+	"message_id" uuid not null,
+	"user_id" uuid not null,
+	"conversation_id" uuid not null,
+	"kind" text not null default 'user',
+	"created_at" timestamptz(0) not null default current_timestamp,
+	primary key ("message_id", "user_id")
+);
+
+alter table "message_mentions"
+	add constraint "message_mentions_message_id_foreign" foreign key ("message_id") references "messages" ("id") on delete cascade;
+alter table "message_mentions"
+	add constraint "message_mentions_user_id_foreign" foreign key ("user_id") references "users" ("id") on delete cascade;
+alter table "message_mentions"
+	add constraint "message_mentions_conversation_id_foreign" foreign key ("conversation_id") references "conversations" ("id") on delete cascade;
+
+create index "message_mentions_user_id_created_at_index" on "message_mentions" ("user_id", "created_at");
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+drop index if exists "messages_content_search_index";
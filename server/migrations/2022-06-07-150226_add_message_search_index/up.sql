@@ -0,0 +1,3 @@
+-- Your SQL goes here
+create index "messages_content_search_index" on "messages"
+	using gin (to_tsvector('simple', coalesce("content", '')));
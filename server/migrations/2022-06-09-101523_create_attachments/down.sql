@@ -0,0 +1,15 @@
+-- This file should undo anything in `up.sql`
+alter table "messages" add column "is_image" boolean not null default false;
+
+update "messages" m set "is_image" = true, "content" = a."url"
+from "attachments" a
+where a."message_id" = m."id" and a."position" = 0 and a."kind" in ('image', 'gif');
+
+update "conversations" c set "last_message_display" = (c."last_message_display" - 'attachments')
+	|| jsonb_build_object('content', m."content")
+from "messages" m
+where m."id" = c."last_message_id" and m."is_image" = true;
+
+alter table "attachments" drop constraint "attachments_message_id_foreign";
+
+drop table if exists "attachments" cascade;
@@ -0,0 +1,54 @@
+-- Your SQL goes here
+create table "attachments" (
+	"id" uuid primary key default uuid_generate_v4(),
+	"message_id" uuid not null,
+	"kind" text not null,
+	"url" text not null,
+	"mime_type" text,
+	"size" bigint,
+	"width" integer,
+	"height" integer,
+	"filename" text,
+	"position" integer not null default 0,
+	"created_at" timestamptz(0) not null default current_timestamp
+);
+
+alter table "attachments"
+	add constraint "attachments_message_id_foreign" foreign key ("message_id") references "messages" ("id") on delete cascade;
+
+create index "attachments_message_id_index" on "attachments" ("message_id");
+
+-- Image messages used to carry their URL in `content`; move it into an attachment.
+insert into "attachments" ("message_id", "kind", "url", "mime_type", "created_at")
+select "id",
+	case when "content" ilike '%giphy.com%' or "content" ilike '%.gif' or "content" ilike '%.gif?%' then 'gif' else 'image' end,
+	"content",
+	case when "content" ilike '%giphy.com%' or "content" ilike '%.gif' or "content" ilike '%.gif?%' then 'image/gif' else null end,
+	"created_at"
+from "messages"
+where "is_image" = true and "content" is not null;
+
+update "conversations" c set "last_message_display" = c."last_message_display"
+	|| jsonb_build_object('content', null)
+	|| jsonb_build_object('attachments', (
+		select coalesce(jsonb_agg(jsonb_build_object(
+			'id', a."id",
+			'messageId', a."message_id",
+			'kind', a."kind",
+			'url', a."url",
+			'mimeType', a."mime_type",
+			'size', a."size",
+			'width', a."width",
+			'height', a."height",
+			'filename', a."filename",
+			'position', a."position",
+			'createdAt', a."created_at"
+		) order by a."position"), '[]'::jsonb)
+		from "attachments" a where a."message_id" = c."last_message_id"
+	))
+from "messages" m
+where m."id" = c."last_message_id" and m."is_image" = true;
+
+update "messages" set "content" = null where "is_image" = true;
+
+alter table "messages" drop column "is_image";
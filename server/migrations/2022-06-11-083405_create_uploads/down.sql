@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+drop index if exists "attachments_upload_id_index";
+alter table "attachments" drop constraint "attachments_upload_id_foreign";
+alter table "attachments" drop column "upload_id";
+
+alter table "uploads" drop constraint "uploads_user_id_foreign";
+
+drop table if exists "uploads" cascade;
@@ -0,0 +1,25 @@
+-- Your SQL goes here
+create table "uploads" (
+	"id" uuid primary key default uuid_generate_v4(),
+	"user_id" uuid not null,
+	"backend" text not null,
+	"storage_key" text not null,
+	"url" text,
+	"mime_type" text not null,
+	"size" bigint not null,
+	"filename" text,
+	"purpose" text not null default 'attachment',
+	"created_at" timestamptz(0) not null default current_timestamp
+);
+
+alter table "uploads"
+	add constraint "uploads_user_id_foreign" foreign key ("user_id") references "users" ("id") on delete cascade;
+
+create index "uploads_user_id_index" on "uploads" ("user_id");
+
+alter table "attachments" add column "upload_id" uuid null;
+
+alter table "attachments"
+	add constraint "attachments_upload_id_foreign" foreign key ("upload_id") references "uploads" ("id") on delete set null;
+
+create index "attachments_upload_id_index" on "attachments" ("upload_id");
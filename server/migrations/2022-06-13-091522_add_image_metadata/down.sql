@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+alter table "attachments" drop column "thumbnails";
+alter table "attachments" drop column "blurhash";
+
+alter table "uploads" drop column "thumbnails";
+alter table "uploads" drop column "blurhash";
+alter table "uploads" drop column "height";
+alter table "uploads" drop column "width";
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+alter table "uploads" add column "width" integer null;
+alter table "uploads" add column "height" integer null;
+alter table "uploads" add column "blurhash" text null;
+alter table "uploads" add column "thumbnails" jsonb not null default '[]';
+
+alter table "attachments" add column "blurhash" text null;
+alter table "attachments" add column "thumbnails" jsonb not null default '[]';
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+drop table if exists "message_link_previews" cascade;
+drop table if exists "link_previews" cascade;
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+create table "link_previews" (
+	"id" uuid primary key default uuid_generate_v4(),
+	"url" text not null,
+	"status" text not null,
+	"title" text,
+	"description" text,
+	"image_url" text,
+	"site_name" text,
+	"fetched_at" timestamptz(0) not null default current_timestamp
+);
+
+create unique index "link_previews_url_unique" on "link_previews" ("url");
+
+create table "message_link_previews" (
+	"message_id" uuid not null,
+	"link_preview_id" uuid not null,
+	"position" integer not null,
+	primary key ("message_id", "link_preview_id")
+);
+
+alter table "message_link_previews"
+	add constraint "message_link_previews_message_id_foreign" foreign key ("message_id") references "messages" ("id") on delete cascade;
+alter table "message_link_previews"
+	add constraint "message_link_previews_link_preview_id_foreign" foreign key ("link_preview_id") references "link_previews" ("id") on delete cascade;
+
+create index "message_link_previews_link_preview_id_index" on "message_link_previews" ("link_preview_id");
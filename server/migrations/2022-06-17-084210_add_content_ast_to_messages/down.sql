@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+alter table "messages" drop column "content_ast";
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+alter table "messages" add column "content_ast" jsonb null;
@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+alter table "messages" add column "reactions" jsonb default '{}'::JSONB not null;
+
+update "messages"
+set "reactions" = "grouped"."reactions"
+from (
+	select "message_id", jsonb_object_agg(
+		"user_id"::text || ':' || "emoji",
+		jsonb_build_object('createdAt', to_char("created_at" at time zone 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"+00:00"'), 'name', "emoji")
+	) as "reactions"
+	from "reactions"
+	group by "message_id"
+) as "grouped"
+where "messages"."id" = "grouped"."message_id";
+
+drop table if exists "reactions" cascade;
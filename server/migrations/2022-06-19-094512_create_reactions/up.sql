@@ -0,0 +1,30 @@
+-- Your SQL goes here
+create table "reactions" (
+	"message_id" uuid not null,
+	"user_id" uuid not null,
+	"emoji" text not null,
+	"created_at" timestamptz(0) not null default current_timestamp,
+	primary key ("message_id", "user_id", "emoji")
+);
+
+alter table "reactions"
+	add constraint "reactions_message_id_foreign" foreign key ("message_id") references "messages" ("id") on delete cascade;
+alter table "reactions"
+	add constraint "reactions_user_id_foreign" foreign key ("user_id") references "users" ("id") on delete cascade;
+
+create index "reactions_message_id_emoji_index" on "reactions" ("message_id", "emoji", "created_at");
+create index "reactions_user_id_index" on "reactions" ("user_id");
+
+-- Keys in the old column look like "{user_id}:{emoji}".
+insert into "reactions" ("message_id", "user_id", "emoji", "created_at")
+select "messages"."id",
+	split_part("reaction"."key", ':', 1)::uuid,
+	substr("reaction"."key", 38),
+	coalesce(("reaction"."value"->>'createdAt')::timestamptz, "messages"."created_at")
+from "messages"
+cross join lateral jsonb_each("messages"."reactions") as "reaction"
+where "reaction"."key" ~ '^[0-9a-fA-F-]{36}:.+$'
+and exists (select 1 from "users" where "users"."id"::text = split_part("reaction"."key", ':', 1))
+on conflict do nothing;
+
+alter table "messages" drop column "reactions";
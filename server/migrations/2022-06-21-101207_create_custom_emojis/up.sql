@@ -0,0 +1,21 @@
+-- Your SQL goes here
+create table "custom_emojis" (
+	"id" uuid primary key default uuid_generate_v4(),
+	"name" text not null,
+	"conversation_id" uuid,
+	"upload_id" uuid not null,
+	"created_by" uuid not null,
+	"created_at" timestamptz(0) not null default current_timestamp
+);
+
+alter table "custom_emojis"
+	add constraint "custom_emojis_conversation_id_foreign" foreign key ("conversation_id") references "conversations" ("id") on delete cascade;
+alter table "custom_emojis"
+	add constraint "custom_emojis_upload_id_foreign" foreign key ("upload_id") references "uploads" ("id") on delete cascade;
+alter table "custom_emojis"
+	add constraint "custom_emojis_created_by_foreign" foreign key ("created_by") references "users" ("id") on delete cascade;
+
+-- A null conversation means the emoji is available everywhere on the server.
+create unique index "custom_emojis_conversation_id_name_unique" on "custom_emojis" ("conversation_id", "name") where "conversation_id" is not null;
+create unique index "custom_emojis_name_unique" on "custom_emojis" ("name") where "conversation_id" is null;
+create index "custom_emojis_upload_id_index" on "custom_emojis" ("upload_id");
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+alter table "messages" drop column "forwarded_from_author_id";
+alter table "messages" drop column "forwarded_from_conversation_id";
+alter table "messages" drop column "forwarded_from_message_id";
+alter table "messages" drop column "is_forwarded";
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+alter table "messages" add column "is_forwarded" boolean not null default false;
+alter table "messages" add column "forwarded_from_message_id" uuid;
+alter table "messages" add column "forwarded_from_conversation_id" uuid;
+alter table "messages" add column "forwarded_from_author_id" uuid;
+
+alter table "messages"
+	add constraint "messages_forwarded_from_message_id_foreign" foreign key ("forwarded_from_message_id") references "messages" ("id") on delete set null;
+alter table "messages"
+	add constraint "messages_forwarded_from_conversation_id_foreign" foreign key ("forwarded_from_conversation_id") references "conversations" ("id") on delete set null;
+alter table "messages"
+	add constraint "messages_forwarded_from_author_id_foreign" foreign key ("forwarded_from_author_id") references "users" ("id") on delete set null;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+create table "pinned_messages" (
+	"message_id" uuid primary key,
+	"conversation_id" uuid not null,
+	"pinned_by" uuid not null,
+	"pinned_at" timestamptz(0) not null default current_timestamp
+);
+
+alter table "pinned_messages"
+	add constraint "pinned_messages_message_id_foreign" foreign key ("message_id") references "messages" ("id") on delete cascade;
+alter table "pinned_messages"
+	add constraint "pinned_messages_conversation_id_foreign" foreign key ("conversation_id") references "conversations" ("id") on delete cascade;
+alter table "pinned_messages"
+	add constraint "pinned_messages_pinned_by_foreign" foreign key ("pinned_by") references "users" ("id") on delete cascade;
+
+create index "pinned_messages_conversation_id_pinned_at_index" on "pinned_messages" ("conversation_id", "pinned_at");
@@ -0,0 +1,19 @@
+-- Your SQL goes here
+create table "scheduled_messages" (
+	"id" uuid primary key default uuid_generate_v4(),
+	"conversation_id" uuid not null,
+	"author_id" uuid not null,
+	"content" text,
+	"attachments" jsonb not null default '[]',
+	"send_at" timestamptz(0) not null,
+	"created_at" timestamptz(0) not null default current_timestamp,
+	"updated_at" timestamptz(0)
+);
+
+alter table "scheduled_messages"
+	add constraint "scheduled_messages_conversation_id_foreign" foreign key ("conversation_id") references "conversations" ("id") on delete cascade;
+alter table "scheduled_messages"
+	add constraint "scheduled_messages_author_id_foreign" foreign key ("author_id") references "users" ("id") on delete cascade;
+
+create index "scheduled_messages_send_at_index" on "scheduled_messages" ("send_at");
+create index "scheduled_messages_author_id_send_at_index" on "scheduled_messages" ("author_id", "send_at");
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+create table "drafts" (
+	"user_id" uuid not null,
+	"conversation_id" uuid not null,
+	"content" text not null,
+	"updated_at" timestamptz(0) not null default current_timestamp,
+	primary key ("user_id", "conversation_id")
+);
+
+alter table "drafts"
+	add constraint "drafts_user_id_foreign" foreign key ("user_id") references "users" ("id") on delete cascade;
+alter table "drafts"
+	add constraint "drafts_conversation_id_foreign" foreign key ("conversation_id") references "conversations" ("id") on delete cascade;
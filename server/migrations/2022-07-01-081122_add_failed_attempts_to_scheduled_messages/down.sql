@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+alter table "scheduled_messages" drop column "failed_attempts";
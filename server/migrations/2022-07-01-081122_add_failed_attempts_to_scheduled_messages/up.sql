@@ -0,0 +1,2 @@
+-- Your SQL goes here
+alter table "scheduled_messages" add column "failed_attempts" integer not null default 0;
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+alter table "uploads" drop column "used_as_emoji";
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+alter table "uploads" add column "used_as_emoji" bool not null default false;
+update "uploads" set "used_as_emoji" = true where "id" in (select "upload_id" from "custom_emojis");
@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+const DEFAULT_PATH: &str = "emoji_catalogue.json";
+const DEFAULT_RELOAD_SECONDS: u64 = 30;
+pub const DEFAULT_SEARCH_LIMIT: usize = 10;
+pub const MAX_SEARCH_LIMIT: usize = 50;
+
+/// A built-in emoji. `name` is what reactions and shortcodes store; aliases resolve to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct CatalogueEmoji {
+	pub name: String,
+	/// Code points, dash separated, as used by the twemoji sprite.
+	pub unicode: String,
+	#[serde(default)]
+	pub aliases: Vec<String>,
+	#[serde(default)]
+	pub keywords: Vec<String>,
+	/// Skin tone variants, lightest to darkest.
+	#[serde(default)]
+	pub skins: Vec<String>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct Category {
+	pub name: String,
+	pub emojis: Vec<CatalogueEmoji>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CatalogueFile {
+	categories: Vec<Category>
+}
+
+#[derive(Debug)]
+pub enum CatalogueError {
+	Io(std::io::Error),
+	Parse(serde_json::Error)
+}
+
+impl fmt::Display for CatalogueError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			CatalogueError::Io(e) => write!(f, "reading the catalogue failed: {}", e),
+			CatalogueError::Parse(e) => write!(f, "the catalogue is malformed: {}", e)
+		}
+	}
+}
+
+/// One loaded version of the catalogue file.
+#[derive(Debug)]
+struct Snapshot {
+	categories: Vec<Category>,
+	/// Names and aliases to (category, emoji) positions.
+	lookup: HashMap<String, (usize, usize)>,
+	modified: Option<SystemTime>
+}
+
+impl Snapshot {
+	fn parse(data: &str, modified: Option<SystemTime>) -> Result<Snapshot, CatalogueError> {
+		let file: CatalogueFile = serde_json::from_str(data).map_err(CatalogueError::Parse)?;
+		let mut lookup: HashMap<String, (usize, usize)> = HashMap::new();
+		for (category_index, category) in file.categories.iter().enumerate() {
+			for (emoji_index, emoji) in category.emojis.iter().enumerate() {
+				lookup.entry(emoji.name.to_owned()).or_insert((category_index, emoji_index));
+			}
+		}
+		// Aliases never shadow a real name, whatever order they come in.
+		for (category_index, category) in file.categories.iter().enumerate() {
+			for (emoji_index, emoji) in category.emojis.iter().enumerate() {
+				for alias in emoji.aliases.iter() {
+					lookup.entry(alias.to_owned()).or_insert((category_index, emoji_index));
+				}
+			}
+		}
+		Ok(Snapshot { categories: file.categories, lookup, modified })
+	}
+
+	fn get(&self, name: &str) -> Option<&CatalogueEmoji> {
+		self.lookup.get(name).map(|(category_index, emoji_index)| &self.categories[*category_index].emojis[*emoji_index])
+	}
+
+	fn emojis(&self) -> impl Iterator<Item = &CatalogueEmoji> {
+		self.categories.iter().flat_map(|category| category.emojis.iter())
+	}
+}
+
+/// How well `emoji` matches the lowercased `query`; lower is better.
+fn match_rank(emoji: &CatalogueEmoji, query: &str) -> Option<u8> {
+	let word_starts = |candidate: &str| candidate.split(['-', '_', ' ']).any(|word| word.starts_with(query));
+	if emoji.name == query || emoji.aliases.iter().any(|alias| alias == query) {
+		Some(0)
+	} else if emoji.name.starts_with(query) {
+		Some(1)
+	} else if emoji.aliases.iter().any(|alias| alias.starts_with(query)) {
+		Some(2)
+	} else if word_starts(&emoji.name) || emoji.aliases.iter().any(|alias| word_starts(alias)) {
+		Some(3)
+	} else if emoji.keywords.iter().any(|keyword| word_starts(keyword)) {
+		Some(4)
+	} else if emoji.name.contains(query) {
+		Some(5)
+	} else {
+		None
+	}
+}
+
+/// The built-in emojis, loaded from a JSON file that can be edited while the server runs.
+pub struct EmojiCatalogue {
+	path: PathBuf,
+	snapshot: RwLock<Arc<Snapshot>>
+}
+
+impl EmojiCatalogue {
+	pub fn load(path: PathBuf) -> Result<EmojiCatalogue, CatalogueError> {
+		let snapshot = EmojiCatalogue::read(&path)?;
+		Ok(EmojiCatalogue { path, snapshot: RwLock::new(Arc::new(snapshot)) })
+	}
+
+	/// Loads the file at `EMOJI_CATALOGUE_PATH`, `emoji_catalogue.json` by default.
+	pub fn from_env() -> Result<EmojiCatalogue, CatalogueError> {
+		let path = env::var("EMOJI_CATALOGUE_PATH").unwrap_or_else(|_| String::from(DEFAULT_PATH));
+		EmojiCatalogue::load(PathBuf::from(path))
+	}
+
+	fn read(path: &PathBuf) -> Result<Snapshot, CatalogueError> {
+		let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+		let data = fs::read_to_string(path).map_err(CatalogueError::Io)?;
+		Snapshot::parse(&data, modified)
+	}
+
+	fn current(&self) -> Arc<Snapshot> {
+		self.snapshot.read().unwrap().clone()
+	}
+
+	/// Re-reads the file. On failure the previous catalogue stays in use.
+	/// Returns how many emojis were loaded.
+	pub fn reload(&self) -> Result<usize, CatalogueError> {
+		let snapshot = EmojiCatalogue::read(&self.path)?;
+		let count = snapshot.emojis().count();
+		*self.snapshot.write().unwrap() = Arc::new(snapshot);
+		Ok(count)
+	}
+
+	/// Reloads when the file's modification time differs from the loaded one.
+	pub fn reload_if_changed(&self) {
+		let modified = fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+		if modified.is_none() || modified == self.current().modified {
+			return;
+		}
+		match self.reload() {
+			Ok(count) => log::info!("reloaded emoji catalogue with {} emojis", count),
+			Err(e) => log::error!("keeping the previous emoji catalogue: {}", e)
+		}
+	}
+
+	/// Watches the file every `EMOJI_CATALOGUE_RELOAD_SECONDS` (30 by default, `0` turns it off).
+	pub fn spawn_reloader(catalogue: Arc<EmojiCatalogue>) {
+		let seconds = env::var("EMOJI_CATALOGUE_RELOAD_SECONDS")
+			.ok()
+			.and_then(|value| value.parse::<u64>().ok())
+			.unwrap_or(DEFAULT_RELOAD_SECONDS);
+		if seconds == 0 {
+			return;
+		}
+		actix_web::rt::spawn(async move {
+			let mut interval = actix_web::rt::time::interval(Duration::from_secs(seconds));
+			loop {
+				interval.tick().await;
+				catalogue.reload_if_changed();
+			}
+		});
+	}
+
+	pub fn categories(&self) -> Vec<Category> {
+		self.current().categories.clone()
+	}
+
+	/// Looks up an emoji by name or alias.
+	pub fn get(&self, name: &str) -> Option<CatalogueEmoji> {
+		self.current().get(name).cloned()
+	}
+
+	/// The name reactions should be stored under, so aliases of an emoji count together.
+	pub fn canonical_name(&self, name: &str) -> Option<String> {
+		self.current().get(name).map(|emoji| emoji.name.to_owned())
+	}
+
+	pub fn contains(&self, name: &str) -> bool {
+		self.current().lookup.contains_key(name)
+	}
+
+	/// Emojis for a `:shortcode` being typed, best matches first. Exact names and aliases come
+	/// before prefixes, then word prefixes and keywords; ties keep catalogue order.
+	pub fn search(&self, query: &str, limit: usize) -> Vec<CatalogueEmoji> {
+		let query = query.trim().trim_matches(':').to_lowercase();
+		if query.is_empty() {
+			return Vec::new();
+		}
+		let snapshot = self.current();
+		let mut matches: Vec<(u8, &CatalogueEmoji)> = snapshot.emojis()
+			.filter_map(|emoji| match_rank(emoji, &query).map(|rank| (rank, emoji)))
+			.collect();
+		matches.sort_by_key(|(rank, _)| *rank);
+		matches.into_iter().take(limit).map(|(_, emoji)| emoji.clone()).collect()
+	}
+}
@@ -9,8 +9,6 @@ use std::{
         Arc,
     },
     time::Instant,
-    collections::HashMap,
-    fs,
     io,
     env
 };
@@ -25,14 +23,22 @@ use actix_web_actors::ws;
 // use diesel_migrations::;
 
 pub mod socket_session;
+pub mod emoji_catalogue;
 pub mod lib;
 pub mod models;
 pub mod route;
+pub mod scheduler;
 pub mod schema;
+pub mod storage;
+pub mod unfurl;
 pub mod ws_server;
 
 embed_migrations!();
 
+/// Logger's default format, but with a request line that leaves out the query string,
+/// since media requests carry the caller's `access_token` there.
+const LOG_FORMAT: &str = r#"%a "%{request_line}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T"#;
+
 #[get("/ws")]
 async fn websocket_route(
     req: HttpRequest,
@@ -61,13 +67,14 @@ async fn main() -> io::Result<()> {
     let app_state = Arc::new(AtomicUsize::new(0));
 
     let ws_server = ws_server::WsServer::new(app_state.clone(), pool.clone()).start();
+    let media_storage = storage::from_env();
+    let link_preview_fetcher = Arc::new(unfurl::LinkPreviewFetcher::from_env());
+    scheduler::Scheduler::new(pool.clone(), ws_server.clone(), link_preview_fetcher.clone()).spawn();
 
-    let emoji_map_string = fs::read_to_string("emoji_map.json")
-        .expect("Unable to read `emoji_map.json`");
-    let emoji_map_json: serde_json::Value = serde_json::from_str(&emoji_map_string)
-        .expect("emoji_map does not have correct format");
-    let emoji_map: HashMap<String, String> = serde_json::from_value(emoji_map_json)
-        .expect("emoji_map_json does not have correct structure");
+    let emoji_catalogue = Arc::new(
+        emoji_catalogue::EmojiCatalogue::from_env().expect("Unable to load the emoji catalogue")
+    );
+    emoji_catalogue::EmojiCatalogue::spawn_reloader(emoji_catalogue.clone());
     let google_client_id = ClientId::new(
         env::var("GOOGLE_CLIENT_ID")
         .expect("`GOOGLE_CLIENT_ID is not set in .env file`")    
@@ -105,9 +112,13 @@ async fn main() -> io::Result<()> {
             .app_data(web::Data::new(pool.clone()))
             .app_data(web::Data::from(app_state.clone()))
             .app_data(web::Data::new(ws_server.clone()))
-            .app_data(web::Data::new(emoji_map.clone()))
+            .app_data(web::Data::from(emoji_catalogue.clone()))
             .app_data(web::Data::new(google_client.clone()))
-            .wrap(middleware::Logger::default())
+            .app_data(web::Data::from(media_storage.clone()))
+            .app_data(web::Data::from(link_preview_fetcher.clone()))
+            .wrap(middleware::Logger::new(LOG_FORMAT).custom_request_replace("request_line", |req| {
+                format!("{} {} {:?}", req.method(), req.path(), req.version())
+            }))
             .wrap(cors)
             .service(websocket_route)
             .service(route::auth::register)
@@ -118,17 +129,71 @@ async fn main() -> io::Result<()> {
             .service(route::user::list_user)
             .service(route::user::get_signed_signature)
             .service(route::user::change_avatar)
+            .service(route::media::upload_media)
+            .service(route::media::get_media)
+            .service(route::media::get_media_thumbnail)
+            .service(route::media::delete_media)
             .service(route::auth::refresh_token_route)
             .service(route::conversation::get_conversation_members)
             .service(route::conversation::get_conversation_users)
             .service(route::conversation::get_conversation_by_recipient)
             .service(route::conversation::get_conversations_for_user)
             .service(route::conversation::get_conversation_by_id)
+            .service(route::conversation::create_group)
+            .service(route::conversation::rename_conversation)
+            .service(route::conversation::add_conversation_members)
+            .service(route::conversation::kick_member)
+            .service(route::conversation::ban_member)
+            .service(route::conversation::unban_member)
+            .service(route::conversation::get_conversation_bans)
+            .service(route::conversation::leave_conversation)
+            .service(route::conversation::set_member_role)
+            .service(route::conversation::transfer_ownership)
+            .service(route::conversation::update_member_profile)
+            .service(route::conversation::mark_conversation_read)
+            .service(route::conversation::archive_conversation)
+            .service(route::conversation::unarchive_conversation)
+            .service(route::invite::create_invite)
+            .service(route::invite::get_conversation_invites)
+            .service(route::invite::revoke_invite)
+            .service(route::invite::set_join_approval)
+            .service(route::invite::get_invite)
+            .service(route::invite::join_by_invite)
+            .service(route::invite::get_join_requests)
+            .service(route::invite::approve_join_request)
+            .service(route::invite::deny_join_request)
             .service(route::message::create_message)
             .service(route::message::delete_message)
+            .service(route::message::edit_message)
+            .service(route::message::get_message_edits)
+            .service(route::message::forward_message)
+            .service(route::message::get_thread_messages)
+            .service(route::message::get_mentions)
+            .service(route::message::search_messages)
             .service(route::message::get_messages_by_conversation)
             .service(route::message::create_reaction)
             .service(route::message::delete_reaction)
+            .service(route::message::get_reaction_users)
+            .service(route::emoji::get_conversation_emojis)
+            .service(route::emoji::create_conversation_emoji)
+            .service(route::emoji::delete_conversation_emoji)
+            .service(route::emoji::get_emoji_catalogue)
+            .service(route::emoji::reload_emoji_catalogue)
+            .service(route::emoji::search_emojis)
+            .service(route::emoji::get_frequent_emojis)
+            .service(route::emoji::get_server_emojis)
+            .service(route::emoji::create_server_emoji)
+            .service(route::emoji::delete_server_emoji)
+            .service(route::pin::get_pinned_messages)
+            .service(route::pin::pin_message)
+            .service(route::pin::unpin_message)
+            .service(route::scheduled_message::schedule_message)
+            .service(route::scheduled_message::get_scheduled_messages)
+            .service(route::scheduled_message::edit_scheduled_message)
+            .service(route::scheduled_message::cancel_scheduled_message)
+            .service(route::draft::get_draft)
+            .service(route::draft::save_draft)
+            .service(route::draft::delete_draft)
             .service(route::message::search_giphy)
     })
     .bind(("0.0.0.0", port))
@@ -0,0 +1,234 @@
+use diesel::prelude::*;
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::{Uuid};
+use crate::schema::attachments::{self, dsl::*};
+use crate::lib::{json_time, ErrorField};
+
+pub const MAX_ATTACHMENTS_PER_MESSAGE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="lowercase")]
+pub enum AttachmentKind {
+	Image,
+	Gif,
+	Video,
+	Audio,
+	File
+}
+
+impl AttachmentKind {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			AttachmentKind::Image => "image",
+			AttachmentKind::Gif => "gif",
+			AttachmentKind::Video => "video",
+			AttachmentKind::Audio => "audio",
+			AttachmentKind::File => "file"
+		}
+	}
+
+	pub fn parse(value: &str) -> Option<AttachmentKind> {
+		match value {
+			"image" => Some(AttachmentKind::Image),
+			"gif" => Some(AttachmentKind::Gif),
+			"video" => Some(AttachmentKind::Video),
+			"audio" => Some(AttachmentKind::Audio),
+			"file" => Some(AttachmentKind::File),
+			_ => None
+		}
+	}
+
+	/// Whether `mime` is plausible for this kind; `file` accepts anything.
+	pub fn accepts_mime_type(&self, mime: &str) -> bool {
+		match self {
+			AttachmentKind::Image => mime.starts_with("image/"),
+			AttachmentKind::Gif => mime == "image/gif" || mime.starts_with("video/"),
+			AttachmentKind::Video => mime.starts_with("video/"),
+			AttachmentKind::Audio => mime.starts_with("audio/"),
+			AttachmentKind::File => true
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[serde(rename_all="camelCase")]
+pub struct Attachment {
+	pub id: Uuid,
+	pub message_id: Uuid,
+	pub kind: String,
+	pub url: String,
+	pub mime_type: Option<String>,
+	pub size: Option<i64>,
+	pub width: Option<i32>,
+	pub height: Option<i32>,
+	pub filename: Option<String>,
+	pub position: i32,
+	#[serde(with = "json_time")]
+	pub created_at: chrono::NaiveDateTime,
+	pub upload_id: Option<Uuid>,
+	pub blurhash: Option<String>,
+	pub thumbnails: serde_json::Value
+}
+
+/// A scaled-down copy of an uploaded image, smallest first on the attachment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentThumbnail {
+	pub url: String,
+	pub width: i32,
+	pub height: i32
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name="attachments"]
+pub struct NewAttachment {
+	pub message_id: Uuid,
+	pub kind: String,
+	pub url: String,
+	pub mime_type: Option<String>,
+	pub size: Option<i64>,
+	pub width: Option<i32>,
+	pub height: Option<i32>,
+	pub filename: Option<String>,
+	pub position: i32,
+	pub upload_id: Option<Uuid>,
+	pub blurhash: Option<String>,
+	pub thumbnails: serde_json::Value
+}
+
+/// An attachment as sent by the client when creating a message. Files uploaded through
+/// `/media/upload` are referenced by `upload_id`, which fills in the URL, MIME type and size
+/// along with the dimensions, blurhash and thumbnails worked out for images.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentBody {
+	pub kind: AttachmentKind,
+	#[serde(default)]
+	pub url: String,
+	pub mime_type: Option<String>,
+	pub size: Option<i64>,
+	pub width: Option<i32>,
+	pub height: Option<i32>,
+	pub filename: Option<String>,
+	#[serde(default)]
+	pub upload_id: Option<Uuid>,
+	#[serde(skip_deserializing)]
+	pub blurhash: Option<String>,
+	#[serde(skip_deserializing)]
+	pub thumbnails: Vec<AttachmentThumbnail>
+}
+
+impl AttachmentBody {
+	pub fn validate(bodies: &[AttachmentBody]) -> Vec<ErrorField> {
+		let mut errors: Vec<ErrorField> = Vec::new();
+		if bodies.len() > MAX_ATTACHMENTS_PER_MESSAGE {
+			errors.push(ErrorField {
+				path: String::from("attachments"),
+				messages: vec![format!("a message can have at most {} attachments", MAX_ATTACHMENTS_PER_MESSAGE)]
+			});
+		}
+		for (index, body) in bodies.iter().enumerate() {
+			let mut messages: Vec<String> = Vec::new();
+			match ::url::Url::parse(&body.url) {
+				Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => {}
+				_ => messages.push(String::from("url must be an http(s) URL"))
+			}
+			if let Some(mime) = &body.mime_type {
+				if !body.kind.accepts_mime_type(mime) {
+					messages.push(format!("mime type {} does not match kind {}", mime, body.kind.as_str()));
+				}
+			}
+			if body.size.is_some_and(|value| value < 0) {
+				messages.push(String::from("size cannot be negative"));
+			}
+			if body.width.is_some_and(|value| value <= 0) || body.height.is_some_and(|value| value <= 0) {
+				messages.push(String::from("dimensions must be positive"));
+			}
+			if !messages.is_empty() {
+				errors.push(ErrorField {path: format!("attachments[{}]", index), messages});
+			}
+		}
+		errors
+	}
+}
+
+impl Attachment {
+	pub fn kind(&self) -> Option<AttachmentKind> {
+		AttachmentKind::parse(&self.kind)
+	}
+
+	pub fn insert_for_message(mid: &Uuid, bodies: &[AttachmentBody], conn: &PgConnection) -> QueryResult<Vec<Attachment>> {
+		if bodies.is_empty() {
+			return Ok(Vec::new());
+		}
+		let new_attachments: Vec<NewAttachment> = bodies.iter().enumerate().map(|(index, body)| NewAttachment {
+			message_id: *mid,
+			kind: body.kind.as_str().to_string(),
+			url: body.url.to_owned(),
+			mime_type: body.mime_type.to_owned(),
+			size: body.size,
+			width: body.width,
+			height: body.height,
+			filename: body.filename.to_owned(),
+			position: index as i32,
+			upload_id: body.upload_id,
+			blurhash: body.blurhash.to_owned(),
+			thumbnails: serde_json::json!(body.thumbnails)
+		}).collect();
+		diesel::insert_into(attachments)
+			.values(&new_attachments)
+			.get_results::<Attachment>(conn)
+	}
+
+	/// Attaches copies of `sources` to another message, keeping their order.
+	pub fn copy_to_message(mid: &Uuid, sources: &[Attachment], conn: &PgConnection) -> QueryResult<Vec<Attachment>> {
+		if sources.is_empty() {
+			return Ok(Vec::new());
+		}
+		let new_attachments: Vec<NewAttachment> = sources.iter().map(|source| NewAttachment {
+			message_id: *mid,
+			kind: source.kind.to_owned(),
+			url: source.url.to_owned(),
+			mime_type: source.mime_type.to_owned(),
+			size: source.size,
+			width: source.width,
+			height: source.height,
+			filename: source.filename.to_owned(),
+			position: source.position,
+			upload_id: source.upload_id,
+			blurhash: source.blurhash.to_owned(),
+			thumbnails: source.thumbnails.clone()
+		}).collect();
+		diesel::insert_into(attachments)
+			.values(&new_attachments)
+			.get_results::<Attachment>(conn)
+	}
+
+	pub fn fetch_by_message_id(mid: &Uuid, conn: &PgConnection) -> QueryResult<Vec<Attachment>> {
+		attachments
+			.filter(message_id.eq(mid))
+			.order_by(position.asc())
+			.get_results::<Attachment>(conn)
+	}
+
+	/// Deleted messages keep their row but lose their content, attachments included.
+	pub fn delete_by_message_id(mid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::delete(attachments.filter(message_id.eq(mid))).execute(conn)
+	}
+
+	/// Attachments for several messages at once, grouped by message in display order.
+	pub fn fetch_by_message_ids(mids: &[Uuid], conn: &PgConnection) -> QueryResult<HashMap<Uuid, Vec<Attachment>>> {
+		let mut grouped: HashMap<Uuid, Vec<Attachment>> = HashMap::new();
+		if mids.is_empty() {
+			return Ok(grouped);
+		}
+		let rows = attachments
+			.filter(message_id.eq_any(mids))
+			.order_by((message_id, position.asc()))
+			.get_results::<Attachment>(conn)?;
+		for row in rows {
+			grouped.entry(row.message_id).or_default().push(row);
+		}
+		Ok(grouped)
+	}
+}
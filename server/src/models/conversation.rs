@@ -1,6 +1,6 @@
 use diesel::prelude::*;
 use diesel::sql_query;
-use diesel::sql_types::{Jsonb, Array, Text};
+use diesel::sql_types::{Jsonb, Array, Text, Nullable, Timestamptz, BigInt};
 use diesel::pg::Pg;
 // use diesel::backend::Backend;
 use diesel::deserialize::{FromSql};
@@ -15,6 +15,11 @@ use crate::schema::conversations::{self, dsl::*};
 use crate::schema::members::{dsl::*};
 use crate::models::member::{Member, NewMember};
 use crate::models::user::{User};
+use crate::models::attachment::Attachment;
+use crate::models::draft::Draft;
+use crate::models::permission::Role;
+use crate::lib::{json_time, json_option_time};
+use crate::models::pagination;
 // use std::env;
 
 sql_function!{
@@ -29,7 +34,9 @@ pub struct LastMessageDisplay {
 	pub user_name: Option<String>,
 	pub content: Option<String>,
 
-	pub created_at: Option<chrono::NaiveDateTime>
+	pub created_at: Option<chrono::NaiveDateTime>,
+	#[serde(default)]
+	pub attachments: Vec<Attachment>
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -84,6 +91,7 @@ pub struct Conversation {
 	pub created_at: chrono::NaiveDateTime,
 	pub is_group: bool,
 	pub withs: Option<serde_json::Value>,
+	pub join_approval: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Insertable, Queryable)]
@@ -96,6 +104,90 @@ pub struct NewConversation {
 	pub withs: Option<serde_json::Value>
 }
 
+/// A conversation as listed for one user, with their unread state.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct ConversationSummary {
+	#[serde(flatten)]
+	pub conversation: Conversation,
+	#[serde(with = "json_option_time")]
+	pub last_read_at: Option<chrono::NaiveDateTime>,
+	pub unread_count: i64,
+	pub mention_count: i64,
+	pub is_archived: bool,
+	#[serde(with = "json_time")]
+	pub last_activity_at: chrono::NaiveDateTime,
+	/// Whether the user left an unsent draft here.
+	pub has_draft: bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all="lowercase")]
+pub enum ConversationFilter {
+	#[default]
+	All,
+	Groups,
+	Dms,
+	Unread,
+	Archived
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationListQuery {
+	#[serde(default)]
+	pub filter: ConversationFilter
+}
+
+/// Last activity is the latest message time, falling back to when the conversation was created.
+const ACTIVITY_AT_SQL: &str = "coalesce((c.last_message_display->>'createdAt')::timestamp at time zone 'UTC', c.created_at)";
+
+#[derive(Debug, Clone, QueryableByName)]
+struct ConversationRow {
+	#[diesel(embed)]
+	conversation: Conversation,
+	#[sql_type = "diesel::sql_types::Bool"]
+	is_archived: bool,
+	#[sql_type = "diesel::sql_types::Timestamptz"]
+	activity_at: chrono::NaiveDateTime
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct CreateGroupBody {
+	pub name: String,
+	pub member_ids: Vec<Uuid>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct RenameConversationBody {
+	pub name: String
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct AddMembersBody {
+	pub user_ids: Vec<Uuid>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct JoinApprovalBody {
+	pub enabled: bool
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct SetRoleBody {
+	pub role: Role
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct TransferOwnershipBody {
+	pub user_id: Uuid
+}
+
 impl Conversation {
 	pub fn fetch_by_id(uid: &Uuid, conn: &PgConnection) -> QueryResult<Conversation> {
 		conversations.filter(id.eq(uid)).get_result::<Conversation>(conn)
@@ -107,6 +199,19 @@ impl Conversation {
 			.execute(conn)
 	}
 
+	/// Refreshes the preview text when the conversation's latest message was edited.
+	pub fn update_last_message_content(uid: &Uuid, message_id: &Uuid, new_content: &Option<String>, conn: &PgConnection) -> QueryResult<usize> {
+		let conversation = Self::fetch_by_id(uid, conn)?;
+		if conversation.last_message_id != Some(*message_id) {
+			return Ok(0);
+		}
+		let lmd = LastMessageDisplay {
+			content: new_content.to_owned(),
+			..conversation.last_message_display
+		};
+		Self::update_last_message(uid, message_id, &lmd, conn)
+	}
+
 	pub fn update_user_online(uid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
 		sql_query(r#"
 			with item as (
@@ -146,6 +251,42 @@ impl Conversation {
 		diesel::insert_into(conversations).values(conversation).get_result(conn)
 	}
 
+	pub fn create_group(group_name: &str, creator_id: &Uuid, member_ids: &[Uuid], conn: &PgConnection) -> QueryResult<Conversation> {
+		conn.transaction(|| {
+			let conversation = Self::insert_one(&NewConversation {
+				name: Some(group_name.to_owned()), is_group: true, admin_id: Some(*creator_id), withs: None
+			}, conn)?;
+			let mut new_members = vec![NewMember {
+				conversation_id: conversation.id, user_id: *creator_id, role: Some(Role::Owner.as_str().to_string())
+			}];
+			for mid in member_ids.iter() {
+				if !new_members.iter().any(|m| m.user_id == *mid) {
+					new_members.push(NewMember {conversation_id: conversation.id, user_id: *mid, role: None});
+				}
+			}
+			Member::insert_many(&new_members, conn)?;
+			Ok(conversation)
+		})
+	}
+
+	pub fn set_admin(uid: &Uuid, new_admin_id: Option<Uuid>, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::update(conversations.filter(id.eq(uid)))
+			.set(admin_id.eq(new_admin_id))
+			.execute(conn)
+	}
+
+	pub fn set_join_approval(uid: &Uuid, enabled: bool, conn: &PgConnection) -> QueryResult<Conversation> {
+		diesel::update(conversations.filter(id.eq(uid)))
+			.set(join_approval.eq(enabled))
+			.get_result::<Conversation>(conn)
+	}
+
+	pub fn rename(uid: &Uuid, new_name: &str, conn: &PgConnection) -> QueryResult<Conversation> {
+		diesel::update(conversations.filter(id.eq(uid)))
+			.set(name.eq(new_name))
+			.get_result::<Conversation>(conn)
+	}
+
 	pub fn get_by_recipient(rid: &Uuid, uid: &Uuid, conn: &PgConnection) -> Result<Conversation, diesel::result::Error> {
 		let conversation = sql_query(r#"
 			select c.* from members m1
@@ -180,7 +321,7 @@ impl Conversation {
 						}, &conn);
 						match conversation {
 							Ok(conversation) => {
-								let new_members = vec![NewMember {conversation_id: conversation.id, user_id: rid.to_owned()}, NewMember {conversation_id: conversation.id, user_id: uid.to_owned()}];
+								let new_members = vec![NewMember {conversation_id: conversation.id, user_id: rid.to_owned(), role: None}, NewMember {conversation_id: conversation.id, user_id: uid.to_owned(), role: None}];
 								let result = Member::insert_many(&new_members, conn);
 								if let Err(e) = result {
 									return Err(e);
@@ -201,8 +342,83 @@ impl Conversation {
 		}
 	}
 
+	/// Lists the user's conversations, most recently active first, one page at a time.
+	pub fn fetch_page_by_user_id(
+		uid: &Uuid,
+		options: &pagination::Options,
+		filter: ConversationFilter,
+		conn: &PgConnection
+	) -> QueryResult<pagination::Result<Vec<ConversationSummary>>> {
+		let filter_sql = match filter {
+			ConversationFilter::All => "and m.is_archived = false",
+			ConversationFilter::Groups => "and m.is_archived = false and c.is_group = true",
+			ConversationFilter::Dms => "and m.is_archived = false and c.is_group = false",
+			ConversationFilter::Unread => r#"
+				and m.is_archived = false
+				and exists (
+					select 1 from messages msg
+					where msg.conversation_id = c.id
+					and msg.author_id != m.user_id and msg.is_deleted = false
+					and msg.thread_root_id is null
+					and msg.created_at > coalesce(m.last_read_at, m.joined_at)
+				)
+			"#,
+			ConversationFilter::Archived => "and m.is_archived = true"
+		};
+		let limit = options.limit_or_default();
+		let rows = sql_query(format!(r#"
+			select c.*, m.is_archived, {activity} as activity_at
+			from members m
+			inner join conversations c on c.id = m.conversation_id
+			where m.user_id = $1 and m.is_kicked = false and m.is_banned = false
+			and (
+				$2::timestamptz is null
+				or ({activity}, c.id) < ($2::timestamptz, coalesce($3::uuid, '00000000-0000-0000-0000-000000000000'::uuid))
+			)
+			{filter}
+			order by activity_at desc, c.id desc
+			limit $4;
+		"#, activity = ACTIVITY_AT_SQL, filter = filter_sql))
+		.bind::<diesel::sql_types::Uuid, _>(uid)
+		.bind::<Nullable<Timestamptz>, _>(options.before)
+		.bind::<Nullable<diesel::sql_types::Uuid>, _>(options.before_id)
+		.bind::<BigInt, _>(limit as i64 + 1)
+		.get_results::<ConversationRow>(conn)?;
+
+		let has_more = rows.len() > limit;
+		let rows: Vec<ConversationRow> = rows.into_iter().take(limit).collect();
+		let (next_cursor, next_cursor_id) = match rows.last() {
+			Some(last) if has_more => (Some(last.activity_at), Some(last.conversation.id)),
+			_ => (None, None)
+		};
+		let cids: Vec<Uuid> = rows.iter().map(|r| r.conversation.id).collect();
+		let unread_counts = Member::fetch_unread_counts(uid, &cids, conn)?;
+		let draft_cids = Draft::fetch_conversation_ids(uid, &cids, conn)?;
+		let items = rows.into_iter().map(|row| {
+			let unread = unread_counts.iter().find(|u| u.conversation_id == row.conversation.id);
+			ConversationSummary {
+				last_read_at: unread.and_then(|u| u.last_read_at),
+				unread_count: unread.map_or(0, |u| u.unread_count),
+				mention_count: unread.map_or(0, |u| u.mention_count),
+				is_archived: row.is_archived,
+				last_activity_at: row.activity_at,
+				has_draft: draft_cids.contains(&row.conversation.id),
+				conversation: row.conversation
+			}
+		}).collect();
+		Ok(pagination::Result {
+			items,
+			next_cursor,
+			prev_cursor: None,
+			next_cursor_id,
+			prev_cursor_id: None
+		})
+	}
+
 	pub fn fetch_by_user_id(uid: &Uuid, conn: &PgConnection) -> QueryResult<Vec<Conversation>> {
-		let conversation_ids : Vec<uuid::Uuid> = members.select(conversation_id).filter(user_id.eq(uid)).get_results(conn).unwrap(); 
+		let conversation_ids : Vec<uuid::Uuid> = members.select(conversation_id)
+			.filter(user_id.eq(uid).and(is_kicked.eq(false)).and(is_banned.eq(false)))
+			.get_results(conn)?;
 		conversations.filter(id.eq(any(conversation_ids)))
 			.get_results(conn)
 	}
@@ -0,0 +1,189 @@
+use diesel::prelude::*;
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use uuid::{Uuid};
+use crate::schema::custom_emojis::dsl::*;
+use crate::emoji_catalogue::EmojiCatalogue;
+use crate::lib::{json_time, ErrorField};
+use crate::models::markdown;
+use crate::models::upload::{Upload, UploadPurpose};
+
+const MIN_NAME_CHARS: usize = 2;
+const MAX_NAME_CHARS: usize = 32;
+const DEFAULT_MAX_BYTES: i64 = 256 * 1024;
+const DEFAULT_LIMIT: i64 = 100;
+
+/// An uploaded image usable as `:name:`, either in one conversation or, without
+/// a conversation, everywhere on the server.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[serde(rename_all="camelCase")]
+pub struct CustomEmoji {
+	pub id: Uuid,
+	pub name: String,
+	pub conversation_id: Option<Uuid>,
+	pub upload_id: Uuid,
+	pub created_by: Uuid,
+	#[serde(with = "json_time")]
+	pub created_at: chrono::NaiveDateTime
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct CustomEmojiView {
+	#[serde(flatten)]
+	pub emoji: CustomEmoji,
+	pub url: String
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCustomEmojiBody {
+	pub name: String,
+	/// An image uploaded with `?purpose=emoji`.
+	pub upload_id: Uuid
+}
+
+/// The largest image accepted for an emoji, from `CUSTOM_EMOJI_MAX_BYTES`.
+pub fn max_bytes() -> i64 {
+	env::var("CUSTOM_EMOJI_MAX_BYTES")
+		.ok()
+		.and_then(|value| value.parse::<i64>().ok())
+		.unwrap_or(DEFAULT_MAX_BYTES)
+}
+
+/// How many emojis a conversation, or the server, may have; from `CUSTOM_EMOJI_LIMIT`.
+pub fn limit() -> i64 {
+	env::var("CUSTOM_EMOJI_LIMIT")
+		.ok()
+		.and_then(|value| value.parse::<i64>().ok())
+		.unwrap_or(DEFAULT_LIMIT)
+}
+
+pub fn is_valid_name(candidate: &str) -> bool {
+	(MIN_NAME_CHARS..=MAX_NAME_CHARS).contains(&candidate.chars().count())
+		&& candidate.chars().all(markdown::is_shortcode_char)
+}
+
+impl CustomEmoji {
+	pub fn url(&self) -> String {
+		Upload::media_url_for(&self.upload_id)
+	}
+
+	pub fn view(self) -> CustomEmojiView {
+		let emoji_url = self.url();
+		CustomEmojiView { emoji: self, url: emoji_url }
+	}
+
+	/// Checks a new emoji for the conversation `cid`, or for the whole server when `None`.
+	/// Names must not clash with built-in emojis, aliases included, or with any custom emoji a member could see
+	/// next to it, so a name always means one emoji inside a conversation.
+	pub fn validate(
+		body: &CreateCustomEmojiBody,
+		cid: Option<&Uuid>,
+		uid: &Uuid,
+		catalogue: &EmojiCatalogue,
+		conn: &PgConnection
+	) -> QueryResult<Vec<ErrorField>> {
+		let mut errors: Vec<ErrorField> = Vec::new();
+		if !is_valid_name(&body.name) {
+			errors.push(ErrorField {path: String::from("name"), messages: vec![format!(
+				"name must be {} to {} lowercase letters, digits or underscores", MIN_NAME_CHARS, MAX_NAME_CHARS
+			)]});
+		} else {
+			let clashing = match cid {
+				Some(cid) => custom_emojis
+					.filter(name.eq(&body.name))
+					.filter(conversation_id.eq(cid).or(conversation_id.is_null()))
+					.select(id)
+					.first::<Uuid>(conn)
+					.optional()?,
+				None => custom_emojis
+					.filter(name.eq(&body.name))
+					.select(id)
+					.first::<Uuid>(conn)
+					.optional()?
+			};
+			if catalogue.contains(&body.name) || clashing.is_some() {
+				errors.push(ErrorField {path: String::from("name"), messages: vec![String::from("name is already taken")]});
+			}
+		}
+		let existing = match cid {
+			Some(cid) => custom_emojis.filter(conversation_id.eq(cid)).count().get_result::<i64>(conn)?,
+			None => custom_emojis.filter(conversation_id.is_null()).count().get_result::<i64>(conn)?
+		};
+		if existing >= limit() {
+			errors.push(ErrorField {path: String::from("name"), messages: vec![format!("there can be at most {} custom emojis", limit())]});
+		}
+		match Upload::fetch_by_id(&body.upload_id, conn).optional()? {
+			Some(upload) if upload.user_id == *uid => {
+				if upload.purpose != UploadPurpose::Emoji.as_str() || !upload.mime_type.starts_with("image/") {
+					errors.push(ErrorField {path: String::from("upload_id"), messages: vec![String::from("upload must be an image uploaded for an emoji")]});
+				} else if upload.size > max_bytes() {
+					errors.push(ErrorField {path: String::from("upload_id"), messages: vec![format!("emoji images are limited to {} bytes", max_bytes())]});
+				}
+			}
+			_ => errors.push(ErrorField {path: String::from("upload_id"), messages: vec![String::from("upload does not exist")]})
+		}
+		Ok(errors)
+	}
+
+	/// Adds the emoji and makes its image public for good; see `Upload::used_as_emoji`.
+	pub fn insert_one(body: &CreateCustomEmojiBody, cid: Option<&Uuid>, uid: &Uuid, conn: &PgConnection) -> QueryResult<CustomEmoji> {
+		conn.transaction(|| {
+			Upload::mark_used_as_emoji(&body.upload_id, conn)?;
+			diesel::insert_into(custom_emojis)
+				.values((
+					name.eq(&body.name),
+					conversation_id.eq(cid),
+					upload_id.eq(body.upload_id),
+					created_by.eq(uid)
+				))
+				.get_result::<CustomEmoji>(conn)
+		})
+	}
+
+	/// Everything usable in the conversation: its own emojis and the server-wide ones.
+	pub fn fetch_usable(cid: &Uuid, conn: &PgConnection) -> QueryResult<Vec<CustomEmoji>> {
+		custom_emojis
+			.filter(conversation_id.eq(cid).or(conversation_id.is_null()))
+			.order_by(name.asc())
+			.get_results::<CustomEmoji>(conn)
+	}
+
+	pub fn fetch_server_wide(conn: &PgConnection) -> QueryResult<Vec<CustomEmoji>> {
+		custom_emojis
+			.filter(conversation_id.is_null())
+			.order_by(name.asc())
+			.get_results::<CustomEmoji>(conn)
+	}
+
+	pub fn find_usable(cid: &Uuid, emoji_name: &str, conn: &PgConnection) -> QueryResult<Option<CustomEmoji>> {
+		custom_emojis
+			.filter(name.eq(emoji_name))
+			.filter(conversation_id.eq(cid).or(conversation_id.is_null()))
+			.first::<CustomEmoji>(conn)
+			.optional()
+	}
+
+	/// The emojis usable in the conversation out of `names`, by name.
+	pub fn resolve(cid: &Uuid, names: &[String], conn: &PgConnection) -> QueryResult<HashMap<String, CustomEmoji>> {
+		if names.is_empty() {
+			return Ok(HashMap::new());
+		}
+		let found = custom_emojis
+			.filter(name.eq_any(names))
+			.filter(conversation_id.eq(cid).or(conversation_id.is_null()))
+			.get_results::<CustomEmoji>(conn)?;
+		Ok(found.into_iter().map(|emoji| (emoji.name.to_owned(), emoji)).collect())
+	}
+
+	/// Deletes an emoji of the conversation `cid`, or a server-wide one when `None`.
+	/// Messages keep showing it, since its upload stays public; it just can't be used anymore.
+	pub fn delete_one(emoji_id: &Uuid, cid: Option<&Uuid>, conn: &PgConnection) -> QueryResult<usize> {
+		match cid {
+			Some(cid) => diesel::delete(custom_emojis.filter(id.eq(emoji_id).and(conversation_id.eq(cid)))).execute(conn),
+			None => diesel::delete(custom_emojis.filter(id.eq(emoji_id).and(conversation_id.is_null()))).execute(conn)
+		}
+	}
+}
@@ -0,0 +1,65 @@
+use diesel::prelude::*;
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use uuid::{Uuid};
+use crate::schema::drafts;
+use crate::lib::json_time;
+
+pub const MAX_CONTENT_CHARS: usize = 10000;
+
+/// What a user has typed but not sent in a conversation, kept so they can continue on another device.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[serde(rename_all="camelCase")]
+pub struct Draft {
+	pub user_id: Uuid,
+	pub conversation_id: Uuid,
+	pub content: String,
+	#[serde(with = "json_time")]
+	pub updated_at: chrono::NaiveDateTime
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveDraftBody {
+	pub content: String
+}
+
+impl Draft {
+	pub fn find(uid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<Option<Draft>> {
+		drafts::table
+			.filter(drafts::user_id.eq(uid).and(drafts::conversation_id.eq(cid)))
+			.get_result::<Draft>(conn)
+			.optional()
+	}
+
+	/// Creates or replaces the user's draft for the conversation.
+	pub fn save(uid: &Uuid, cid: &Uuid, text: &str, conn: &PgConnection) -> QueryResult<Draft> {
+		let now = chrono::Utc::now().naive_utc();
+		diesel::insert_into(drafts::table)
+			.values((
+				drafts::user_id.eq(uid),
+				drafts::conversation_id.eq(cid),
+				drafts::content.eq(text),
+				drafts::updated_at.eq(now)
+			))
+			.on_conflict((drafts::user_id, drafts::conversation_id))
+			.do_update()
+			.set((drafts::content.eq(text), drafts::updated_at.eq(now)))
+			.get_result::<Draft>(conn)
+	}
+
+	pub fn delete_one(uid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::delete(drafts::table.filter(drafts::user_id.eq(uid).and(drafts::conversation_id.eq(cid))))
+			.execute(conn)
+	}
+
+	/// Which of `cids` the user has a draft in.
+	pub fn fetch_conversation_ids(uid: &Uuid, cids: &[Uuid], conn: &PgConnection) -> QueryResult<Vec<Uuid>> {
+		if cids.is_empty() {
+			return Ok(Vec::new());
+		}
+		drafts::table
+			.filter(drafts::user_id.eq(uid).and(drafts::conversation_id.eq_any(cids)))
+			.select(drafts::conversation_id)
+			.get_results::<Uuid>(conn)
+	}
+}
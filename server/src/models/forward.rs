@@ -0,0 +1,69 @@
+use diesel::prelude::*;
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::{Uuid};
+use crate::schema::{conversations, members};
+use crate::models::member::{Member, MemberIdentity};
+use crate::models::message::Message;
+
+/// Where a forwarded message came from, as far as the viewer is allowed to know.
+/// It is `Unknown` when the viewer is not a member of the original conversation
+/// (anymore), or when that conversation is gone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Provenance {
+	#[serde(rename_all = "camelCase")]
+	Known {
+		/// Missing once the original message has been removed for good.
+		message_id: Option<Uuid>,
+		conversation_id: Uuid,
+		conversation_name: Option<String>,
+		author: Option<MemberIdentity>
+	},
+	Unknown
+}
+
+impl Provenance {
+	/// Resolves the provenance of every forwarded message in `items` for `viewer`, by message id.
+	pub fn resolve_many(viewer: &Uuid, items: &[Message], conn: &PgConnection) -> QueryResult<HashMap<Uuid, Provenance>> {
+		let mut resolved: HashMap<Uuid, Provenance> = HashMap::new();
+		let forwarded: Vec<&Message> = items.iter().filter(|message| message.is_forwarded).collect();
+		if forwarded.is_empty() {
+			return Ok(resolved);
+		}
+		let mut source_cids: Vec<Uuid> = forwarded.iter().filter_map(|message| message.forwarded_from_conversation_id).collect();
+		source_cids.sort();
+		source_cids.dedup();
+		let visible: Vec<(Uuid, Option<String>)> = conversations::table
+			.inner_join(members::table.on(members::conversation_id.eq(conversations::id)))
+			.filter(conversations::id.eq_any(&source_cids))
+			.filter(members::user_id.eq(viewer).and(members::is_kicked.eq(false)).and(members::is_banned.eq(false)))
+			.select((conversations::id, conversations::name))
+			.get_results(conn)?;
+		let mut identities: HashMap<Uuid, HashMap<Uuid, MemberIdentity>> = HashMap::new();
+		for (source_cid, _) in visible.iter() {
+			let author_ids: Vec<Uuid> = forwarded.iter()
+				.filter(|message| message.forwarded_from_conversation_id == Some(*source_cid))
+				.filter_map(|message| message.forwarded_from_author_id)
+				.collect();
+			identities.insert(*source_cid, Member::fetch_identities(source_cid, &author_ids, conn)?);
+		}
+		for message in forwarded {
+			let source = message.forwarded_from_conversation_id
+				.and_then(|source_cid| visible.iter().find(|(visible_cid, _)| *visible_cid == source_cid));
+			let provenance = match source {
+				Some((source_cid, source_name)) => Provenance::Known {
+					message_id: message.forwarded_from_message_id,
+					conversation_id: *source_cid,
+					conversation_name: source_name.to_owned(),
+					author: message.forwarded_from_author_id
+						.and_then(|source_author| identities.get(source_cid).and_then(|found| found.get(&source_author)).cloned())
+				},
+				None => Provenance::Unknown
+			};
+			resolved.insert(message.id, provenance);
+		}
+		Ok(resolved)
+	}
+}
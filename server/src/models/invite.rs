@@ -0,0 +1,238 @@
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::{PgConnection, QueryResult};
+use rand::{Rng, distributions::Alphanumeric};
+use serde::{Deserialize, Serialize};
+use uuid::{Uuid};
+use crate::schema::invites::{self, dsl::*};
+use crate::schema::join_requests;
+use crate::lib::{json_time, json_option_time};
+use crate::models::member::{JoinResult, Member};
+
+const INVITE_CODE_LENGTH: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, QueryableByName)]
+#[table_name="invites"]
+#[serde(rename_all="camelCase")]
+pub struct Invite {
+	pub id: Uuid,
+	pub code: String,
+	pub conversation_id: Uuid,
+	pub created_by: Uuid,
+	#[serde(with = "json_option_time")]
+	pub expires_at: Option<chrono::NaiveDateTime>,
+	pub max_uses: Option<i32>,
+	pub uses: i32,
+	pub is_revoked: bool,
+	#[serde(with = "json_time")]
+	pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name="invites"]
+pub struct NewInvite {
+	pub code: String,
+	pub conversation_id: Uuid,
+	pub created_by: Uuid,
+	pub expires_at: Option<chrono::NaiveDateTime>,
+	pub max_uses: Option<i32>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct CreateInviteBody {
+	/// Seconds until the invite expires; `None` never expires.
+	pub expires_in: Option<i64>,
+	pub max_uses: Option<i32>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[serde(rename_all="camelCase")]
+pub struct JoinRequest {
+	pub conversation_id: Uuid,
+	pub user_id: Uuid,
+	pub invite_id: Option<Uuid>,
+	pub status: String,
+	#[serde(with = "json_time")]
+	pub created_at: chrono::NaiveDateTime,
+	pub resolved_by: Option<Uuid>,
+	#[serde(with = "json_option_time")]
+	pub resolved_at: Option<chrono::NaiveDateTime>,
+}
+
+/// What happened when a user redeemed an invite.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub enum JoinOutcome {
+	Joined,
+	AlreadyMember,
+	Requested
+}
+
+/// Why a user could not join through an invite or join request.
+#[derive(Debug)]
+pub enum JoinError {
+	/// The invite is revoked, expired or used up.
+	InviteUnusable,
+	Banned,
+	Database(diesel::result::Error)
+}
+
+impl From<diesel::result::Error> for JoinError {
+	fn from(e: diesel::result::Error) -> Self {
+		JoinError::Database(e)
+	}
+}
+
+impl Invite {
+	pub fn generate_code() -> String {
+		rand::thread_rng()
+			.sample_iter(&Alphanumeric)
+			.take(INVITE_CODE_LENGTH)
+			.map(char::from)
+			.collect()
+	}
+
+	pub fn insert_one(new_invite: &NewInvite, conn: &PgConnection) -> QueryResult<Invite> {
+		diesel::insert_into(invites).values(new_invite).get_result::<Invite>(conn)
+	}
+
+	pub fn fetch_by_code(invite_code: &str, conn: &PgConnection) -> QueryResult<Invite> {
+		invites.filter(code.eq(invite_code)).get_result::<Invite>(conn)
+	}
+
+	pub fn fetch_by_conversation(cid: &Uuid, conn: &PgConnection) -> QueryResult<Vec<Invite>> {
+		invites
+			.filter(conversation_id.eq(cid).and(is_revoked.eq(false)))
+			.order_by(created_at.desc())
+			.get_results::<Invite>(conn)
+	}
+
+	pub fn revoke(iid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::update(invites.filter(id.eq(iid).and(conversation_id.eq(cid))))
+			.set(is_revoked.eq(true))
+			.execute(conn)
+	}
+
+	pub fn is_usable(&self) -> bool {
+		let not_expired = match self.expires_at {
+			Some(expiry) => expiry > chrono::Utc::now().naive_utc(),
+			None => true
+		};
+		let has_uses_left = match self.max_uses {
+			Some(limit) => self.uses < limit,
+			None => true
+		};
+		!self.is_revoked && not_expired && has_uses_left
+	}
+
+	/// Consumes one use of the invite, returning `false` when it is revoked, expired or used up.
+	fn consume(iid: &Uuid, conn: &PgConnection) -> QueryResult<bool> {
+		let affected_rows = sql_query(r#"
+			update invites
+			set uses = uses + 1
+			where invites.id = $1
+			and invites.is_revoked = false
+			and (invites.expires_at is null or invites.expires_at > current_timestamp)
+			and (invites.max_uses is null or invites.uses < invites.max_uses);
+		"#)
+		.bind::<diesel::sql_types::Uuid, _>(iid)
+		.execute(conn)?;
+		Ok(affected_rows > 0)
+	}
+
+	/// Redeems the invite for `uid`. When the conversation needs approval, the use is only
+	/// counted once a moderator approves the request.
+	pub fn redeem(&self, uid: &Uuid, needs_approval: bool, conn: &PgConnection) -> Result<JoinOutcome, JoinError> {
+		conn.transaction(|| {
+			if let Some(member) = Member::find_one(uid, &self.conversation_id, conn).optional()? {
+				if member.is_banned {
+					return Err(JoinError::Banned);
+				}
+				if !member.is_kicked {
+					return Ok(JoinOutcome::AlreadyMember);
+				}
+			}
+			if needs_approval {
+				if JoinRequest::find_pending(&self.conversation_id, uid, conn)?.is_some() {
+					return Ok(JoinOutcome::Requested);
+				}
+				if !invites.filter(id.eq(self.id)).get_result::<Invite>(conn)?.is_usable() {
+					return Err(JoinError::InviteUnusable);
+				}
+				JoinRequest::upsert_pending(&self.conversation_id, uid, Some(self.id), conn)?;
+				return Ok(JoinOutcome::Requested);
+			}
+			match Member::join(uid, &self.conversation_id, conn)? {
+				JoinResult::Joined if Invite::consume(&self.id, conn)? => Ok(JoinOutcome::Joined),
+				JoinResult::Joined => Err(JoinError::InviteUnusable),
+				JoinResult::AlreadyMember => Ok(JoinOutcome::AlreadyMember),
+				JoinResult::Banned => Err(JoinError::Banned)
+			}
+		})
+	}
+}
+
+impl JoinRequest {
+	pub fn upsert_pending(cid: &Uuid, uid: &Uuid, iid: Option<Uuid>, conn: &PgConnection) -> QueryResult<JoinRequest> {
+		use crate::schema::join_requests::dsl;
+		diesel::insert_into(dsl::join_requests)
+			.values((dsl::conversation_id.eq(cid), dsl::user_id.eq(uid), dsl::invite_id.eq(iid)))
+			.on_conflict((dsl::conversation_id, dsl::user_id))
+			.do_update()
+			.set((
+				dsl::invite_id.eq(iid),
+				dsl::status.eq("pending"),
+				dsl::created_at.eq(diesel::dsl::now),
+				dsl::resolved_by.eq(None::<Uuid>),
+				dsl::resolved_at.eq(None::<chrono::NaiveDateTime>)
+			))
+			.get_result::<JoinRequest>(conn)
+	}
+
+	pub fn find_pending(cid: &Uuid, uid: &Uuid, conn: &PgConnection) -> QueryResult<Option<JoinRequest>> {
+		use crate::schema::join_requests::dsl;
+		join_requests::table
+			.filter(dsl::conversation_id.eq(cid).and(dsl::user_id.eq(uid)).and(dsl::status.eq("pending")))
+			.get_result::<JoinRequest>(conn)
+			.optional()
+	}
+
+	pub fn fetch_pending_by_conversation(cid: &Uuid, conn: &PgConnection) -> QueryResult<Vec<JoinRequest>> {
+		use crate::schema::join_requests::dsl;
+		join_requests::table
+			.filter(dsl::conversation_id.eq(cid).and(dsl::status.eq("pending")))
+			.order_by(dsl::created_at.asc())
+			.get_results::<JoinRequest>(conn)
+	}
+
+	/// Marks a pending request approved or denied. Approving adds the member and counts a use of
+	/// the invite they came through, if it still has one left; the moderator's approval admits
+	/// them either way. Returns whether the user was added.
+	pub fn resolve(cid: &Uuid, uid: &Uuid, approver_id: &Uuid, approve: bool, conn: &PgConnection) -> Result<(JoinRequest, bool), JoinError> {
+		use crate::schema::join_requests::dsl;
+		conn.transaction(|| {
+			let request = diesel::update(join_requests::table
+				.filter(dsl::conversation_id.eq(cid).and(dsl::user_id.eq(uid)).and(dsl::status.eq("pending"))))
+				.set((
+					dsl::status.eq(if approve { "approved" } else { "denied" }),
+					dsl::resolved_by.eq(Some(*approver_id)),
+					dsl::resolved_at.eq(Some(chrono::Utc::now().naive_utc()))
+				))
+				.get_result::<JoinRequest>(conn)?;
+			if !approve {
+				return Ok((request, false));
+			}
+			match Member::join(uid, cid, conn)? {
+				JoinResult::Joined => {
+					if let Some(iid) = request.invite_id {
+						Invite::consume(&iid, conn)?;
+					}
+					Ok((request, true))
+				}
+				JoinResult::AlreadyMember => Ok((request, false)),
+				JoinResult::Banned => Err(JoinError::Banned)
+			}
+		})
+	}
+}
@@ -0,0 +1,144 @@
+use diesel::pg::upsert::excluded;
+use diesel::prelude::*;
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use uuid::{Uuid};
+use crate::schema::{link_previews, message_link_previews};
+
+pub const STATUS_OK: &str = "ok";
+pub const STATUS_FAILED: &str = "failed";
+const DEFAULT_CACHE_SECONDS: i64 = 24 * 60 * 60;
+/// Failed fetches are retried sooner, in case the site was only briefly down.
+const FAILED_CACHE_SECONDS: i64 = 60 * 60;
+
+/// Metadata unfurled for a URL, shared by every message linking to it.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[serde(rename_all="camelCase")]
+pub struct LinkPreview {
+	#[serde(skip_serializing)]
+	pub id: Uuid,
+	pub url: String,
+	#[serde(skip_serializing)]
+	pub status: String,
+	pub title: Option<String>,
+	pub description: Option<String>,
+	pub image_url: Option<String>,
+	pub site_name: Option<String>,
+	#[serde(skip_serializing)]
+	pub fetched_at: chrono::NaiveDateTime
+}
+
+/// What the fetcher found on a page.
+#[derive(Debug, Clone, Default)]
+pub struct LinkMetadata {
+	pub title: Option<String>,
+	pub description: Option<String>,
+	pub image_url: Option<String>,
+	pub site_name: Option<String>
+}
+
+/// How long a fetched preview is reused, from `LINK_PREVIEW_CACHE_SECONDS`.
+fn cache_duration() -> chrono::Duration {
+	let seconds = env::var("LINK_PREVIEW_CACHE_SECONDS")
+		.ok()
+		.and_then(|value| value.parse::<i64>().ok())
+		.unwrap_or(DEFAULT_CACHE_SECONDS);
+	chrono::Duration::seconds(seconds)
+}
+
+impl LinkPreview {
+	pub fn is_ok(&self) -> bool {
+		self.status == STATUS_OK
+	}
+
+	/// The cached preview for `target`, unless it's old enough to be fetched again.
+	pub fn fetch_fresh(target: &str, conn: &PgConnection) -> QueryResult<Option<LinkPreview>> {
+		let cached = link_previews::table
+			.filter(link_previews::url.eq(target))
+			.first::<LinkPreview>(conn)
+			.optional()?;
+		let now = chrono::Utc::now().naive_utc();
+		Ok(cached.filter(|preview| {
+			let max_age = if preview.is_ok() { cache_duration() } else { chrono::Duration::seconds(FAILED_CACHE_SECONDS) };
+			preview.fetched_at + max_age > now
+		}))
+	}
+
+	/// Stores the outcome of fetching `target`; `None` records a failed fetch so it isn't retried right away.
+	pub fn upsert(target: &str, metadata: Option<&LinkMetadata>, conn: &PgConnection) -> QueryResult<LinkPreview> {
+		let empty = LinkMetadata::default();
+		let found = metadata.unwrap_or(&empty);
+		let status = if metadata.is_some() { STATUS_OK } else { STATUS_FAILED };
+		diesel::insert_into(link_previews::table)
+			.values((
+				link_previews::url.eq(target),
+				link_previews::status.eq(status),
+				link_previews::title.eq(&found.title),
+				link_previews::description.eq(&found.description),
+				link_previews::image_url.eq(&found.image_url),
+				link_previews::site_name.eq(&found.site_name),
+				link_previews::fetched_at.eq(chrono::Utc::now().naive_utc())
+			))
+			.on_conflict(link_previews::url)
+			.do_update()
+			.set((
+				link_previews::status.eq(excluded(link_previews::status)),
+				link_previews::title.eq(excluded(link_previews::title)),
+				link_previews::description.eq(excluded(link_previews::description)),
+				link_previews::image_url.eq(excluded(link_previews::image_url)),
+				link_previews::site_name.eq(excluded(link_previews::site_name)),
+				link_previews::fetched_at.eq(excluded(link_previews::fetched_at))
+			))
+			.get_result::<LinkPreview>(conn)
+	}
+
+	/// Points the message at `preview_ids`, in order, dropping whatever it had before.
+	/// Returns how many previews the message had.
+	pub fn replace_for_message(mid: &Uuid, preview_ids: &[Uuid], conn: &PgConnection) -> QueryResult<usize> {
+		conn.transaction(|| {
+			let removed = LinkPreview::delete_by_message_id(mid, conn)?;
+			let rows: Vec<_> = preview_ids.iter().enumerate().map(|(index, preview_id)| (
+				message_link_previews::message_id.eq(*mid),
+				message_link_previews::link_preview_id.eq(*preview_id),
+				message_link_previews::position.eq(index as i32)
+			)).collect();
+			if !rows.is_empty() {
+				diesel::insert_into(message_link_previews::table)
+					.values(&rows)
+					.on_conflict_do_nothing()
+					.execute(conn)?;
+			}
+			Ok(removed)
+		})
+	}
+
+	pub fn delete_by_message_id(mid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::delete(message_link_previews::table.filter(message_link_previews::message_id.eq(mid))).execute(conn)
+	}
+
+	pub fn fetch_by_message_id(mid: &Uuid, conn: &PgConnection) -> QueryResult<Vec<LinkPreview>> {
+		let mut grouped = LinkPreview::fetch_by_message_ids(&[*mid], conn)?;
+		Ok(grouped.remove(mid).unwrap_or_default())
+	}
+
+	/// Successful previews for several messages at once, grouped by message in display order.
+	pub fn fetch_by_message_ids(mids: &[Uuid], conn: &PgConnection) -> QueryResult<HashMap<Uuid, Vec<LinkPreview>>> {
+		let mut grouped: HashMap<Uuid, Vec<LinkPreview>> = HashMap::new();
+		if mids.is_empty() {
+			return Ok(grouped);
+		}
+		let rows = message_link_previews::table
+			.inner_join(link_previews::table)
+			.filter(message_link_previews::message_id.eq_any(mids))
+			.filter(link_previews::status.eq(STATUS_OK))
+			.order_by((message_link_previews::message_id, message_link_previews::position.asc()))
+			.select((message_link_previews::message_id, link_previews::all_columns))
+			.get_results::<(Uuid, LinkPreview)>(conn)?;
+		for (mid, preview) in rows {
+			grouped.entry(mid).or_default().push(preview);
+		}
+		Ok(grouped)
+	}
+}
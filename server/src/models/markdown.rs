@@ -0,0 +1,379 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Quotes and inline formatting deeper than this are kept as plain text.
+const MAX_DEPTH: usize = 5;
+const MAX_LANGUAGE_CHARS: usize = 32;
+const MAX_SHORTCODE_CHARS: usize = 32;
+
+/// A block of message content. Stored as JSON next to the raw text so every client
+/// renders the same thing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag="type", rename_all="camelCase")]
+pub enum Block {
+	Paragraph { children: Vec<Inline> },
+	CodeBlock { language: Option<String>, code: String },
+	Quote { children: Vec<Block> }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag="type", rename_all="camelCase")]
+pub enum Inline {
+	Text { text: String },
+	Bold { children: Vec<Inline> },
+	Italic { children: Vec<Inline> },
+	Spoiler { children: Vec<Inline> },
+	Code { code: String },
+	/// `url` is always http(s) or mailto; anything else stays text.
+	Link { url: String, children: Vec<Inline> },
+	/// A custom emoji as it was when the message was written.
+	CustomEmoji { id: Uuid, name: String, url: String },
+	LineBreak
+}
+
+/// A custom emoji `parse_with_emojis` may turn `:name:` into.
+#[derive(Debug, Clone)]
+pub struct EmojiRef {
+	pub id: Uuid,
+	pub url: String
+}
+
+/// Parses the supported subset: `**bold**`, `*italic*` or `_italic_`, `` `code` ``,
+/// fenced code blocks with an optional language, `[text](url)` and bare links,
+/// `||spoilers||` and `>` block quotes. Everything else is text.
+pub fn parse(text: &str) -> Vec<Block> {
+	parse_with_emojis(text, &HashMap::new())
+}
+
+/// Like `parse`, also turning `:name:` into the custom emojis found in `emojis`.
+pub fn parse_with_emojis(text: &str, emojis: &HashMap<String, EmojiRef>) -> Vec<Block> {
+	let lines: Vec<&str> = text.lines().collect();
+	parse_blocks(&lines, 0, emojis)
+}
+
+pub fn is_shortcode_char(c: char) -> bool {
+	c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'
+}
+
+/// The name of a `:shortcode:` starting at `at`, if there is one.
+fn shortcode_at(chars: &[char], at: usize) -> Option<String> {
+	if chars.get(at) != Some(&':') {
+		return None;
+	}
+	let length = chars[at + 1..].iter().take(MAX_SHORTCODE_CHARS + 1).take_while(|c| is_shortcode_char(**c)).count();
+	if length == 0 || length > MAX_SHORTCODE_CHARS || chars.get(at + 1 + length) != Some(&':') {
+		return None;
+	}
+	Some(chars[at + 1..at + 1 + length].iter().collect())
+}
+
+/// Every `:name:` in the text, so callers can look up only the emojis that are used.
+pub fn shortcodes(text: &str) -> Vec<String> {
+	let chars: Vec<char> = text.chars().collect();
+	let mut names: Vec<String> = Vec::new();
+	let mut i = 0;
+	while i < chars.len() {
+		match shortcode_at(&chars, i) {
+			Some(name) => {
+				i += name.chars().count() + 2;
+				if !names.contains(&name) {
+					names.push(name);
+				}
+			}
+			None => i += 1
+		}
+	}
+	names
+}
+
+fn fence_language(line: &str) -> Option<Option<String>> {
+	let rest = line.trim_start().strip_prefix("```")?;
+	// ```like this``` on one line is inline code, not a block.
+	if rest.contains("```") {
+		return None;
+	}
+	let language: String = rest.trim().chars()
+		.take_while(|c| c.is_ascii_alphanumeric() || "_+#-.".contains(*c))
+		.take(MAX_LANGUAGE_CHARS)
+		.collect();
+	Some(if language.is_empty() { None } else { Some(language.to_ascii_lowercase()) })
+}
+
+fn quote_line(line: &str) -> Option<&str> {
+	let rest = line.trim_start().strip_prefix('>')?;
+	Some(rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+fn parse_blocks(lines: &[&str], depth: usize, emojis: &HashMap<String, EmojiRef>) -> Vec<Block> {
+	let mut blocks: Vec<Block> = Vec::new();
+	let mut i = 0;
+	while i < lines.len() {
+		let line = lines[i];
+		if line.trim().is_empty() {
+			i += 1;
+			continue;
+		}
+		if let Some(language) = fence_language(line) {
+			let start = i + 1;
+			let mut end = start;
+			while end < lines.len() && lines[end].trim() != "```" {
+				end += 1;
+			}
+			// An unclosed fence runs to the end of the message.
+			blocks.push(Block::CodeBlock { language, code: lines[start..end].join("\n") });
+			i = end + 1;
+			continue;
+		}
+		if depth < MAX_DEPTH && quote_line(line).is_some() {
+			let mut quoted: Vec<&str> = Vec::new();
+			while let Some(inner) = lines.get(i).and_then(|line| quote_line(line)) {
+				quoted.push(inner);
+				i += 1;
+			}
+			blocks.push(Block::Quote { children: parse_blocks(&quoted, depth + 1, emojis) });
+			continue;
+		}
+		let start = i;
+		while i < lines.len()
+			&& !lines[i].trim().is_empty()
+			&& fence_language(lines[i]).is_none()
+			&& (i == start || depth >= MAX_DEPTH || quote_line(lines[i]).is_none()) {
+			i += 1;
+		}
+		let chars: Vec<char> = lines[start..i].join("\n").chars().collect();
+		blocks.push(Block::Paragraph { children: parse_inlines(&chars, depth, emojis) });
+	}
+	blocks
+}
+
+fn find(chars: &[char], from: usize, pattern: &str) -> Option<usize> {
+	let pattern: Vec<char> = pattern.chars().collect();
+	if chars.len() < pattern.len() {
+		return None;
+	}
+	(from..=chars.len() - pattern.len()).find(|start| chars[*start..*start + pattern.len()] == pattern[..])
+}
+
+fn starts_with(chars: &[char], at: usize, pattern: &str) -> bool {
+	let pattern: Vec<char> = pattern.chars().collect();
+	chars.get(at..at + pattern.len()).is_some_and(|window| window == &pattern[..])
+}
+
+/// Only web and mail links survive; `javascript:` and friends are left as text.
+fn safe_url(candidate: &str) -> Option<String> {
+	match ::url::Url::parse(candidate.trim()) {
+		Ok(parsed) if matches!(parsed.scheme(), "http" | "https" | "mailto") => Some(parsed.to_string()),
+		_ => None
+	}
+}
+
+fn push_text(nodes: &mut Vec<Inline>, text: &mut String) {
+	if !text.is_empty() {
+		nodes.push(Inline::Text { text: std::mem::take(text) });
+	}
+}
+
+fn parse_inlines(chars: &[char], depth: usize, emojis: &HashMap<String, EmojiRef>) -> Vec<Inline> {
+	let mut nodes: Vec<Inline> = Vec::new();
+	let mut text = String::new();
+	let mut i = 0;
+	while i < chars.len() {
+		let c = chars[i];
+		let after_word = i > 0 && chars[i - 1].is_alphanumeric();
+
+		if c == '\\' && chars.get(i + 1).is_some_and(|next| "\\*_`|[]()>~".contains(*next)) {
+			text.push(chars[i + 1]);
+			i += 2;
+			continue;
+		}
+		if c == '\n' {
+			push_text(&mut nodes, &mut text);
+			nodes.push(Inline::LineBreak);
+			i += 1;
+			continue;
+		}
+		if c == '`' {
+			let fence = if starts_with(chars, i, "```") { "```" } else { "`" };
+			let start = i + fence.len();
+			if let Some(end) = find(chars, start, fence).filter(|end| *end > start) {
+				push_text(&mut nodes, &mut text);
+				nodes.push(Inline::Code { code: chars[start..end].iter().collect() });
+				i = end + fence.len();
+				continue;
+			}
+		}
+		if depth < MAX_DEPTH {
+			let delimited = if starts_with(chars, i, "**") {
+				Some(("**", "bold"))
+			} else if starts_with(chars, i, "||") {
+				Some(("||", "spoiler"))
+			} else if c == '*' {
+				Some(("*", "italic"))
+			} else if c == '_' && !after_word {
+				Some(("_", "italic"))
+			} else {
+				None
+			};
+			if let Some((delimiter, kind)) = delimited {
+				let start = i + delimiter.len();
+				let closing = find(chars, start, delimiter).filter(|end| {
+					*end > start
+						&& !chars[start].is_whitespace()
+						// `snake_case_names` aren't italic.
+						&& (delimiter != "_" || !chars.get(*end + 1).is_some_and(|next| next.is_alphanumeric()))
+				});
+				if let Some(end) = closing {
+					push_text(&mut nodes, &mut text);
+					let children = parse_inlines(&chars[start..end], depth + 1, emojis);
+					nodes.push(match kind {
+						"bold" => Inline::Bold { children },
+						"spoiler" => Inline::Spoiler { children },
+						_ => Inline::Italic { children }
+					});
+					i = end + delimiter.len();
+					continue;
+				}
+			}
+			if c == '[' {
+				if let Some(label_end) = find(chars, i + 1, "](") {
+					let url_start = label_end + 2;
+					if let Some(url_end) = find(chars, url_start, ")") {
+						let target: String = chars[url_start..url_end].iter().collect();
+						if let (true, Some(url)) = (label_end > i + 1, safe_url(&target)) {
+							push_text(&mut nodes, &mut text);
+							nodes.push(Inline::Link { url, children: parse_inlines(&chars[i + 1..label_end], depth + 1, emojis) });
+							i = url_end + 1;
+							continue;
+						}
+					}
+				}
+			}
+		}
+		if let Some((name, emoji)) = shortcode_at(chars, i).and_then(|name| emojis.get(&name).map(|emoji| (name, emoji))) {
+			push_text(&mut nodes, &mut text);
+			i += name.chars().count() + 2;
+			nodes.push(Inline::CustomEmoji { id: emoji.id, name, url: emoji.url.to_owned() });
+			continue;
+		}
+		if !after_word && (starts_with(chars, i, "https://") || starts_with(chars, i, "http://")) {
+			let mut end = i;
+			while end < chars.len() && !chars[end].is_whitespace() {
+				end += 1;
+			}
+			// Trailing punctuation belongs to the sentence, not the link.
+			while end > i && ".,;:!?)]}>'\"*_~`|".contains(chars[end - 1]) {
+				end -= 1;
+			}
+			let candidate: String = chars[i..end].iter().collect();
+			if let Some(url) = safe_url(&candidate) {
+				push_text(&mut nodes, &mut text);
+				nodes.push(Inline::Link { url, children: vec![Inline::Text { text: candidate }] });
+				i = end;
+				continue;
+			}
+		}
+		text.push(c);
+		i += 1;
+	}
+	push_text(&mut nodes, &mut text);
+	nodes
+}
+
+fn inline_plain_text(nodes: &[Inline], out: &mut String) {
+	for node in nodes {
+		match node {
+			Inline::Text { text } => out.push_str(text),
+			Inline::Code { code } => out.push_str(code),
+			Inline::Bold { children } | Inline::Italic { children } | Inline::Link { children, .. } => {
+				inline_plain_text(children, out)
+			}
+			// Previews shouldn't give the spoiler away.
+			Inline::Spoiler { .. } => out.push_str("[spoiler]"),
+			Inline::CustomEmoji { name, .. } => out.push_str(&format!(":{}:", name)),
+			Inline::LineBreak => out.push('\n')
+		}
+	}
+}
+
+/// Renders the content without formatting, e.g. for conversation list previews.
+pub fn to_plain_text(blocks: &[Block]) -> String {
+	let mut parts: Vec<String> = Vec::new();
+	for block in blocks {
+		match block {
+			Block::Paragraph { children } => {
+				let mut out = String::new();
+				inline_plain_text(children, &mut out);
+				parts.push(out);
+			}
+			Block::CodeBlock { code, .. } => parts.push(code.to_owned()),
+			Block::Quote { children } => parts.push(to_plain_text(children))
+		}
+	}
+	parts.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn text(text: &str) -> Inline {
+		Inline::Text { text: String::from(text) }
+	}
+
+	fn paragraph(children: Vec<Inline>) -> Vec<Block> {
+		vec![Block::Paragraph { children }]
+	}
+
+	#[test]
+	fn leaves_unsafe_links_as_text() {
+		assert_eq!(parse("[x](javascript:alert(1))"), paragraph(vec![text("[x](javascript:alert(1))")]));
+		assert_eq!(parse("[x](data:text/html,hi)"), paragraph(vec![text("[x](data:text/html,hi)")]));
+		assert_eq!(parse("[x](https://example.com)"), paragraph(vec![Inline::Link {
+			url: String::from("https://example.com/"),
+			children: vec![text("x")]
+		}]));
+	}
+
+	#[test]
+	fn trims_sentence_punctuation_from_bare_links() {
+		assert_eq!(parse("see https://example.com/a."), paragraph(vec![
+			text("see "),
+			Inline::Link { url: String::from("https://example.com/a"), children: vec![text("https://example.com/a")] },
+			text(".")
+		]));
+	}
+
+	#[test]
+	fn keeps_snake_case_and_escapes_as_text() {
+		assert_eq!(parse("call snake_case_names here"), paragraph(vec![text("call snake_case_names here")]));
+		assert_eq!(parse("\\*not italic\\*"), paragraph(vec![text("*not italic*")]));
+		assert_eq!(parse("_italic_"), paragraph(vec![Inline::Italic { children: vec![text("italic")] }]));
+	}
+
+	#[test]
+	fn runs_an_unclosed_fence_to_the_end() {
+		assert_eq!(parse("```Rust\nfn main() {}\n\n**still code**"), vec![Block::CodeBlock {
+			language: Some(String::from("rust")),
+			code: String::from("fn main() {}\n\n**still code**")
+		}]);
+	}
+
+	#[test]
+	fn stops_nesting_quotes_at_max_depth() {
+		let mut blocks = parse(&format!("{} deep", ">".repeat(MAX_DEPTH + 2)));
+		for _ in 0..MAX_DEPTH {
+			blocks = match blocks.as_slice() {
+				[Block::Quote { children }] => children.clone(),
+				other => panic!("expected a quote, got {:?}", other)
+			};
+		}
+		assert_eq!(blocks, paragraph(vec![text(">> deep")]));
+	}
+
+	#[test]
+	fn hides_spoilers_in_plain_text() {
+		let blocks = parse("the butler **did** it, ||or did he||\n> quoted");
+		assert_eq!(to_plain_text(&blocks), "the butler did it, [spoiler]\nquoted");
+	}
+}
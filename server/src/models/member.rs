@@ -1,13 +1,21 @@
 use diesel::prelude::*;
+use diesel::sql_query;
 use diesel::{PgConnection, QueryResult};
 use serde::{Deserialize, Serialize};
+use diesel::pg::expression::dsl::{any};
 use uuid::{Uuid};
 // use chrono::prelude::*;
 use crate::schema::members::{self, dsl::*};
 use crate::lib::{json_time, json_option_time};
+use crate::models::conversation::Conversation;
+use crate::models::permission::Role;
+use crate::models::user::User;
+use crate::schema::users;
+use std::collections::HashMap;
 // use std::env;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, QueryableByName)]
+#[table_name="members"]
 #[serde(rename_all="camelCase")]
 pub struct Member {
 	pub conversation_id: Uuid,
@@ -20,6 +28,8 @@ pub struct Member {
 	pub avatar: Option<String>,
 	pub is_kicked: bool,
 	pub is_banned: bool,
+	pub role: String,
+	pub is_archived: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Insertable, Queryable)]
@@ -27,7 +37,81 @@ pub struct Member {
 #[table_name="members"]
 pub struct NewMember {
 	pub conversation_id: Uuid,
-	pub user_id: Uuid
+	pub user_id: Uuid,
+	pub role: Option<String>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct UpdateProfileBody {
+	pub nick: Option<String>,
+	pub avatar: Option<String>
+}
+
+/// How a user appears inside a conversation: the member's nick and avatar
+/// take precedence over the global username and avatar.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct MemberIdentity {
+	pub user_id: Uuid,
+	pub username: String,
+	pub display_name: String,
+	pub avatar_url: Option<String>
+}
+
+impl MemberIdentity {
+	pub fn resolve(user: &User, member: Option<&Member>) -> MemberIdentity {
+		let nick_value = member.and_then(|m| m.nick.to_owned());
+		let avatar_value = member.and_then(|m| m.avatar.to_owned());
+		MemberIdentity {
+			user_id: user.id,
+			username: user.username.to_owned(),
+			display_name: nick_value.unwrap_or_else(|| user.username.to_owned()),
+			avatar_url: avatar_value.or_else(|| user.avatar_url.to_owned())
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct MemberWithIdentity {
+	#[serde(flatten)]
+	pub member: Member,
+	pub identity: MemberIdentity
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct UserWithIdentity {
+	#[serde(flatten)]
+	pub user: User,
+	pub identity: MemberIdentity
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct MarkReadBody {
+	pub message_id: Option<Uuid>
+}
+
+/// What `Member::join` did for a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinResult {
+	Joined,
+	AlreadyMember,
+	Banned
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+pub struct UnreadCount {
+	#[sql_type = "diesel::sql_types::Uuid"]
+	pub conversation_id: Uuid,
+	#[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>"]
+	pub last_read_at: Option<chrono::NaiveDateTime>,
+	#[sql_type = "diesel::sql_types::BigInt"]
+	pub unread_count: i64,
+	#[sql_type = "diesel::sql_types::BigInt"]
+	pub mention_count: i64
 }
 
 impl Member {
@@ -39,11 +123,243 @@ impl Member {
 		diesel::insert_into(members).values(new_members).execute(conn)
 	}
 
+	pub fn add_many(cid: &Uuid, user_ids: &[Uuid], conn: &PgConnection) -> QueryResult<Vec<Uuid>> {
+		conn.transaction(|| {
+			let existing = members
+				.filter(conversation_id.eq(cid).and(user_id.eq(any(user_ids))))
+				.get_results::<Member>(conn)?;
+			let new_members: Vec<NewMember> = user_ids.iter()
+				.filter(|uid| !existing.iter().any(|m| m.user_id == **uid))
+				.map(|uid| NewMember {conversation_id: *cid, user_id: *uid, role: None})
+				.collect();
+			let mut added_ids = diesel::insert_into(members)
+				.values(&new_members)
+				.on_conflict_do_nothing()
+				.returning(user_id)
+				.get_results::<Uuid>(conn)?;
+			let kicked_ids: Vec<Uuid> = existing.iter()
+				.filter(|m| m.is_kicked && !m.is_banned)
+				.map(|m| m.user_id)
+				.collect();
+			if !kicked_ids.is_empty() {
+				diesel::update(members.filter(conversation_id.eq(cid).and(user_id.eq(any(&kicked_ids)))))
+					.set((is_kicked.eq(false), role.eq(Role::Member.as_str()), joined_at.eq(diesel::dsl::now)))
+					.execute(conn)?;
+				added_ids.extend(kicked_ids);
+			}
+			Ok(added_ids)
+		})
+	}
+
+	pub fn fetch_user_ids(cid: &Uuid, conn: &PgConnection) -> QueryResult<Vec<Uuid>> {
+		members.select(user_id)
+			.filter(conversation_id.eq(cid).and(is_kicked.eq(false)).and(is_banned.eq(false)))
+			.get_results::<Uuid>(conn)
+	}
+
 	pub fn fetch_by_conversation(uid: &Uuid, conn: &PgConnection) -> QueryResult<std::vec::Vec<Member>> {
-		members.filter(conversation_id.eq(uid)).get_results::<Member>(conn)
+		members.filter(conversation_id.eq(uid).and(is_kicked.eq(false)).and(is_banned.eq(false))).get_results::<Member>(conn)
 	}
 
-	pub fn get_member_or_throw(uid: &uuid::Uuid, cid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<Member> {
+	pub fn fetch_with_users_by_conversation(cid: &Uuid, conn: &PgConnection) -> QueryResult<Vec<(Member, User)>> {
+		members.inner_join(users::table)
+			.filter(conversation_id.eq(cid).and(is_kicked.eq(false)).and(is_banned.eq(false)))
+			.get_results::<(Member, User)>(conn)
+	}
+
+	/// Resolves identities for the given users, including ones who are no longer members.
+	pub fn fetch_identities(cid: &Uuid, user_ids: &[Uuid], conn: &PgConnection) -> QueryResult<HashMap<Uuid, MemberIdentity>> {
+		let user_rows = User::fetch_by_ids(&user_ids.to_vec(), conn)?;
+		let member_rows = members
+			.filter(conversation_id.eq(cid).and(user_id.eq(any(user_ids))))
+			.get_results::<Member>(conn)?;
+		let mut identities: HashMap<Uuid, MemberIdentity> = HashMap::new();
+		for user in user_rows.iter() {
+			let member = member_rows.iter().find(|m| m.user_id == user.id);
+			identities.insert(user.id, MemberIdentity::resolve(user, member));
+		}
+		Ok(identities)
+	}
+
+	pub fn update_profile(uid: &Uuid, cid: &Uuid, new_nick: Option<String>, new_avatar: Option<String>, conn: &PgConnection) -> QueryResult<Member> {
+		diesel::update(members.filter(user_id.eq(uid).and(conversation_id.eq(cid))))
+			.set((nick.eq(new_nick), avatar.eq(new_avatar)))
+			.get_result::<Member>(conn)
+	}
+
+	/// Advances `last_read_at` to `read_at`; it never moves backwards.
+	pub fn mark_read(uid: &Uuid, cid: &Uuid, read_at: &chrono::NaiveDateTime, conn: &PgConnection) -> QueryResult<Member> {
+		sql_query(r#"
+			update members
+			set last_read_at = greatest(coalesce(members.last_read_at, $3), $3)
+			where members.user_id = $1 and members.conversation_id = $2
+			and members.is_kicked = false and members.is_banned = false
+			returning *;
+		"#)
+		.bind::<diesel::sql_types::Uuid, _>(uid)
+		.bind::<diesel::sql_types::Uuid, _>(cid)
+		.bind::<diesel::sql_types::Timestamptz, _>(read_at)
+		.get_result::<Member>(conn)
+	}
+
+	/// Counts messages from others newer than the user's read marker, per conversation.
+	pub fn fetch_unread_counts(uid: &Uuid, cids: &[Uuid], conn: &PgConnection) -> QueryResult<Vec<UnreadCount>> {
+		sql_query(r#"
+			select m.conversation_id, m.last_read_at,
+			(
+				select count(*) from messages msg
+				where msg.conversation_id = m.conversation_id
+				and msg.author_id != m.user_id and msg.is_deleted = false
+				and msg.thread_root_id is null
+				and msg.created_at > coalesce(m.last_read_at, m.joined_at)
+			) as unread_count,
+			(
+				select count(*) from message_mentions mm
+				inner join messages msg on msg.id = mm.message_id
+				where mm.conversation_id = m.conversation_id and mm.user_id = m.user_id
+				and msg.is_deleted = false
+				and msg.created_at > coalesce(m.last_read_at, m.joined_at)
+			) as mention_count
+			from members m
+			where m.user_id = $1 and m.conversation_id = any($2)
+			and m.is_kicked = false and m.is_banned = false;
+		"#)
+		.bind::<diesel::sql_types::Uuid, _>(uid)
+		.bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(cids)
+		.get_results::<UnreadCount>(conn)
+	}
+
+	pub fn set_archived(uid: &Uuid, cid: &Uuid, archived: bool, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::update(members.filter(user_id.eq(uid).and(conversation_id.eq(cid))))
+			.filter(is_kicked.eq(false).and(is_banned.eq(false)))
+			.set(is_archived.eq(archived))
+			.execute(conn)
+	}
+
+	pub fn fetch_banned_by_conversation(cid: &Uuid, conn: &PgConnection) -> QueryResult<Vec<Member>> {
+		members.filter(conversation_id.eq(cid).and(is_banned.eq(true))).get_results::<Member>(conn)
+	}
+
+	/// Fetches a member row regardless of its kicked/banned state.
+	pub fn find_one(uid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<Member> {
 		members.filter(user_id.eq(uid).and(conversation_id.eq(cid))).get_result::<Member>(conn)
 	}
+
+	/// Fetches an active member, failing with `NotFound` for kicked or banned users.
+	pub fn get_member_or_throw(uid: &uuid::Uuid, cid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<Member> {
+		members
+			.filter(user_id.eq(uid).and(conversation_id.eq(cid)))
+			.filter(is_kicked.eq(false).and(is_banned.eq(false)))
+			.get_result::<Member>(conn)
+	}
+
+	pub fn kick(uid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::update(members.filter(user_id.eq(uid).and(conversation_id.eq(cid))))
+			.set(is_kicked.eq(true))
+			.execute(conn)
+	}
+
+	pub fn ban(uid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::insert_into(members)
+			.values(&NewMember {conversation_id: *cid, user_id: *uid, role: None})
+			.on_conflict((conversation_id, user_id))
+			.do_update()
+			.set((is_kicked.eq(true), is_banned.eq(true)))
+			.execute(conn)
+	}
+
+	/// Lifts a ban. The user stays kicked until someone adds them back.
+	pub fn unban(uid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::update(members.filter(user_id.eq(uid).and(conversation_id.eq(cid)).and(is_banned.eq(true))))
+			.set(is_banned.eq(false))
+			.execute(conn)
+	}
+
+	pub fn set_role(uid: &Uuid, cid: &Uuid, new_role: Role, conn: &PgConnection) -> QueryResult<Member> {
+		diesel::update(members.filter(user_id.eq(uid).and(conversation_id.eq(cid))))
+			.set(role.eq(new_role.as_str()))
+			.get_result::<Member>(conn)
+	}
+
+	/// Makes `new_owner_id` the owner and demotes the current owner to admin.
+	pub fn transfer_ownership(cid: &Uuid, owner_id: &Uuid, new_owner_id: &Uuid, conn: &PgConnection) -> QueryResult<()> {
+		conn.transaction(|| {
+			Member::set_role(owner_id, cid, Role::Admin, conn)?;
+			Member::set_role(new_owner_id, cid, Role::Owner, conn)?;
+			Conversation::set_admin(cid, Some(*new_owner_id), conn)?;
+			Ok(())
+		})
+	}
+
+	/// Picks the highest ranked, longest standing active member to inherit ownership.
+	pub fn find_successor(cid: &Uuid, except_id: &Uuid, conn: &PgConnection) -> QueryResult<Option<Member>> {
+		let candidates = members
+			.filter(conversation_id.eq(cid).and(user_id.ne(except_id)))
+			.filter(is_kicked.eq(false).and(is_banned.eq(false)))
+			.order_by(joined_at.asc())
+			.get_results::<Member>(conn)?;
+		let mut successor: Option<Member> = None;
+		for candidate in candidates {
+			let is_better = match &successor {
+				Some(current) => candidate.role() > current.role(),
+				None => true
+			};
+			if is_better {
+				successor = Some(candidate);
+			}
+		}
+		Ok(successor)
+	}
+
+	/// Removes the member; if they owned the conversation, ownership passes to a successor.
+	pub fn leave(uid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<Option<Uuid>> {
+		conn.transaction(|| {
+			let member = Member::get_member_or_throw(uid, cid, conn)?;
+			Member::delete_one(uid, cid, conn)?;
+			if member.role() != Role::Owner {
+				return Ok(None);
+			}
+			match Member::find_successor(cid, uid, conn)? {
+				Some(successor) => {
+					Member::set_role(&successor.user_id, cid, Role::Owner, conn)?;
+					Conversation::set_admin(cid, Some(successor.user_id), conn)?;
+					Ok(Some(successor.user_id))
+				}
+				None => {
+					Conversation::set_admin(cid, None, conn)?;
+					Ok(None)
+				}
+			}
+		})
+	}
+
+	/// Adds the user as a plain member, re-activating their row if they were kicked.
+	/// Active members keep their role and banned users are left out.
+	pub fn join(uid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<JoinResult> {
+		conn.transaction(|| {
+			let existing = members
+				.filter(user_id.eq(uid).and(conversation_id.eq(cid)))
+				.for_update()
+				.get_result::<Member>(conn)
+				.optional()?;
+			match existing {
+				Some(member) if member.is_banned => Ok(JoinResult::Banned),
+				Some(member) if !member.is_kicked => Ok(JoinResult::AlreadyMember),
+				Some(_) => {
+					diesel::update(members.filter(user_id.eq(uid).and(conversation_id.eq(cid))))
+						.set((is_kicked.eq(false), role.eq(Role::Member.as_str()), joined_at.eq(diesel::dsl::now)))
+						.execute(conn)?;
+					Ok(JoinResult::Joined)
+				}
+				None => {
+					Member::insert_one(&NewMember {conversation_id: *cid, user_id: *uid, role: None}, conn)?;
+					Ok(JoinResult::Joined)
+				}
+			}
+		})
+	}
+
+	pub fn delete_one(uid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::delete(members.filter(user_id.eq(uid).and(conversation_id.eq(cid)))).execute(conn)
+	}
 }
\ No newline at end of file
@@ -0,0 +1,174 @@
+use diesel::prelude::*;
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use uuid::{Uuid};
+use crate::schema::message_mentions::{self, dsl::*};
+use crate::schema::{members, messages};
+use crate::lib::json_time;
+use crate::models;
+use crate::models::member::Member;
+use crate::models::message::Message;
+
+pub const KIND_USER: &str = "user";
+pub const KIND_EVERYONE: &str = "everyone";
+pub const KIND_HERE: &str = "here";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[serde(rename_all="camelCase")]
+pub struct Mention {
+	pub message_id: Uuid,
+	pub user_id: Uuid,
+	pub conversation_id: Uuid,
+	pub kind: String,
+	#[serde(with = "json_time")]
+	pub created_at: chrono::NaiveDateTime
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name="message_mentions"]
+pub struct NewMention {
+	pub message_id: Uuid,
+	pub user_id: Uuid,
+	pub conversation_id: Uuid,
+	pub kind: String
+}
+
+/// The mention tokens found in a message body, before they are matched against members.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedMentions {
+	pub usernames: Vec<String>,
+	pub everyone: bool,
+	pub here: bool
+}
+
+impl ParsedMentions {
+	/// Finds `@name` tokens. An `@` only starts a mention at the beginning of the text or
+	/// after a non-word character, so email addresses are not picked up.
+	pub fn parse(text: &str) -> ParsedMentions {
+		let mut parsed = ParsedMentions::default();
+		let chars: Vec<char> = text.chars().collect();
+		let mut i = 0;
+		while i < chars.len() {
+			let starts_mention = chars[i] == '@'
+				&& (i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == '_'));
+			if !starts_mention {
+				i += 1;
+				continue;
+			}
+			let mut end = i + 1;
+			while end < chars.len() && (chars[end].is_alphanumeric() || "_.-".contains(chars[end])) {
+				end += 1;
+			}
+			// Trailing punctuation belongs to the sentence, not the name.
+			while end > i + 1 && ".-".contains(chars[end - 1]) {
+				end -= 1;
+			}
+			let name: String = chars[i + 1..end].iter().collect();
+			match name.as_str() {
+				"" => {}
+				"everyone" => parsed.everyone = true,
+				"here" => parsed.here = true,
+				_ => {
+					if !parsed.usernames.iter().any(|n| n.eq_ignore_ascii_case(&name)) {
+						parsed.usernames.push(name);
+					}
+				}
+			}
+			i = end.max(i + 1);
+		}
+		parsed
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.usernames.is_empty() && !self.everyone && !self.here
+	}
+}
+
+impl Mention {
+	/// Matches the parsed tokens against the conversation's active members. `@everyone`
+	/// reaches every member, `@here` only those currently online; the author is never mentioned.
+	pub fn resolve(message: &Message, conn: &PgConnection) -> QueryResult<Vec<NewMention>> {
+		let parsed = match &message.content {
+			Some(text) => ParsedMentions::parse(text),
+			None => return Ok(Vec::new())
+		};
+		if parsed.is_empty() {
+			return Ok(Vec::new());
+		}
+		let member_rows = Member::fetch_with_users_by_conversation(&message.conversation_id, conn)?;
+		let mut mentions: Vec<NewMention> = Vec::new();
+		for (member, user) in member_rows.iter() {
+			if member.user_id == message.author_id {
+				continue;
+			}
+			let mention_kind = if parsed.usernames.iter().any(|name| name.eq_ignore_ascii_case(&user.username)) {
+				KIND_USER
+			} else if parsed.everyone {
+				KIND_EVERYONE
+			} else if parsed.here && user.is_online {
+				KIND_HERE
+			} else {
+				continue;
+			};
+			mentions.push(NewMention {
+				message_id: message.id,
+				user_id: member.user_id,
+				conversation_id: message.conversation_id,
+				kind: mention_kind.to_string()
+			});
+		}
+		Ok(mentions)
+	}
+
+	/// Parses and stores the mentions of a freshly created message.
+	pub fn create_for_message(message: &Message, conn: &PgConnection) -> QueryResult<Vec<Mention>> {
+		let new_mentions = Mention::resolve(message, conn)?;
+		if new_mentions.is_empty() {
+			return Ok(Vec::new());
+		}
+		diesel::insert_into(message_mentions)
+			.values(&new_mentions)
+			.on_conflict_do_nothing()
+			.get_results::<Mention>(conn)
+	}
+
+	/// Re-parses an edited message, dropping mentions that were removed.
+	/// Returns only the mentions that are new, so users are not notified twice.
+	pub fn sync_for_message(message: &Message, conn: &PgConnection) -> QueryResult<Vec<Mention>> {
+		conn.transaction(|| {
+			let new_mentions = Mention::resolve(message, conn)?;
+			let kept_ids: Vec<Uuid> = new_mentions.iter().map(|m| m.user_id).collect();
+			diesel::delete(message_mentions
+				.filter(message_id.eq(message.id).and(diesel::dsl::not(user_id.eq_any(&kept_ids)))))
+				.execute(conn)?;
+			if new_mentions.is_empty() {
+				return Ok(Vec::new());
+			}
+			diesel::insert_into(message_mentions)
+				.values(&new_mentions)
+				.on_conflict_do_nothing()
+				.get_results::<Mention>(conn)
+		})
+	}
+
+	/// Messages mentioning the user, newest first, limited to conversations they are still in.
+	pub fn fetch_inbox(
+		uid: &Uuid,
+		options: &models::pagination::Options,
+		conn: &PgConnection
+	) -> QueryResult<models::pagination::Result<Vec<Message>>> {
+		let make_query = || {
+			let mentioned_ids = message_mentions
+				.filter(user_id.eq(uid))
+				.select(message_id);
+			let member_conversation_ids = members::table
+				.filter(members::user_id.eq(uid).and(members::is_kicked.eq(false)).and(members::is_banned.eq(false)))
+				.select(members::conversation_id);
+			messages::table.into_boxed()
+				.filter(messages::id.eq_any(mentioned_ids))
+				.filter(messages::conversation_id.eq_any(member_conversation_ids))
+				.filter(messages::is_deleted.eq(false))
+		};
+		Message::paginate(make_query, options, conn)
+	}
+}
@@ -1,12 +1,24 @@
 use diesel::prelude::*;
 use diesel::sql_query;
+use diesel::pg::Pg;
 use serde::{Serialize, Deserialize};
 // use diesel::{}
 use crate::schema::messages::{self, dsl::*};
+use crate::schema::message_edits;
+use std::env;
+use diesel::pg::expression::dsl::{any};
 use crate::models;
+use crate::models::attachment::{Attachment, AttachmentBody};
+use crate::models::custom_emoji::CustomEmoji;
+use crate::models::forward::Provenance;
+use crate::models::link_preview::LinkPreview;
+use crate::models::pin::PinnedMessage;
+use crate::models::reaction::{Reaction, ReactionSummary};
+use crate::models::markdown;
 use crate::lib::{json_option_time, json_time};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, QueryableByName)]
+#[table_name="messages"]
 #[serde(rename_all="camelCase")]
 pub struct Message {
 	pub id: uuid::Uuid,
@@ -18,15 +30,217 @@ pub struct Message {
 	pub created_at: chrono::NaiveDateTime,
 	#[serde(with = "json_option_time")]
 	pub updated_at: Option<chrono::NaiveDateTime>,
-	pub reactions: serde_json::Value,
-	pub is_image: bool
+	pub reply_to_id: Option<uuid::Uuid>,
+	pub thread_root_id: Option<uuid::Uuid>,
+	pub thread_reply_count: i32,
+	#[serde(with = "json_option_time")]
+	pub thread_last_reply_at: Option<chrono::NaiveDateTime>,
+	/// `content` parsed into `markdown::Block`s; missing for messages sent before formatting existed.
+	pub content_ast: Option<serde_json::Value>,
+	pub is_forwarded: bool,
+	/// Where a forwarded message originally came from. Clients get this as a `Provenance`
+	/// resolved for them instead, so the raw ids are never sent.
+	#[serde(skip_serializing)]
+	pub forwarded_from_message_id: Option<uuid::Uuid>,
+	#[serde(skip_serializing)]
+	pub forwarded_from_conversation_id: Option<uuid::Uuid>,
+	#[serde(skip_serializing)]
+	pub forwarded_from_author_id: Option<uuid::Uuid>
 }
 
+/// Sent along with a thread reply so clients can update the root's thread badge.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct ThreadSummary {
+	pub root_id: uuid::Uuid,
+	pub reply_count: i32,
+	#[serde(with = "json_option_time")]
+	pub last_reply_at: Option<chrono::NaiveDateTime>
+}
+
+const REPLY_PREVIEW_LENGTH: usize = 100;
+
+/// A compact view of the message being replied to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct ReplyPreview {
+	pub id: uuid::Uuid,
+	pub author_id: uuid::Uuid,
+	pub author_name: Option<String>,
+	pub content: Option<String>,
+	pub is_deleted: bool,
+	/// Kind of the first attachment, so clients can show e.g. "Photo" for captionless replies.
+	pub attachment_kind: Option<String>,
+	pub attachment_count: usize
+}
+
+impl ReplyPreview {
+	pub fn new(parent: &Message, author_identity: Option<&models::member::MemberIdentity>, parent_attachments: &[Attachment]) -> ReplyPreview {
+		let content_preview = if parent.is_deleted {
+			None
+		} else {
+			parent.content.as_ref().map(|text| {
+				let text = text.trim();
+				if text.chars().count() > REPLY_PREVIEW_LENGTH {
+					format!("{}…", text.chars().take(REPLY_PREVIEW_LENGTH).collect::<String>())
+				} else {
+					text.to_string()
+				}
+			})
+		};
+		ReplyPreview {
+			id: parent.id,
+			author_id: parent.author_id,
+			author_name: author_identity.map(|identity| identity.display_name.to_owned()),
+			content: content_preview,
+			is_deleted: parent.is_deleted,
+			attachment_kind: parent_attachments.first().map(|attachment| attachment.kind.to_owned()),
+			attachment_count: parent_attachments.len()
+		}
+	}
+}
+
+/// A message as returned to clients, with its author resolved for the conversation.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct MessageView {
+	#[serde(flatten)]
+	pub message: Message,
+	pub author_identity: Option<models::member::MemberIdentity>,
+	pub reply_to: Option<ReplyPreview>,
+	pub attachments: Vec<Attachment>,
+	pub link_previews: Vec<LinkPreview>,
+	pub reactions: Vec<ReactionSummary>,
+	/// Set on forwarded messages only.
+	pub forwarded_from: Option<Provenance>
+}
+
+impl MessageView {
+	/// `viewer` decides the `me` flag of each reaction summary.
+	pub fn from_messages(cid: &uuid::Uuid, viewer: &uuid::Uuid, items: Vec<Message>, conn: &PgConnection) -> QueryResult<Vec<MessageView>> {
+		let reply_ids: Vec<uuid::Uuid> = items.iter().filter_map(|message| message.reply_to_id).collect();
+		let parents = if reply_ids.is_empty() {
+			Vec::new()
+		} else {
+			messages.filter(id.eq(any(&reply_ids))).get_results::<Message>(conn)?
+		};
+		let mut author_ids: Vec<uuid::Uuid> = Vec::new();
+		for message in items.iter().chain(parents.iter()) {
+			if !author_ids.contains(&message.author_id) {
+				author_ids.push(message.author_id);
+			}
+		}
+		let identities = models::member::Member::fetch_identities(cid, &author_ids, conn)?;
+		let message_ids: Vec<uuid::Uuid> = items.iter().chain(parents.iter()).map(|message| message.id).collect();
+		let mut attachments = Attachment::fetch_by_message_ids(&message_ids, conn)?;
+		let mut link_previews = LinkPreview::fetch_by_message_ids(&message_ids[..items.len()], conn)?;
+		let mut reaction_summaries = Reaction::summaries(&message_ids[..items.len()], viewer, conn)?;
+		let mut provenances = Provenance::resolve_many(viewer, &items, conn)?;
+		let reply_previews: Vec<ReplyPreview> = parents.iter().map(|parent| {
+			let parent_attachments = attachments.get(&parent.id).map_or(&[][..], |found| &found[..]);
+			ReplyPreview::new(parent, identities.get(&parent.author_id), parent_attachments)
+		}).collect();
+		Ok(items.into_iter().map(|message| {
+			let author_identity = identities.get(&message.author_id).cloned();
+			let reply_to = message.reply_to_id
+				.and_then(|reply_id| reply_previews.iter().find(|preview| preview.id == reply_id))
+				.cloned();
+			let message_attachments = attachments.remove(&message.id).unwrap_or_default();
+			let message_link_previews = link_previews.remove(&message.id).unwrap_or_default();
+			let message_reactions = reaction_summaries.remove(&message.id).unwrap_or_default();
+			let forwarded_from = provenances.remove(&message.id);
+			MessageView {
+				message,
+				author_identity,
+				reply_to,
+				attachments: message_attachments,
+				link_previews: message_link_previews,
+				reactions: message_reactions,
+				forwarded_from
+			}
+		}).collect())
+	}
+
+	/// Like `from_messages`, for lists spanning several conversations. Keeps the input order.
+	pub fn from_mixed_messages(viewer: &uuid::Uuid, items: Vec<Message>, conn: &PgConnection) -> QueryResult<Vec<MessageView>> {
+		let order: Vec<uuid::Uuid> = items.iter().map(|message| message.id).collect();
+		let mut grouped: Vec<(uuid::Uuid, Vec<Message>)> = Vec::new();
+		for message in items {
+			match grouped.iter_mut().find(|(cid, _)| *cid == message.conversation_id) {
+				Some((_, group)) => group.push(message),
+				None => grouped.push((message.conversation_id, vec![message]))
+			}
+		}
+		let mut views: Vec<MessageView> = Vec::new();
+		for (cid, group) in grouped {
+			views.extend(MessageView::from_messages(&cid, viewer, group, conn)?);
+		}
+		views.sort_by_key(|view| order.iter().position(|mid| *mid == view.message.id));
+		Ok(views)
+	}
+}
+
+/// A previous version of a message, recorded each time it is edited.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[serde(rename_all="camelCase")]
+pub struct MessageEdit {
+	pub id: uuid::Uuid,
+	pub message_id: uuid::Uuid,
+	pub content: Option<String>,
+	#[serde(with = "json_time")]
+	pub edited_at: chrono::NaiveDateTime
+}
+
+/// Extra options for message history on top of `pagination::Options`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageListQuery {
+	/// Load a page centred on this message instead of paging from a cursor.
+	pub around: Option<uuid::Uuid>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditMessageBody {
+	pub content: String
+}
+
+pub const MAX_FORWARD_TARGETS: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct ForwardMessageBody {
+	pub conversation_ids: Vec<uuid::Uuid>
+}
+
+#[derive(Debug)]
+pub enum EditError {
+	NotFound,
+	NotAuthor,
+	NotEditable,
+	WindowExpired,
+	Database(diesel::result::Error)
+}
+
+impl From<diesel::result::Error> for EditError {
+	fn from(e: diesel::result::Error) -> Self {
+		match e {
+			diesel::result::Error::NotFound => EditError::NotFound,
+			e => EditError::Database(e)
+		}
+	}
+}
+
+const DEFAULT_EDIT_WINDOW_SECONDS: i64 = 15 * 60;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateMessageBody {
 	pub conversation_id: uuid::Uuid,
 	pub content: Option<String>,
-	pub is_image: bool
+	#[serde(default)]
+	pub attachments: Vec<AttachmentBody>,
+	#[serde(default)]
+	pub reply_to_id: Option<uuid::Uuid>,
+	#[serde(default)]
+	pub thread_root_id: Option<uuid::Uuid>
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Insertable, Queryable)]
@@ -36,7 +250,8 @@ pub struct NewMessage {
 	pub conversation_id: uuid::Uuid,
 	pub author_id: uuid::Uuid,
 	pub content: Option<String>,
-	pub is_image: bool
+	pub reply_to_id: Option<uuid::Uuid>,
+	pub thread_root_id: Option<uuid::Uuid>
 }	
 
 impl NewMessage {
@@ -45,116 +260,380 @@ impl NewMessage {
 	}
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all="camelCase")]
-struct ReactionData {
-	#[serde(with = "json_time")]
-	created_at: chrono::NaiveDateTime,
-	name: String
-}
-
 impl Message {
 	pub fn insert_one(new_message: &NewMessage, conn: &PgConnection) -> QueryResult<Message> {
-		diesel::insert_into(messages).values(new_message).get_result::<Message>(conn)
+		let ast = match new_message.content.as_deref() {
+			Some(text) => Some(Message::parse_content(text, &new_message.conversation_id, conn)?),
+			None => None
+		};
+		diesel::insert_into(messages)
+			.values((new_message, content_ast.eq(ast)))
+			.get_result::<Message>(conn)
 	}
 
-	pub fn create_reaction(uid: &uuid::Uuid, 
-		cid: &uuid::Uuid, 
-		mid: &uuid::Uuid, 
-		emoji_name: &String, 
+	/// Parses `text` for the conversation `cid`, resolving the custom emojis usable there.
+	pub fn parse_content(text: &str, cid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<serde_json::Value> {
+		let emojis = CustomEmoji::resolve(cid, &markdown::shortcodes(text), conn)?
+			.into_iter()
+			.map(|(emoji_name, emoji)| {
+				let emoji_url = emoji.url();
+				(emoji_name, markdown::EmojiRef { id: emoji.id, url: emoji_url })
+			})
+			.collect();
+		Ok(serde_json::json!(markdown::parse_with_emojis(text, &emojis)))
+	}
+
+	/// The content with formatting stripped, for previews such as `LastMessageDisplay`.
+	pub fn plain_text(&self) -> Option<String> {
+		let text = self.content.as_ref()?;
+		let blocks = self.content_ast.clone()
+			.and_then(|ast| serde_json::from_value::<Vec<markdown::Block>>(ast).ok())
+			.unwrap_or_else(|| markdown::parse(text));
+		Some(markdown::to_plain_text(&blocks))
+	}
+
+	/// Inserts a message with its attachments. Thread replies also bump the root's reply
+	/// count and last reply time, which is returned so clients can update the root.
+	pub fn create(
+		new_message: &NewMessage,
+		attachment_bodies: &[AttachmentBody],
 		conn: &PgConnection
-	) -> Result<usize, diesel::result::Error> {
-		let reaction_key = format!("{}:{}",uid.to_hyphenated(), emoji_name);
-		let reaction_data: ReactionData = ReactionData {
-			created_at: chrono::Utc::now().naive_utc(),
-			name: emoji_name.to_owned()
-		};
-		let data: serde_json::Value = serde_json::to_value(reaction_data).unwrap();
-		sql_query(r#"
-			update messages
-			set reactions = jsonb_set(reactions, $1::TEXT[], $2, true)
-			where messages.id = $3 and messages.conversation_id = $4; 
-		"#).bind::<diesel::sql_types::Text, _>(format!("{{ {} }}", reaction_key))
-		.bind::<diesel::sql_types::Jsonb, _>(data)
-		.bind::<diesel::sql_types::Uuid, _>(mid)
-		.bind::<diesel::sql_types::Uuid, _>(cid)
-		.execute(conn)
+	) -> QueryResult<(Message, Vec<Attachment>, Option<ThreadSummary>)> {
+		conn.transaction(|| {
+			let message = Self::insert_one(new_message, conn)?;
+			let message_attachments = Attachment::insert_for_message(&message.id, attachment_bodies, conn)?;
+			let thread = match message.thread_root_id {
+				Some(root_id) => {
+					let root = diesel::update(messages.filter(id.eq(root_id)))
+						.set((
+							thread_reply_count.eq(thread_reply_count + 1),
+							thread_last_reply_at.eq(Some(message.created_at))
+						))
+						.get_result::<Message>(conn)?;
+					Some(ThreadSummary {
+						root_id: root.id,
+						reply_count: root.thread_reply_count,
+						last_reply_at: root.thread_last_reply_at
+					})
+				}
+				None => None
+			};
+			Ok((message, message_attachments, thread))
+		})
 	}
 
-	pub fn delete_message(mid: &uuid::Uuid, uid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<usize> {
-		sql_query(r#"
-			update messages
-			set content = null,
-			is_deleted = true
-			where messages.id = $1 and messages.author_id = $2;
-		"#)
-		.bind::<diesel::sql_types::Uuid, _>(mid)
-		.bind::<diesel::sql_types::Uuid, _>(uid)
-		.execute(conn)
+	/// Copies `source` into conversation `target_cid` as a new message by `uid`, attachments included.
+	/// Forwarding a forwarded message keeps pointing at the original rather than the copy.
+	pub fn forward(source: &Message, target_cid: &uuid::Uuid, uid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<(Message, Vec<Attachment>)> {
+		conn.transaction(|| {
+			let (origin_message_id, origin_conversation_id, origin_author_id) = if source.is_forwarded {
+				(source.forwarded_from_message_id, source.forwarded_from_conversation_id, source.forwarded_from_author_id)
+			} else {
+				(Some(source.id), Some(source.conversation_id), Some(source.author_id))
+			};
+			let ast = match source.content.as_deref() {
+				Some(text) => Some(Message::parse_content(text, target_cid, conn)?),
+				None => None
+			};
+			let message = diesel::insert_into(messages)
+				.values((
+					conversation_id.eq(target_cid),
+					author_id.eq(uid),
+					content.eq(&source.content),
+					content_ast.eq(ast),
+					is_forwarded.eq(true),
+					forwarded_from_message_id.eq(origin_message_id),
+					forwarded_from_conversation_id.eq(origin_conversation_id),
+					forwarded_from_author_id.eq(origin_author_id)
+				))
+				.get_result::<Message>(conn)?;
+			let source_attachments = Attachment::fetch_by_message_id(&source.id, conn)?;
+			let message_attachments = Attachment::copy_to_message(&message.id, &source_attachments, conn)?;
+			Ok((message, message_attachments))
+		})
 	}
 
-	pub fn delete_reaction(uid: &uuid::Uuid, cid: &uuid::Uuid, mid: &uuid::Uuid, emoji_name: &String, conn: &PgConnection) -> Result<usize, diesel::result::Error> {
-		let reaction_key = format!("{}:{}", uid.to_hyphenated(), emoji_name);
-		sql_query(r#"
+	/// Recounts a thread root's replies and last reply time from the replies that are not deleted.
+	pub fn refresh_thread_summary(root_id: &uuid::Uuid, conn: &PgConnection) -> QueryResult<ThreadSummary> {
+		let root = sql_query(r#"
 			update messages
-			set reactions = reactions - $1
-			where messages.id = $2 and messages.conversation_id = $3;
+			set thread_reply_count = replies.reply_count,
+			thread_last_reply_at = replies.last_reply_at
+			from (
+				select count(*)::int4 as reply_count, max(created_at) as last_reply_at
+				from messages
+				where thread_root_id = $1 and is_deleted = false
+			) as replies
+			where messages.id = $1
+			returning messages.*;
 		"#)
-		.bind::<diesel::sql_types::Text, _>(reaction_key)
-		.bind::<diesel::sql_types::Uuid, _>(mid)
-		.bind::<diesel::sql_types::Uuid, _>(cid)
-		.execute(conn)
+		.bind::<diesel::sql_types::Uuid, _>(root_id)
+		.get_result::<Message>(conn)?;
+		Ok(ThreadSummary {
+			root_id: root.id,
+			reply_count: root.thread_reply_count,
+			last_reply_at: root.thread_last_reply_at
+		})
+	}
+
+	/// A thread root must be a top-level message of the same conversation.
+	pub fn fetch_thread_root(root_id: &uuid::Uuid, cid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<Message> {
+		messages
+			.filter(id.eq(root_id).and(conversation_id.eq(cid)).and(thread_root_id.is_null()))
+			.get_result::<Message>(conn)
+	}
+
+	pub fn fetch_by_id(mid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<Message> {
+		messages.filter(id.eq(mid)).get_result::<Message>(conn)
+	}
+
+	/// Builds the reply preview for `reply_id`, which must belong to conversation `cid`.
+	pub fn fetch_reply_preview(reply_id: &uuid::Uuid, cid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<ReplyPreview> {
+		let parent = messages.filter(id.eq(reply_id).and(conversation_id.eq(cid))).get_result::<Message>(conn)?;
+		let identities = models::member::Member::fetch_identities(cid, &[parent.author_id], conn)?;
+		let parent_attachments = Attachment::fetch_by_message_id(&parent.id, conn)?;
+		Ok(ReplyPreview::new(&parent, identities.get(&parent.author_id), &parent_attachments))
+	}
+
+	/// Deletes any message in the conversation; callers must check `Permission::DeleteMessages`.
+	pub fn delete_message_in_conversation(mid: &uuid::Uuid, cid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		conn.transaction(|| {
+			let affected_rows = sql_query(r#"
+				update messages
+				set content = null,
+				content_ast = null,
+				is_deleted = true
+				where messages.id = $1 and messages.conversation_id = $2;
+			"#)
+			.bind::<diesel::sql_types::Uuid, _>(mid)
+			.bind::<diesel::sql_types::Uuid, _>(cid)
+			.execute(conn)?;
+			if affected_rows > 0 {
+				Message::cleanup_deleted(mid, conn)?;
+			}
+			Ok(affected_rows)
+		})
+	}
+
+	/// How long after sending a message its author may still edit it.
+	/// Configured with `MESSAGE_EDIT_WINDOW_SECONDS`; `0` means no limit.
+	pub fn edit_window() -> Option<chrono::Duration> {
+		let seconds = env::var("MESSAGE_EDIT_WINDOW_SECONDS")
+			.ok()
+			.and_then(|value| value.parse::<i64>().ok())
+			.unwrap_or(DEFAULT_EDIT_WINDOW_SECONDS);
+		if seconds <= 0 {
+			None
+		} else {
+			Some(chrono::Duration::seconds(seconds))
+		}
+	}
+
+	/// Replaces the content of the author's message, keeping the old content in `message_edits`.
+	/// Forwarded copies can't be edited, so the text shown under their provenance stays the original's.
+	pub fn edit(mid: &uuid::Uuid, cid: &uuid::Uuid, uid: &uuid::Uuid, new_content: &str, conn: &PgConnection) -> Result<Message, EditError> {
+		conn.transaction(|| {
+			let message = messages
+				.filter(id.eq(mid).and(conversation_id.eq(cid)))
+				.for_update()
+				.get_result::<Message>(conn)?;
+			if message.author_id != *uid {
+				return Err(EditError::NotAuthor);
+			}
+			if message.is_deleted || message.is_forwarded {
+				return Err(EditError::NotEditable);
+			}
+			if let Some(window) = Message::edit_window() {
+				if chrono::Utc::now().naive_utc() > message.created_at + window {
+					return Err(EditError::WindowExpired);
+				}
+			}
+			let ast = Message::parse_content(new_content, cid, conn)?;
+			diesel::insert_into(message_edits::table)
+				.values((
+					message_edits::message_id.eq(message.id),
+					message_edits::content.eq(&message.content)
+				))
+				.execute(conn)?;
+			let edited = diesel::update(messages.filter(id.eq(mid)))
+				.set((
+					content.eq(new_content),
+					content_ast.eq(Some(ast)),
+					updated_at.eq(chrono::Utc::now().naive_utc())
+				))
+				.get_result::<Message>(conn)?;
+			Ok(edited)
+		})
+	}
+
+	pub fn fetch_edits(mid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<Vec<MessageEdit>> {
+		message_edits::table
+			.filter(message_edits::message_id.eq(mid))
+			.order_by(message_edits::edited_at.desc())
+			.get_results::<MessageEdit>(conn)
+	}
+
+	/// Removes what hangs off a message that was just deleted and recounts its thread.
+	/// Both delete paths call this inside their transaction.
+	fn cleanup_deleted(mid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<()> {
+		Attachment::delete_by_message_id(mid, conn)?;
+		LinkPreview::delete_by_message_id(mid, conn)?;
+		PinnedMessage::delete_by_message_id(mid, conn)?;
+		let root_id = messages.filter(id.eq(mid)).select(thread_root_id).get_result::<Option<uuid::Uuid>>(conn)?;
+		if let Some(root_id) = root_id {
+			Message::refresh_thread_summary(&root_id, conn)?;
+		}
+		Ok(())
+	}
+
+	pub fn delete_message(mid: &uuid::Uuid, uid: &uuid::Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		conn.transaction(|| {
+			let affected_rows = sql_query(r#"
+				update messages
+				set content = null,
+				content_ast = null,
+				is_deleted = true
+				where messages.id = $1 and messages.author_id = $2;
+			"#)
+			.bind::<diesel::sql_types::Uuid, _>(mid)
+			.bind::<diesel::sql_types::Uuid, _>(uid)
+			.execute(conn)?;
+			if affected_rows > 0 {
+				Message::cleanup_deleted(mid, conn)?;
+			}
+			Ok(affected_rows)
+		})
 	}
 
 	pub fn fetch_by_conversation(
-		uid: &uuid::Uuid, 
+		cid: &uuid::Uuid, 
 		options: &models::pagination::Options, 
+		around: Option<uuid::Uuid>,
 		conn: &PgConnection
 	) -> Result<models::pagination::Result<Vec<Message>>, diesel::result::Error> {
-		let mut query = messages::table.into_boxed();
-		query = query.filter(conversation_id.eq(uid));
+		let make_query = || messages::table.into_boxed()
+			.filter(conversation_id.eq(cid))
+			.filter(thread_root_id.is_null());
+		match around {
+			Some(anchor_id) => Self::paginate_around(make_query, &anchor_id, options, conn),
+			None => Self::paginate(make_query, options, conn)
+		}
+	}
+
+	pub fn fetch_thread(
+		root_id: &uuid::Uuid,
+		options: &models::pagination::Options,
+		around: Option<uuid::Uuid>,
+		conn: &PgConnection
+	) -> Result<models::pagination::Result<Vec<Message>>, diesel::result::Error> {
+		let make_query = || messages::table.into_boxed().filter(thread_root_id.eq(root_id));
+		match around {
+			Some(anchor_id) => Self::paginate_around(make_query, &anchor_id, options, conn),
+			None => Self::paginate(make_query, options, conn)
+		}
+	}
 
-		match options.after {
-			Some(after) => {
-				query = query.filter(created_at.le(after));
+	/// Pages through the messages matched by `make_query`, newest first, keyed on (created_at, id).
+	///
+	/// `before`/`before_id` load older messages and `after`/`after_id` newer ones. The returned
+	/// `next_cursor` points further back in history and `prev_cursor` back towards the present.
+	pub fn paginate<'a>(
+		make_query: impl Fn() -> messages::BoxedQuery<'a, Pg>,
+		options: &models::pagination::Options,
+		conn: &PgConnection
+	) -> Result<models::pagination::Result<Vec<Message>>, diesel::result::Error> {
+		let limit = options.limit_or_default();
+		match (options.before, options.after) {
+			(None, Some(after)) => {
+				let (items, has_newer) = Self::fetch_newer(make_query(), after, options.after_id, limit, conn)?;
+				Ok(Self::page(items, true, has_newer))
 			}
-			None => {}
-		};
-		let limit : usize;
-		match options.limit {
-			Some(_limit) => {
-				if _limit > 50 {
-					limit = 50;
-				} else {
-					limit = _limit;
-				}
-			} 
-			None => {
-				limit = 10;
+			(before, _) => {
+				let cursor = before.map(|at| (at, options.before_id));
+				let (items, has_older) = Self::fetch_older(make_query(), cursor, limit, conn)?;
+				Ok(Self::page(items, has_older, cursor.is_some()))
 			}
 		}
-		let limit_plus_one : i32 = limit as i32 + 1;
-		query = query.limit(limit_plus_one.into());
-		query = query.order_by(created_at.desc());
-		let result = query.get_results::<Message>(conn);
-		match result {
-			Ok(users) => {
-				let mut next_cursor : Option<chrono::NaiveDateTime> = None;
-				let items : Vec<Message>;
-				if users.len() == limit_plus_one as usize {
-					next_cursor = Some(users[limit_plus_one as usize - 1].created_at);
-					items = (&users[0..limit]).to_vec();
-				} else {
-					items = users;
-				}
-				let res : models::pagination::Result<Vec<Message>> = models::pagination::Result {
-					items: items,
-					next_cursor: next_cursor,
-					prev_cursor: None
-				};
-				Ok(res)
-			}
-			Err(err) => Err(err)
+	}
+
+	/// Loads a page centred on `anchor_id`, for jumping to a search result or a replied message.
+	pub fn paginate_around<'a>(
+		make_query: impl Fn() -> messages::BoxedQuery<'a, Pg>,
+		anchor_id: &uuid::Uuid,
+		options: &models::pagination::Options,
+		conn: &PgConnection
+	) -> Result<models::pagination::Result<Vec<Message>>, diesel::result::Error> {
+		let anchor = make_query().filter(id.eq(anchor_id)).first::<Message>(conn)?;
+		let limit = options.limit_or_default().max(1);
+		let newer_limit = (limit - 1) / 2;
+		let older_limit = limit - 1 - newer_limit;
+		let (newer, has_newer) = Self::fetch_newer(make_query(), anchor.created_at, Some(anchor.id), newer_limit, conn)?;
+		let (older, has_older) = Self::fetch_older(make_query(), Some((anchor.created_at, Some(anchor.id))), older_limit, conn)?;
+		let mut items = newer;
+		items.push(anchor);
+		items.extend(older);
+		Ok(Self::page(items, has_older, has_newer))
+	}
+
+	/// Messages strictly older than the cursor, newest first. Without a cursor id, every
+	/// message sharing the cursor's timestamp counts as not older.
+	fn fetch_older(
+		mut query: messages::BoxedQuery<'_, Pg>,
+		cursor: Option<(chrono::NaiveDateTime, Option<uuid::Uuid>)>,
+		limit: usize,
+		conn: &PgConnection
+	) -> QueryResult<(Vec<Message>, bool)> {
+		query = match cursor {
+			Some((at, Some(mid))) => query.filter(created_at.lt(at).or(created_at.eq(at).and(id.lt(mid)))),
+			Some((at, None)) => query.filter(created_at.lt(at)),
+			None => query
+		};
+		let mut rows = query
+			.order_by((created_at.desc(), id.desc()))
+			.limit(limit as i64 + 1)
+			.get_results::<Message>(conn)?;
+		let has_more = rows.len() > limit;
+		rows.truncate(limit);
+		Ok((rows, has_more))
+	}
+
+	/// Messages strictly newer than the cursor, returned newest first like every other page.
+	fn fetch_newer(
+		mut query: messages::BoxedQuery<'_, Pg>,
+		at: chrono::NaiveDateTime,
+		mid: Option<uuid::Uuid>,
+		limit: usize,
+		conn: &PgConnection
+	) -> QueryResult<(Vec<Message>, bool)> {
+		query = match mid {
+			Some(mid) => query.filter(created_at.gt(at).or(created_at.eq(at).and(id.gt(mid)))),
+			None => query.filter(created_at.gt(at))
+		};
+		let mut rows = query
+			.order_by((created_at.asc(), id.asc()))
+			.limit(limit as i64 + 1)
+			.get_results::<Message>(conn)?;
+		let has_more = rows.len() > limit;
+		rows.truncate(limit);
+		rows.reverse();
+		Ok((rows, has_more))
+	}
+
+	fn page(items: Vec<Message>, has_older: bool, has_newer: bool) -> models::pagination::Result<Vec<Message>> {
+		let (next_cursor, next_cursor_id) = match items.last() {
+			Some(oldest) if has_older => (Some(oldest.created_at), Some(oldest.id)),
+			_ => (None, None)
+		};
+		let (prev_cursor, prev_cursor_id) = match items.first() {
+			Some(newest) if has_newer => (Some(newest.created_at), Some(newest.id)),
+			_ => (None, None)
+		};
+		models::pagination::Result {
+			items,
+			next_cursor,
+			prev_cursor,
+			next_cursor_id,
+			prev_cursor_id
 		}
 	}
-}
\ No newline at end of file
+}
@@ -4,3 +4,17 @@ pub mod member;
 pub mod auth;
 pub mod message;
 pub mod pagination;
+pub mod permission;
+pub mod invite;
+pub mod mention;
+pub mod search;
+pub mod attachment;
+pub mod upload;
+pub mod link_preview;
+pub mod markdown;
+pub mod reaction;
+pub mod custom_emoji;
+pub mod forward;
+pub mod pin;
+pub mod scheduled_message;
+pub mod draft;
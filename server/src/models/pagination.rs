@@ -5,6 +5,20 @@ pub struct Options {
 	pub limit: Option<usize>,
 	pub before: Option<chrono::NaiveDateTime>,
 	pub after: Option<chrono::NaiveDateTime>,
+	/// Tie-breaker for `before` when several rows share the same timestamp.
+	pub before_id: Option<uuid::Uuid>,
+	/// Tie-breaker for `after` when several rows share the same timestamp.
+	pub after_id: Option<uuid::Uuid>,
+}
+
+impl Options {
+	pub fn limit_or_default(&self) -> usize {
+		match self.limit {
+			Some(limit) if limit > 50 => 50,
+			Some(limit) => limit,
+			None => 10
+		}
+	}
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,5 +26,9 @@ pub struct Options {
 pub struct Result<T> {
 	pub items: T,
 	pub next_cursor: Option<chrono::NaiveDateTime>,
-	pub prev_cursor: Option<chrono::NaiveDateTime>
-}
\ No newline at end of file
+	pub prev_cursor: Option<chrono::NaiveDateTime>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub next_cursor_id: Option<uuid::Uuid>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub prev_cursor_id: Option<uuid::Uuid>
+}
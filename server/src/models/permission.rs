@@ -0,0 +1,132 @@
+use actix_web::HttpResponse;
+use diesel::{PgConnection};
+use serde::{Deserialize, Serialize};
+use std::env;
+use uuid::{Uuid};
+use crate::lib::ErrorResponse;
+use crate::models::member::Member;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all="lowercase")]
+pub enum Role {
+	Member = 0,
+	Moderator = 1,
+	Admin = 2,
+	Owner = 3
+}
+
+impl Role {
+	pub fn parse(role: &str) -> Option<Role> {
+		match role {
+			"owner" => Some(Role::Owner),
+			"admin" => Some(Role::Admin),
+			"moderator" => Some(Role::Moderator),
+			"member" => Some(Role::Member),
+			_ => None
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Role::Owner => "owner",
+			Role::Admin => "admin",
+			Role::Moderator => "moderator",
+			Role::Member => "member"
+		}
+	}
+
+	pub fn can(&self, permission: Permission) -> bool {
+		*self >= permission.min_role()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+	AddMembers,
+	KickMembers,
+	ViewBans,
+	DeleteMessages,
+	RenameConversation,
+	BanMembers,
+	ManageRoles,
+	ManageInvites,
+	ReviewJoinRequests,
+	ManageEmojis,
+	PinMessages,
+	/// Unpinning messages someone else pinned.
+	ManagePins,
+	TransferOwnership
+}
+
+impl Permission {
+	fn min_role(&self) -> Role {
+		match self {
+			Permission::AddMembers => Role::Member,
+			Permission::KickMembers => Role::Moderator,
+			Permission::ViewBans => Role::Moderator,
+			Permission::DeleteMessages => Role::Moderator,
+			Permission::RenameConversation => Role::Admin,
+			Permission::BanMembers => Role::Admin,
+			Permission::ManageRoles => Role::Admin,
+			Permission::ManageInvites => Role::Admin,
+			Permission::ReviewJoinRequests => Role::Moderator,
+			Permission::ManageEmojis => Role::Admin,
+			Permission::PinMessages => Role::Member,
+			Permission::ManagePins => Role::Moderator,
+			Permission::TransferOwnership => Role::Owner
+		}
+	}
+}
+
+/// Whether the user may manage server-wide settings such as custom emojis.
+/// Server admins are listed by id, comma separated, in `SERVER_ADMIN_IDS`.
+pub fn is_server_admin(uid: &Uuid) -> bool {
+	env::var("SERVER_ADMIN_IDS")
+		.unwrap_or_default()
+		.split(',')
+		.filter_map(|value| Uuid::parse_str(value.trim()).ok())
+		.any(|admin_id| admin_id == *uid)
+}
+
+#[derive(Debug)]
+pub enum PermissionError {
+	NotMember,
+	Forbidden,
+	Database(diesel::result::Error)
+}
+
+impl PermissionError {
+	pub fn error_response(&self) -> HttpResponse {
+		match self {
+			PermissionError::NotMember => HttpResponse::Forbidden().json(ErrorResponse {message: String::from("not a member of this conversation")}),
+			PermissionError::Forbidden => HttpResponse::Forbidden().json(ErrorResponse {message: String::from("missing permission")}),
+			PermissionError::Database(_) => HttpResponse::InternalServerError().finish()
+		}
+	}
+}
+
+impl Member {
+	pub fn role(&self) -> Role {
+		Role::parse(&self.role).unwrap_or(Role::Member)
+	}
+
+	/// Loads the active member and checks that their role grants `permission`.
+	pub fn check_permission(uid: &Uuid, cid: &Uuid, permission: Permission, conn: &PgConnection) -> Result<Member, PermissionError> {
+		match Member::get_member_or_throw(uid, cid, conn) {
+			Ok(member) => {
+				if member.role().can(permission) {
+					Ok(member)
+				} else {
+					Err(PermissionError::Forbidden)
+				}
+			}
+			Err(diesel::result::Error::NotFound) => Err(PermissionError::NotMember),
+			Err(e) => Err(PermissionError::Database(e))
+		}
+	}
+
+	/// An actor may only act on members ranked strictly below them.
+	pub fn outranks(&self, other: &Member) -> bool {
+		self.role() > other.role()
+	}
+}
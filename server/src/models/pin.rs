@@ -0,0 +1,135 @@
+use diesel::prelude::*;
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use std::env;
+use uuid::{Uuid};
+use crate::schema::{conversations, messages, pinned_messages};
+use crate::models;
+use crate::models::member::MemberIdentity;
+use crate::models::message::{Message, MessageView};
+use crate::lib::json_time;
+
+const DEFAULT_LIMIT: i64 = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[serde(rename_all="camelCase")]
+pub struct PinnedMessage {
+	pub message_id: Uuid,
+	pub conversation_id: Uuid,
+	pub pinned_by: Uuid,
+	#[serde(with = "json_time")]
+	pub pinned_at: chrono::NaiveDateTime
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct PinnedMessageView {
+	pub pinned_by: Uuid,
+	pub pinned_by_identity: Option<MemberIdentity>,
+	#[serde(with = "json_time")]
+	pub pinned_at: chrono::NaiveDateTime,
+	pub message: MessageView
+}
+
+#[derive(Debug)]
+pub enum PinError {
+	NotFound,
+	/// The conversation already has `limit()` pinned messages.
+	LimitReached,
+	Database(diesel::result::Error)
+}
+
+impl From<diesel::result::Error> for PinError {
+	fn from(e: diesel::result::Error) -> Self {
+		match e {
+			diesel::result::Error::NotFound => PinError::NotFound,
+			e => PinError::Database(e)
+		}
+	}
+}
+
+/// How many messages a conversation may have pinned at once, from `PINNED_MESSAGE_LIMIT`.
+pub fn limit() -> i64 {
+	env::var("PINNED_MESSAGE_LIMIT")
+		.ok()
+		.and_then(|value| value.parse::<i64>().ok())
+		.unwrap_or(DEFAULT_LIMIT)
+}
+
+impl PinnedMessage {
+	/// Pins a message of conversation `cid`. Returns `None` if it was already pinned.
+	pub fn create(uid: &Uuid, cid: &Uuid, mid: &Uuid, conn: &PgConnection) -> Result<Option<PinnedMessage>, PinError> {
+		conn.transaction(|| {
+			// Locking the conversation serializes concurrent pins, so the limit can't be overshot.
+			conversations::table
+				.filter(conversations::id.eq(cid))
+				.select(conversations::id)
+				.for_update()
+				.get_result::<Uuid>(conn)?;
+			messages::table
+				.filter(messages::id.eq(mid).and(messages::conversation_id.eq(cid)).and(messages::is_deleted.eq(false)))
+				.select(messages::id)
+				.get_result::<Uuid>(conn)?;
+			let existing = pinned_messages::table
+				.filter(pinned_messages::conversation_id.eq(cid))
+				.select(pinned_messages::message_id)
+				.get_results::<Uuid>(conn)?;
+			if existing.contains(mid) {
+				return Ok(None);
+			}
+			if existing.len() as i64 >= limit() {
+				return Err(PinError::LimitReached);
+			}
+			let pin = diesel::insert_into(pinned_messages::table)
+				.values((
+					pinned_messages::message_id.eq(mid),
+					pinned_messages::conversation_id.eq(cid),
+					pinned_messages::pinned_by.eq(uid)
+				))
+				.get_result::<PinnedMessage>(conn)?;
+			Ok(Some(pin))
+		})
+	}
+
+	pub fn find(mid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<PinnedMessage> {
+		pinned_messages::table
+			.filter(pinned_messages::message_id.eq(mid).and(pinned_messages::conversation_id.eq(cid)))
+			.get_result::<PinnedMessage>(conn)
+	}
+
+	pub fn delete(mid: &Uuid, cid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::delete(pinned_messages::table
+			.filter(pinned_messages::message_id.eq(mid).and(pinned_messages::conversation_id.eq(cid))))
+			.execute(conn)
+	}
+
+	/// Deleted messages stop being pinned.
+	pub fn delete_by_message_id(mid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::delete(pinned_messages::table.filter(pinned_messages::message_id.eq(mid))).execute(conn)
+	}
+
+	/// The conversation's pins as seen by `viewer`, most recently pinned first.
+	pub fn fetch_by_conversation(cid: &Uuid, viewer: &Uuid, conn: &PgConnection) -> QueryResult<Vec<PinnedMessageView>> {
+		let rows = pinned_messages::table
+			.inner_join(messages::table)
+			.filter(pinned_messages::conversation_id.eq(cid))
+			.filter(messages::is_deleted.eq(false))
+			.order_by((pinned_messages::pinned_at.desc(), pinned_messages::message_id.desc()))
+			.get_results::<(PinnedMessage, Message)>(conn)?;
+		let mut pinner_ids: Vec<Uuid> = Vec::new();
+		for (pin, _) in rows.iter() {
+			if !pinner_ids.contains(&pin.pinned_by) {
+				pinner_ids.push(pin.pinned_by);
+			}
+		}
+		let identities = models::member::Member::fetch_identities(cid, &pinner_ids, conn)?;
+		let (pins, items): (Vec<PinnedMessage>, Vec<Message>) = rows.into_iter().unzip();
+		let views = MessageView::from_messages(cid, viewer, items, conn)?;
+		Ok(pins.into_iter().zip(views).map(|(pin, message)| PinnedMessageView {
+			pinned_by: pin.pinned_by,
+			pinned_by_identity: identities.get(&pin.pinned_by).cloned(),
+			pinned_at: pin.pinned_at,
+			message
+		}).collect())
+	}
+}
@@ -0,0 +1,217 @@
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use uuid::{Uuid};
+use crate::models;
+use crate::models::member::MemberIdentity;
+use crate::schema::{messages, reactions};
+use crate::lib::json_time;
+
+const DEFAULT_DISTINCT_EMOJI_LIMIT: i64 = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[serde(rename_all="camelCase")]
+pub struct Reaction {
+	pub message_id: Uuid,
+	pub user_id: Uuid,
+	pub emoji: String,
+	#[serde(with = "json_time")]
+	pub created_at: chrono::NaiveDateTime
+}
+
+/// One emoji on a message as shown to a given viewer.
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+#[serde(rename_all="camelCase")]
+pub struct ReactionSummary {
+	#[serde(skip)]
+	#[sql_type = "diesel::sql_types::Uuid"]
+	pub message_id: Uuid,
+	#[sql_type = "diesel::sql_types::Text"]
+	pub emoji: String,
+	#[sql_type = "diesel::sql_types::BigInt"]
+	pub count: i64,
+	/// Whether the viewer is one of the reactors.
+	#[sql_type = "diesel::sql_types::Bool"]
+	pub me: bool
+}
+
+/// How often a user has reacted with an emoji.
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+#[serde(rename_all="camelCase")]
+pub struct EmojiUsage {
+	#[sql_type = "diesel::sql_types::Text"]
+	pub emoji: String,
+	#[sql_type = "diesel::sql_types::BigInt"]
+	pub count: i64
+}
+
+/// Someone who reacted with a given emoji.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct ReactionUser {
+	pub user_id: Uuid,
+	pub identity: Option<MemberIdentity>,
+	#[serde(with = "json_time")]
+	pub created_at: chrono::NaiveDateTime
+}
+
+#[derive(Debug)]
+pub enum ReactionError {
+	NotFound,
+	/// The message already carries `distinct_emoji_limit()` different emojis.
+	LimitReached,
+	Database(diesel::result::Error)
+}
+
+impl From<diesel::result::Error> for ReactionError {
+	fn from(e: diesel::result::Error) -> Self {
+		match e {
+			diesel::result::Error::NotFound => ReactionError::NotFound,
+			e => ReactionError::Database(e)
+		}
+	}
+}
+
+/// How many different emojis a single message may carry, from `MESSAGE_REACTION_LIMIT`.
+pub fn distinct_emoji_limit() -> i64 {
+	env::var("MESSAGE_REACTION_LIMIT")
+		.ok()
+		.and_then(|value| value.parse::<i64>().ok())
+		.unwrap_or(DEFAULT_DISTINCT_EMOJI_LIMIT)
+}
+
+impl Reaction {
+	/// Adds the user's reaction to a message of conversation `cid`. Returns false if it was already there.
+	pub fn create(uid: &Uuid, cid: &Uuid, mid: &Uuid, emoji_name: &str, conn: &PgConnection) -> Result<bool, ReactionError> {
+		conn.transaction(|| {
+			// Locking the message serializes concurrent reactions, so the limit can't be overshot.
+			messages::table
+				.filter(messages::id.eq(mid).and(messages::conversation_id.eq(cid)).and(messages::is_deleted.eq(false)))
+				.select(messages::id)
+				.for_update()
+				.get_result::<Uuid>(conn)?;
+			let emojis = reactions::table
+				.filter(reactions::message_id.eq(mid))
+				.select(reactions::emoji)
+				.distinct()
+				.get_results::<String>(conn)?;
+			if !emojis.iter().any(|existing| existing == emoji_name) && emojis.len() as i64 >= distinct_emoji_limit() {
+				return Err(ReactionError::LimitReached);
+			}
+			let inserted = diesel::insert_into(reactions::table)
+				.values((
+					reactions::message_id.eq(mid),
+					reactions::user_id.eq(uid),
+					reactions::emoji.eq(emoji_name)
+				))
+				.on_conflict_do_nothing()
+				.execute(conn)?;
+			Ok(inserted > 0)
+		})
+	}
+
+	pub fn delete(uid: &Uuid, cid: &Uuid, mid: &Uuid, emoji_name: &str, conn: &PgConnection) -> QueryResult<usize> {
+		let in_conversation = messages::table
+			.filter(messages::conversation_id.eq(cid))
+			.select(messages::id);
+		diesel::delete(reactions::table
+			.filter(reactions::message_id.eq(mid))
+			.filter(reactions::message_id.eq_any(in_conversation))
+			.filter(reactions::user_id.eq(uid))
+			.filter(reactions::emoji.eq(emoji_name)))
+			.execute(conn)
+	}
+
+	pub fn count(mid: &Uuid, emoji_name: &str, conn: &PgConnection) -> QueryResult<i64> {
+		reactions::table
+			.filter(reactions::message_id.eq(mid).and(reactions::emoji.eq(emoji_name)))
+			.count()
+			.get_result::<i64>(conn)
+	}
+
+	/// Reaction counts for several messages as seen by `viewer`, grouped by message.
+	/// Emojis come in the order they were first used on the message.
+	pub fn summaries(mids: &[Uuid], viewer: &Uuid, conn: &PgConnection) -> QueryResult<HashMap<Uuid, Vec<ReactionSummary>>> {
+		let mut grouped: HashMap<Uuid, Vec<ReactionSummary>> = HashMap::new();
+		if mids.is_empty() {
+			return Ok(grouped);
+		}
+		let rows = sql_query(r#"
+			select reactions.message_id, reactions.emoji, count(*) as count,
+			bool_or(reactions.user_id = $2) as me
+			from reactions
+			where reactions.message_id = any($1)
+			group by reactions.message_id, reactions.emoji
+			order by reactions.message_id, min(reactions.created_at), reactions.emoji;
+		"#)
+		.bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(mids)
+		.bind::<diesel::sql_types::Uuid, _>(viewer)
+		.get_results::<ReactionSummary>(conn)?;
+		for row in rows {
+			grouped.entry(row.message_id).or_default().push(row);
+		}
+		Ok(grouped)
+	}
+
+	/// The emojis the user reacts with most, breaking ties by the most recently used.
+	pub fn most_used_emojis(uid: &Uuid, limit: usize, conn: &PgConnection) -> QueryResult<Vec<EmojiUsage>> {
+		sql_query(r#"
+			select reactions.emoji, count(*) as count
+			from reactions
+			where reactions.user_id = $1
+			group by reactions.emoji
+			order by count(*) desc, max(reactions.created_at) desc
+			limit $2;
+		"#)
+		.bind::<diesel::sql_types::Uuid, _>(uid)
+		.bind::<diesel::sql_types::BigInt, _>(limit as i64)
+		.get_results::<EmojiUsage>(conn)
+	}
+
+	/// Who reacted to the message with `emoji_name`, most recent first.
+	pub fn fetch_users(
+		cid: &Uuid,
+		mid: &Uuid,
+		emoji_name: &str,
+		options: &models::pagination::Options,
+		conn: &PgConnection
+	) -> QueryResult<models::pagination::Result<Vec<ReactionUser>>> {
+		let limit = options.limit_or_default();
+		let mut query = reactions::table
+			.into_boxed()
+			.filter(reactions::message_id.eq(mid).and(reactions::emoji.eq(emoji_name)));
+		query = match (options.before, options.before_id) {
+			(Some(at), Some(before_user)) => query.filter(
+				reactions::created_at.lt(at).or(reactions::created_at.eq(at).and(reactions::user_id.lt(before_user)))
+			),
+			(Some(at), None) => query.filter(reactions::created_at.lt(at)),
+			_ => query
+		};
+		let mut rows = query
+			.order_by((reactions::created_at.desc(), reactions::user_id.desc()))
+			.limit(limit as i64 + 1)
+			.get_results::<Reaction>(conn)?;
+		let has_more = rows.len() > limit;
+		rows.truncate(limit);
+		let user_ids: Vec<Uuid> = rows.iter().map(|row| row.user_id).collect();
+		let identities = models::member::Member::fetch_identities(cid, &user_ids, conn)?;
+		let (next_cursor, next_cursor_id) = match rows.last() {
+			Some(oldest) if has_more => (Some(oldest.created_at), Some(oldest.user_id)),
+			_ => (None, None)
+		};
+		Ok(models::pagination::Result {
+			items: rows.into_iter().map(|row| ReactionUser {
+				user_id: row.user_id,
+				identity: identities.get(&row.user_id).cloned(),
+				created_at: row.created_at
+			}).collect(),
+			next_cursor,
+			prev_cursor: None,
+			next_cursor_id,
+			prev_cursor_id: None
+		})
+	}
+}
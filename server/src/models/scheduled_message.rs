@@ -0,0 +1,251 @@
+use diesel::prelude::*;
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use std::env;
+use uuid::{Uuid};
+use crate::schema::scheduled_messages;
+use crate::lib::{json_option_time, json_time, ErrorField};
+use crate::models::attachment::{Attachment, AttachmentBody};
+use crate::models::member::Member;
+use crate::models::message::{Message, NewMessage};
+use crate::models::upload::Upload;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_DAYS_AHEAD: i64 = 365;
+const MAX_DELIVERY_ATTEMPTS: i32 = 5;
+const RETRY_DELAY_SECONDS: i64 = 30;
+
+/// A message waiting in the database until `send_at`, when the scheduler delivers it.
+/// `attachments` holds the `AttachmentBody`s as sent by the client; uploads are resolved
+/// again on delivery.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+#[serde(rename_all="camelCase")]
+pub struct ScheduledMessage {
+	pub id: Uuid,
+	pub conversation_id: Uuid,
+	pub author_id: Uuid,
+	pub content: Option<String>,
+	pub attachments: serde_json::Value,
+	#[serde(with = "json_time")]
+	pub send_at: chrono::NaiveDateTime,
+	#[serde(with = "json_time")]
+	pub created_at: chrono::NaiveDateTime,
+	#[serde(with = "json_option_time")]
+	pub updated_at: Option<chrono::NaiveDateTime>,
+	/// How many deliveries failed so far; each failure pushes `send_at` back a little more.
+	pub failed_attempts: i32
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleMessageBody {
+	pub conversation_id: Uuid,
+	pub content: Option<String>,
+	#[serde(default)]
+	pub attachments: Vec<AttachmentBody>,
+	#[serde(with = "json_time")]
+	pub send_at: chrono::NaiveDateTime
+}
+
+/// Fields left out stay as they are; an empty `content` removes the text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditScheduledMessageBody {
+	pub content: Option<String>,
+	#[serde(default, with = "json_option_time")]
+	pub send_at: Option<chrono::NaiveDateTime>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledMessageListQuery {
+	pub conversation_id: Option<Uuid>
+}
+
+/// A scheduled message that went out, with the author's membership for resolving their identity.
+pub struct DeliveredMessage {
+	pub scheduled_message_id: Uuid,
+	pub message: Message,
+	pub attachments: Vec<Attachment>,
+	pub member: Member
+}
+
+/// What became of a due scheduled message.
+pub enum Delivery {
+	Delivered(Box<DeliveredMessage>),
+	/// The author is no longer in the conversation, or an attached upload is gone.
+	Dropped(ScheduledMessage),
+	/// Delivering failed. The message is retried later unless it ran out of attempts.
+	Failed {
+		scheduled: ScheduledMessage,
+		error: diesel::result::Error,
+		gave_up: bool
+	}
+}
+
+/// How many pending scheduled messages a user may have, from `SCHEDULED_MESSAGE_LIMIT`.
+pub fn limit() -> i64 {
+	env::var("SCHEDULED_MESSAGE_LIMIT")
+		.ok()
+		.and_then(|value| value.parse::<i64>().ok())
+		.unwrap_or(DEFAULT_LIMIT)
+}
+
+/// Blank text counts as no text.
+pub fn normalize_content(text: Option<&str>) -> Option<String> {
+	text.filter(|text| !text.trim().is_empty()).map(String::from)
+}
+
+/// Checks what a scheduled message would be sent with.
+pub fn validate(text: Option<&str>, has_attachments: bool, at: &chrono::NaiveDateTime) -> Vec<ErrorField> {
+	let mut errors: Vec<ErrorField> = Vec::new();
+	if text.is_none() && !has_attachments {
+		errors.push(ErrorField {path: String::from("content"), messages: vec![String::from("a message needs text or an attachment")]});
+	}
+	let now = chrono::Utc::now().naive_utc();
+	if *at <= now {
+		errors.push(ErrorField {path: String::from("send_at"), messages: vec![String::from("send_at must be in the future")]});
+	} else if *at > now + chrono::Duration::days(MAX_DAYS_AHEAD) {
+		errors.push(ErrorField {path: String::from("send_at"), messages: vec![format!("messages can be scheduled at most {} days ahead", MAX_DAYS_AHEAD)]});
+	}
+	errors
+}
+
+impl ScheduledMessage {
+	pub fn insert_one(uid: &Uuid, body: &ScheduleMessageBody, conn: &PgConnection) -> QueryResult<ScheduledMessage> {
+		diesel::insert_into(scheduled_messages::table)
+			.values((
+				scheduled_messages::conversation_id.eq(body.conversation_id),
+				scheduled_messages::author_id.eq(uid),
+				scheduled_messages::content.eq(normalize_content(body.content.as_deref())),
+				scheduled_messages::attachments.eq(serde_json::json!(body.attachments)),
+				scheduled_messages::send_at.eq(body.send_at)
+			))
+			.get_result::<ScheduledMessage>(conn)
+	}
+
+	pub fn count_pending(uid: &Uuid, conn: &PgConnection) -> QueryResult<i64> {
+		scheduled_messages::table
+			.filter(scheduled_messages::author_id.eq(uid))
+			.count()
+			.get_result::<i64>(conn)
+	}
+
+	/// The user's pending messages, optionally for one conversation, soonest first.
+	pub fn fetch_pending(uid: &Uuid, cid: Option<&Uuid>, conn: &PgConnection) -> QueryResult<Vec<ScheduledMessage>> {
+		let mut query = scheduled_messages::table
+			.into_boxed()
+			.filter(scheduled_messages::author_id.eq(uid));
+		if let Some(cid) = cid {
+			query = query.filter(scheduled_messages::conversation_id.eq(cid));
+		}
+		query
+			.order_by((scheduled_messages::send_at.asc(), scheduled_messages::id.asc()))
+			.get_results::<ScheduledMessage>(conn)
+	}
+
+	pub fn find_pending(sid: &Uuid, uid: &Uuid, conn: &PgConnection) -> QueryResult<ScheduledMessage> {
+		scheduled_messages::table
+			.filter(scheduled_messages::id.eq(sid).and(scheduled_messages::author_id.eq(uid)))
+			.get_result::<ScheduledMessage>(conn)
+	}
+
+	/// Fails with `NotFound` if the message was delivered or cancelled in the meantime.
+	pub fn update(
+		sid: &Uuid,
+		uid: &Uuid,
+		new_content: Option<String>,
+		new_send_at: &chrono::NaiveDateTime,
+		conn: &PgConnection
+	) -> QueryResult<ScheduledMessage> {
+		diesel::update(scheduled_messages::table
+			.filter(scheduled_messages::id.eq(sid).and(scheduled_messages::author_id.eq(uid))))
+			.set((
+				scheduled_messages::content.eq(new_content),
+				scheduled_messages::send_at.eq(new_send_at),
+				scheduled_messages::updated_at.eq(chrono::Utc::now().naive_utc())
+			))
+			.get_result::<ScheduledMessage>(conn)
+	}
+
+	pub fn delete_one(sid: &Uuid, uid: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::delete(scheduled_messages::table
+			.filter(scheduled_messages::id.eq(sid).and(scheduled_messages::author_id.eq(uid))))
+			.execute(conn)
+	}
+
+	/// Turns the oldest due scheduled message into a real message; `None` when nothing is due.
+	/// The row is locked, and deleted in the same transaction as the insert, so each message
+	/// goes out once even with several server instances, and one interrupted by a crash or
+	/// restart is still there for the next run. A message that fails is postponed, so it can't
+	/// hold up the ones behind it, and dropped after `MAX_DELIVERY_ATTEMPTS`.
+	pub fn deliver_next(conn: &PgConnection) -> QueryResult<Option<Delivery>> {
+		let mut failed: Option<(ScheduledMessage, diesel::result::Error)> = None;
+		let result = conn.transaction(|| {
+			let scheduled = match scheduled_messages::table
+				.filter(scheduled_messages::send_at.le(chrono::Utc::now().naive_utc()))
+				.order_by(scheduled_messages::send_at.asc())
+				.for_update()
+				.skip_locked()
+				.first::<ScheduledMessage>(conn)
+				.optional()?
+			{
+				Some(scheduled) => scheduled,
+				None => return Ok(None)
+			};
+			diesel::delete(scheduled_messages::table.filter(scheduled_messages::id.eq(scheduled.id))).execute(conn)?;
+			match ScheduledMessage::deliver(&scheduled, conn) {
+				Ok(delivery) => Ok(Some(delivery)),
+				Err(e) => {
+					failed = Some((scheduled, e));
+					Err(diesel::result::Error::RollbackTransaction)
+				}
+			}
+		});
+		match (result, failed) {
+			(_, Some((scheduled, error))) => ScheduledMessage::record_failure(scheduled, error, conn).map(Some),
+			(result, None) => result
+		}
+	}
+
+	fn deliver(scheduled: &ScheduledMessage, conn: &PgConnection) -> QueryResult<Delivery> {
+		let member = match Member::get_member_or_throw(&scheduled.author_id, &scheduled.conversation_id, conn).optional()? {
+			Some(member) => member,
+			None => return Ok(Delivery::Dropped(scheduled.clone()))
+		};
+		let mut attachment_bodies: Vec<AttachmentBody> = serde_json::from_value(scheduled.attachments.clone()).unwrap_or_default();
+		if !Upload::fill_attachment_bodies(&scheduled.author_id, &mut attachment_bodies, conn)?.is_empty() {
+			return Ok(Delivery::Dropped(scheduled.clone()));
+		}
+		let new_message = NewMessage::new(NewMessage {
+			conversation_id: scheduled.conversation_id,
+			author_id: scheduled.author_id,
+			content: scheduled.content.to_owned(),
+			reply_to_id: None,
+			thread_root_id: None
+		});
+		let (message, message_attachments, _) = Message::create(&new_message, &attachment_bodies, conn)?;
+		Ok(Delivery::Delivered(Box::new(DeliveredMessage {
+			scheduled_message_id: scheduled.id,
+			message,
+			attachments: message_attachments,
+			member
+		})))
+	}
+
+	/// Postpones a message whose delivery failed, or drops it once it has failed too often.
+	fn record_failure(scheduled: ScheduledMessage, error: diesel::result::Error, conn: &PgConnection) -> QueryResult<Delivery> {
+		let attempts = scheduled.failed_attempts + 1;
+		let gave_up = attempts >= MAX_DELIVERY_ATTEMPTS;
+		let target = scheduled_messages::table.filter(scheduled_messages::id.eq(scheduled.id));
+		if gave_up {
+			diesel::delete(target).execute(conn)?;
+		} else {
+			let delay = chrono::Duration::seconds(RETRY_DELAY_SECONDS << attempts);
+			diesel::update(target)
+				.set((
+					scheduled_messages::failed_attempts.eq(attempts),
+					scheduled_messages::send_at.eq(chrono::Utc::now().naive_utc() + delay)
+				))
+				.execute(conn)?;
+		}
+		Ok(Delivery::Failed { scheduled, error, gave_up })
+	}
+}
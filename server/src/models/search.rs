@@ -0,0 +1,147 @@
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Bool, Float4, Nullable, Text, Timestamptz};
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use uuid::{Uuid};
+use crate::models::message::{Message, MessageView};
+use crate::models::pagination;
+
+/// The text search configuration used by both the index and the queries; the two must match
+/// for Postgres to use `messages_content_search_index`.
+const SEARCH_DOCUMENT_SQL: &str = "to_tsvector('simple', coalesce(msg.content, ''))";
+
+/// The hit's content HTML-escaped, so the `<mark>` tags `ts_headline` adds are the only markup
+/// in `highlight`.
+const ESCAPED_CONTENT_SQL: &str = "replace(replace(replace(replace(replace(coalesce(hits.content, ''), \
+	'&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '\"', '&quot;'), '''', '&#39;')";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageSearchQuery {
+	pub q: String,
+	pub conversation_id: Option<Uuid>,
+	pub author_id: Option<Uuid>,
+	pub from: Option<chrono::NaiveDateTime>,
+	pub to: Option<chrono::NaiveDateTime>,
+	pub has_image: Option<bool>,
+	/// The `nextCursorRank` of the previous page; required along with `before` and `before_id`.
+	pub before_rank: Option<f32>
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+struct SearchRow {
+	#[diesel(embed)]
+	message: Message,
+	#[sql_type = "Float4"]
+	rank: f32,
+	#[sql_type = "Text"]
+	highlight: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct SearchHit {
+	#[serde(flatten)]
+	pub message: MessageView,
+	pub rank: f32,
+	/// The matching fragments of the content, with matches wrapped in `<mark>` tags.
+	pub highlight: String
+}
+
+/// A page of hits. The cursor carries the last hit's rank too, so paging carries on
+/// even if that message is edited or deleted in the meantime.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct SearchPage {
+	#[serde(flatten)]
+	pub page: pagination::Result<Vec<SearchHit>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub next_cursor_rank: Option<f32>
+}
+
+impl MessageSearchQuery {
+	/// Whether a page cursor is either complete or absent.
+	pub fn has_valid_cursor(&self, options: &pagination::Options) -> bool {
+		options.before_id.is_none() || (options.before.is_some() && self.before_rank.is_some())
+	}
+
+
+	/// Searches the caller's conversations, best matches first.
+	///
+	/// Results are ordered by (rank, created_at, id); the cursor is the last hit's
+	/// `before_rank`, `before` and `before_id`.
+	pub fn search(
+		&self,
+		uid: &Uuid,
+		options: &pagination::Options,
+		conn: &PgConnection
+	) -> QueryResult<SearchPage> {
+		let limit = options.limit_or_default();
+		let rows = sql_query(format!(r#"
+			with search as (
+				select websearch_to_tsquery('simple', $2) as query
+			),
+			hits as (
+				select msg.*, ts_rank({document}, search.query) as rank
+				from messages msg
+				cross join search
+				inner join members m on m.conversation_id = msg.conversation_id
+				where m.user_id = $1 and m.is_kicked = false and m.is_banned = false
+				and {document} @@ search.query
+				and msg.is_deleted = false
+				and ($3::uuid is null or msg.conversation_id = $3)
+				and ($4::uuid is null or msg.author_id = $4)
+				and ($5::timestamptz is null or msg.created_at >= $5)
+				and ($6::timestamptz is null or msg.created_at < $6)
+				and ($7::bool is null or exists (
+					select 1 from attachments a
+					where a.message_id = msg.id and a.kind in ('image', 'gif')
+				) = $7)
+			)
+			select hits.*, ts_headline(
+				'simple', {escaped_content}, search.query,
+				'StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MaxWords=20, MinWords=5'
+			) as highlight
+			from hits
+			cross join search
+			where $8::uuid is null
+			or (hits.rank, hits.created_at, hits.id) < ($10::real, $11::timestamptz, $8::uuid)
+			order by hits.rank desc, hits.created_at desc, hits.id desc
+			limit $9;
+		"#, document = SEARCH_DOCUMENT_SQL, escaped_content = ESCAPED_CONTENT_SQL))
+		.bind::<diesel::sql_types::Uuid, _>(uid)
+		.bind::<Text, _>(self.q.trim())
+		.bind::<Nullable<diesel::sql_types::Uuid>, _>(self.conversation_id)
+		.bind::<Nullable<diesel::sql_types::Uuid>, _>(self.author_id)
+		.bind::<Nullable<Timestamptz>, _>(self.from)
+		.bind::<Nullable<Timestamptz>, _>(self.to)
+		.bind::<Nullable<Bool>, _>(self.has_image)
+		.bind::<Nullable<diesel::sql_types::Uuid>, _>(options.before_id)
+		.bind::<BigInt, _>(limit as i64 + 1)
+		.bind::<Nullable<Float4>, _>(self.before_rank)
+		.bind::<Nullable<Timestamptz>, _>(options.before)
+		.get_results::<SearchRow>(conn)?;
+
+		let has_more = rows.len() > limit;
+		let rows: Vec<SearchRow> = rows.into_iter().take(limit).collect();
+		let (next_cursor, next_cursor_id, next_cursor_rank) = match rows.last() {
+			Some(last) if has_more => (Some(last.message.created_at), Some(last.message.id), Some(last.rank)),
+			_ => (None, None, None)
+		};
+		let scores: Vec<(f32, String)> = rows.iter().map(|row| (row.rank, row.highlight.to_owned())).collect();
+		let views = MessageView::from_mixed_messages(uid, rows.into_iter().map(|row| row.message).collect(), conn)?;
+		let items = views.into_iter().zip(scores).map(|(message, (rank, highlight))| {
+			SearchHit { message, rank, highlight }
+		}).collect();
+		Ok(SearchPage {
+			page: pagination::Result {
+				items,
+				next_cursor,
+				prev_cursor: None,
+				next_cursor_id,
+				prev_cursor_id: None
+			},
+			next_cursor_rank
+		})
+	}
+}
@@ -0,0 +1,277 @@
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Integer, Jsonb, Nullable, Text};
+use diesel::{PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
+use std::env;
+use uuid::{Uuid};
+use crate::schema::uploads::{self, dsl::*};
+use crate::lib::{json_time, ErrorField};
+use crate::models::attachment::{AttachmentBody, AttachmentThumbnail};
+use crate::storage::StoredObject;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all="lowercase")]
+pub enum UploadPurpose {
+	#[default]
+	Attachment,
+	Avatar,
+	Emoji
+}
+
+impl UploadPurpose {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			UploadPurpose::Attachment => "attachment",
+			UploadPurpose::Avatar => "avatar",
+			UploadPurpose::Emoji => "emoji"
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, QueryableByName)]
+#[table_name="uploads"]
+#[serde(rename_all="camelCase")]
+pub struct Upload {
+	pub id: Uuid,
+	pub user_id: Uuid,
+	#[serde(skip_serializing)]
+	pub backend: String,
+	#[serde(skip_serializing)]
+	pub storage_key: String,
+	#[serde(skip_serializing)]
+	pub url: Option<String>,
+	pub mime_type: String,
+	pub size: i64,
+	pub filename: Option<String>,
+	pub purpose: String,
+	#[serde(with = "json_time")]
+	pub created_at: chrono::NaiveDateTime,
+	pub width: Option<i32>,
+	pub height: Option<i32>,
+	pub blurhash: Option<String>,
+	#[serde(skip_serializing)]
+	pub thumbnails: serde_json::Value,
+	/// Set once a custom emoji uses the upload. It stays public after the emoji is deleted,
+	/// so messages that used the emoji keep showing it.
+	#[serde(skip_serializing)]
+	pub used_as_emoji: bool
+}
+
+/// A thumbnail generated for an image upload, kept in the upload's `thumbnails` column.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct StoredThumbnail {
+	/// The longest side it was scaled down to; also its key in the thumbnail URL.
+	pub size: i32,
+	pub width: i32,
+	pub height: i32,
+	pub storage_key: String,
+	pub url: Option<String>,
+	pub mime_type: String
+}
+
+impl StoredThumbnail {
+	pub fn stored_object(&self) -> StoredObject {
+		StoredObject { key: self.storage_key.to_owned(), url: self.url.to_owned() }
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct NewUpload {
+	pub user_id: Uuid,
+	pub backend: String,
+	pub storage_key: String,
+	pub url: Option<String>,
+	pub mime_type: String,
+	pub size: i64,
+	pub filename: Option<String>,
+	pub purpose: UploadPurpose,
+	pub width: Option<i32>,
+	pub height: Option<i32>,
+	pub blurhash: Option<String>,
+	pub thumbnails: Vec<StoredThumbnail>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadQuery {
+	pub filename: Option<String>,
+	#[serde(default)]
+	pub purpose: UploadPurpose
+}
+
+/// An upload as returned to its owner, with the URL clients should reference it by.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct UploadView {
+	#[serde(flatten)]
+	pub upload: Upload,
+	pub media_url: String,
+	pub thumbnails: Vec<AttachmentThumbnail>
+}
+
+#[derive(QueryableByName)]
+struct UsedBytes {
+	#[sql_type = "BigInt"]
+	used: i64
+}
+
+impl Upload {
+	/// Where clients fetch the upload from; always goes through the authenticated download route.
+	pub fn media_url(&self) -> String {
+		Upload::media_url_for(&self.id)
+	}
+
+	pub fn media_url_for(upload_id: &Uuid) -> String {
+		let base_url = env::var("BASE_URL").expect("`BASE_URL` is not set in .env file");
+		format!("{}/media/{}", base_url.trim_end_matches('/'), upload_id)
+	}
+
+	pub fn stored_thumbnails(&self) -> Vec<StoredThumbnail> {
+		serde_json::from_value(self.thumbnails.clone()).unwrap_or_default()
+	}
+
+	/// Thumbnails as clients reference them, smallest first.
+	pub fn attachment_thumbnails(&self) -> Vec<AttachmentThumbnail> {
+		let media_url = self.media_url();
+		self.stored_thumbnails().into_iter().map(|thumbnail| AttachmentThumbnail {
+			url: format!("{}/thumbnails/{}", media_url, thumbnail.size),
+			width: thumbnail.width,
+			height: thumbnail.height
+		}).collect()
+	}
+
+	pub fn view(self) -> UploadView {
+		let media_url = self.media_url();
+		let attachment_thumbnails = self.attachment_thumbnails();
+		UploadView { upload: self, media_url, thumbnails: attachment_thumbnails }
+	}
+
+	pub fn stored_object(&self) -> StoredObject {
+		StoredObject { key: self.storage_key.to_owned(), url: self.url.to_owned() }
+	}
+
+	pub fn used_bytes(uid: &Uuid, conn: &PgConnection) -> QueryResult<i64> {
+		let row = sql_query("select coalesce(sum(size), 0)::bigint as used from uploads where user_id = $1")
+			.bind::<diesel::sql_types::Uuid, _>(uid)
+			.get_result::<UsedBytes>(conn)?;
+		Ok(row.used)
+	}
+
+	/// Records the upload unless it would take the user over `quota`. Returns `None` when it would.
+	/// The user's row is locked first, so concurrent uploads are summed one after another.
+	pub fn insert_within_quota(new_upload: &NewUpload, quota: Option<i64>, conn: &PgConnection) -> QueryResult<Option<Upload>> {
+		use crate::schema::users;
+		conn.transaction(|| {
+			users::table
+				.filter(users::id.eq(new_upload.user_id))
+				.select(users::id)
+				.for_update()
+				.get_result::<Uuid>(conn)?;
+			let inserted = sql_query(r#"
+				insert into uploads (user_id, backend, storage_key, url, mime_type, size, filename, purpose, width, height, blurhash, thumbnails)
+				select $1, $2, $3, $4, $5, $6, $7, $8, $10, $11, $12, $13
+				where $9::bigint is null
+				or (select coalesce(sum(size), 0) from uploads where user_id = $1) + $6 <= $9
+				returning *;
+			"#)
+			.bind::<diesel::sql_types::Uuid, _>(new_upload.user_id)
+			.bind::<Text, _>(&new_upload.backend)
+			.bind::<Text, _>(&new_upload.storage_key)
+			.bind::<Nullable<Text>, _>(&new_upload.url)
+			.bind::<Text, _>(&new_upload.mime_type)
+			.bind::<BigInt, _>(new_upload.size)
+			.bind::<Nullable<Text>, _>(&new_upload.filename)
+			.bind::<Text, _>(new_upload.purpose.as_str())
+			.bind::<Nullable<BigInt>, _>(quota)
+			.bind::<Nullable<Integer>, _>(new_upload.width)
+			.bind::<Nullable<Integer>, _>(new_upload.height)
+			.bind::<Nullable<Text>, _>(&new_upload.blurhash)
+			.bind::<Jsonb, _>(serde_json::json!(new_upload.thumbnails))
+			.get_results::<Upload>(conn)?;
+			Ok(inserted.into_iter().next())
+		})
+	}
+
+	pub fn fetch_by_id(upload_id: &Uuid, conn: &PgConnection) -> QueryResult<Upload> {
+		uploads.filter(id.eq(upload_id)).get_result::<Upload>(conn)
+	}
+
+	/// Owners can always see their uploads. Ones the owner uses as their avatar, or that have backed a
+	/// custom emoji, are visible to everyone signed in. Anything else needs a message attaching it in a
+	/// conversation the user is still in.
+	pub fn can_view(&self, uid: &Uuid, conn: &PgConnection) -> QueryResult<bool> {
+		if self.user_id == *uid || self.used_as_emoji || self.is_avatar(conn)? {
+			return Ok(true);
+		}
+		use crate::schema::{attachments, members, messages};
+		let visible = attachments::table
+			.inner_join(messages::table.on(messages::id.eq(attachments::message_id)))
+			.inner_join(members::table.on(members::conversation_id.eq(messages::conversation_id)))
+			.filter(attachments::upload_id.eq(self.id))
+			.filter(members::user_id.eq(uid).and(members::is_kicked.eq(false)).and(members::is_banned.eq(false)))
+			.select(attachments::id)
+			.first::<Uuid>(conn)
+			.optional()?;
+		Ok(visible.is_some())
+	}
+
+	/// Whether the uploader has it as their avatar, either on their account or in a conversation.
+	fn is_avatar(&self, conn: &PgConnection) -> QueryResult<bool> {
+		use crate::schema::{members, users};
+		let upload_url = self.media_url();
+		let account_avatars = users::table
+			.filter(users::id.eq(self.user_id).and(users::avatar_url.eq(&upload_url)))
+			.count()
+			.get_result::<i64>(conn)?;
+		if account_avatars > 0 {
+			return Ok(true);
+		}
+		let member_avatars = members::table
+			.filter(members::user_id.eq(self.user_id).and(members::avatar.eq(&upload_url)))
+			.count()
+			.get_result::<i64>(conn)?;
+		Ok(member_avatars > 0)
+	}
+
+	pub fn mark_used_as_emoji(upload_id: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::update(uploads.filter(id.eq(upload_id))).set(used_as_emoji.eq(true)).execute(conn)
+	}
+
+	pub fn delete_one(upload_id: &Uuid, conn: &PgConnection) -> QueryResult<usize> {
+		diesel::delete(uploads.filter(id.eq(upload_id))).execute(conn)
+	}
+
+	/// Completes attachment bodies that reference an upload from the upload itself.
+	/// Only the uploader may attach an upload; anything else is reported as a field error.
+	pub fn fill_attachment_bodies(uid: &Uuid, bodies: &mut [AttachmentBody], conn: &PgConnection) -> QueryResult<Vec<ErrorField>> {
+		let mut errors: Vec<ErrorField> = Vec::new();
+		for (index, body) in bodies.iter_mut().enumerate() {
+			let upload_id = match body.upload_id {
+				Some(upload_id) => upload_id,
+				None => continue
+			};
+			match uploads.filter(id.eq(upload_id).and(user_id.eq(uid))).first::<Upload>(conn).optional()? {
+				Some(upload) => {
+					body.url = upload.media_url();
+					body.mime_type = Some(upload.mime_type.to_owned());
+					body.size = Some(upload.size);
+					if upload.width.is_some() {
+						body.width = upload.width;
+						body.height = upload.height;
+					}
+					body.blurhash = upload.blurhash.to_owned();
+					body.thumbnails = upload.attachment_thumbnails();
+					if body.filename.is_none() {
+						body.filename = upload.filename.to_owned();
+					}
+				}
+				None => errors.push(ErrorField {
+					path: format!("attachments[{}].upload_id", index),
+					messages: vec![String::from("upload does not exist")]
+				})
+			}
+		}
+		Ok(errors)
+	}
+}
@@ -80,6 +80,7 @@ impl User {
 		let user_rows = sql_query(r#"
 			select u.* from users u
 			inner join members m on m.user_id = u.id and m.conversation_id = $1
+			where m.is_kicked = false and m.is_banned = false
 		"#)
 		.bind::<diesel::sql_types::Uuid, _>(cid)
 		.get_results::<User>(conn);
@@ -93,7 +94,8 @@ impl User {
 	pub fn fetch_user_ids_by_conversation(conversation_id: &uuid::Uuid, user_id: &uuid::Uuid, conn: &PgConnection) -> QueryResult<Vec<uuid::Uuid>> {
 		let user_id_rows = sql_query(r#"
 			select m.user_id as "id" from members m
-			where m.user_id != $1 and m.conversation_id = $2;
+			where m.user_id != $1 and m.conversation_id = $2
+			and m.is_kicked = false and m.is_banned = false;
 		"#)
 		.bind::<diesel::sql_types::Uuid, _>(user_id)
 		.bind::<diesel::sql_types::Uuid, _>(conversation_id)
@@ -1,8 +1,21 @@
 use actix_web::{
-   get, web, HttpRequest, HttpResponse
+   get, post, put, patch, delete, web, HttpRequest, HttpResponse
 };
-use crate::lib::{DbPool};
+use actix::*;
+use crate::lib::{DbPool, ErrorField};
 use crate::models;
+use crate::models::permission::{Permission, PermissionError, Role};
+use crate::ws_server;
+
+fn validate_group_name(name: &str) -> Vec<ErrorField> {
+    let mut errors: Vec<ErrorField> = Vec::new();
+    if name.trim().is_empty() {
+        errors.push(ErrorField {path: String::from("name"), messages: vec![String::from("name cannot be blank")]});
+    } else if name.len() > 255 {
+        errors.push(ErrorField {path: String::from("name"), messages: vec![String::from("name length must be less than 255")]});
+    }
+    errors
+}
 
 #[get("/conversation/{conversation_id}")]
 pub async fn get_conversation_by_id(
@@ -56,11 +69,16 @@ pub async fn get_conversation_by_recipient(_: models::auth::Auth,
 #[get("/conversation/list")]
 pub async fn get_conversations_for_user(_: models::auth::Auth, 
     req: HttpRequest, 
+    query: web::Query<models::pagination::Options>,
+    list_query: web::Query<models::conversation::ConversationListQuery>,
     pool: web::Data<DbPool>
 ) -> HttpResponse {
     let conn = pool.get().unwrap();
     let user_id = models::user::User::get_id_from_req(&req);
-    let conversations = models::conversation::Conversation::fetch_by_user_id(&user_id.unwrap(), &conn);
+    let options = query.into_inner();
+    let conversations = models::conversation::Conversation::fetch_page_by_user_id(
+        &user_id.unwrap(), &options, list_query.filter, &conn
+    );
     match conversations {
         Ok(conversations) => {
             HttpResponse::Ok().json(conversations)
@@ -81,12 +99,16 @@ pub async fn get_conversation_members(
     let conn = pool.get().unwrap();
     let conversation_id = path.into_inner();
     let user_id = models::user::User::get_id_from_req(&req);
-    let members = models::member::Member::fetch_by_conversation(&conversation_id, &conn);
+    let members = models::member::Member::fetch_with_users_by_conversation(&conversation_id, &conn);
     let member = models::member::Member::get_member_or_throw(&user_id.unwrap(), &conversation_id, &conn);
     match member {
         Ok(_) => {
             match members {
                 Ok(members) => {
+                    let members: Vec<models::member::MemberWithIdentity> = members.into_iter().map(|(member, user)| {
+                        let identity = models::member::MemberIdentity::resolve(&user, Some(&member));
+                        models::member::MemberWithIdentity { member, identity }
+                    }).collect();
                     HttpResponse::Ok().json(members)
                 }
                 Err(_) => {
@@ -108,13 +130,17 @@ pub async fn get_conversation_users(_: models::auth::Auth,
 ) -> HttpResponse {
     let conn = pool.get().unwrap();
     let conversation_id = path.into_inner();
-    let users = models::user::User::fetch_users_by_conversation(&conversation_id, &conn);
+    let users = models::member::Member::fetch_with_users_by_conversation(&conversation_id, &conn);
     let user_id = models::user::User::get_id_from_req(&req);
     let member = models::member::Member::get_member_or_throw(&user_id.unwrap(), &conversation_id, &conn);
     match member {
         Ok(_) => {
             match users {
                 Ok(users) => {
+                    let users: Vec<models::member::UserWithIdentity> = users.into_iter().map(|(member, user)| {
+                        let identity = models::member::MemberIdentity::resolve(&user, Some(&member));
+                        models::member::UserWithIdentity { user, identity }
+                    }).collect();
                     HttpResponse::Ok().json(users)
                 }
                 Err(_) => {
@@ -126,4 +152,561 @@ pub async fn get_conversation_users(_: models::auth::Auth,
             HttpResponse::InternalServerError().finish()
         }
     }
-}
\ No newline at end of file
+}
+
+#[post("/conversation/group")]
+pub async fn create_group(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    input: web::Json<models::conversation::CreateGroupBody>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let input = input.into_inner();
+    let group_name = input.name.trim().to_string();
+
+    let mut errors = validate_group_name(&group_name);
+    let users = models::user::User::fetch_by_ids(&input.member_ids, &conn);
+    match users {
+        Ok(users) => {
+            if input.member_ids.iter().any(|mid| !users.iter().any(|u| u.id == *mid)) {
+                errors.push(ErrorField {path: String::from("memberIds"), messages: vec![String::from("some members do not exist")]});
+            }
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors);
+    }
+
+    match models::conversation::Conversation::create_group(&group_name, &user_id, &input.member_ids, &conn) {
+        Ok(conversation) => {
+            ws_server.into_inner().send(ws_server::ConversationCreated {
+                conversation: conversation.clone(),
+                created_by: user_id
+            }).await.unwrap();
+            HttpResponse::Created().json(conversation)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[patch("/conversation/{conversation_id}")]
+pub async fn rename_conversation(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    input: web::Json<models::conversation::RenameConversationBody>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let group_name = input.into_inner().name.trim().to_string();
+
+    let errors = validate_group_name(&group_name);
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors);
+    }
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::RenameConversation, &conn) {
+        return e.error_response();
+    }
+    match models::conversation::Conversation::fetch_by_id(&conversation_id, &conn) {
+        Ok(conversation) => {
+            if !conversation.is_group {
+                return HttpResponse::BadRequest().finish();
+            }
+            match models::conversation::Conversation::rename(&conversation_id, &group_name, &conn) {
+                Ok(conversation) => {
+                    ws_server.into_inner().send(ws_server::ConversationUpdated {
+                        conversation: conversation.clone(),
+                        updated_by: user_id
+                    }).await.unwrap();
+                    HttpResponse::Ok().json(conversation)
+                }
+                Err(_) => {
+                    HttpResponse::InternalServerError().finish()
+                }
+            }
+        }
+        _ => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/conversation/{conversation_id}/members")]
+pub async fn add_conversation_members(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    input: web::Json<models::conversation::AddMembersBody>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let input = input.into_inner();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::AddMembers, &conn) {
+        return e.error_response();
+    }
+    match models::conversation::Conversation::fetch_by_id(&conversation_id, &conn) {
+        Ok(conversation) => {
+            if !conversation.is_group {
+                return HttpResponse::BadRequest().finish();
+            }
+            let users = match models::user::User::fetch_by_ids(&input.user_ids, &conn) {
+                Ok(users) => users,
+                Err(_) => return HttpResponse::InternalServerError().finish()
+            };
+            if input.user_ids.is_empty() || input.user_ids.iter().any(|uid| !users.iter().any(|u| u.id == *uid)) {
+                let errors = vec![ErrorField {path: String::from("userIds"), messages: vec![String::from("some users do not exist")]}];
+                return HttpResponse::BadRequest().json(errors);
+            }
+            let banned_ids: Vec<uuid::Uuid> = match models::member::Member::fetch_banned_by_conversation(&conversation_id, &conn) {
+                Ok(banned) => banned.iter().map(|m| m.user_id).collect(),
+                Err(_) => return HttpResponse::InternalServerError().finish()
+            };
+            if input.user_ids.iter().any(|uid| banned_ids.contains(uid)) {
+                let errors = vec![ErrorField {path: String::from("userIds"), messages: vec![String::from("some users are banned from this conversation")]}];
+                return HttpResponse::BadRequest().json(errors);
+            }
+            match models::member::Member::add_many(&conversation_id, &input.user_ids, &conn) {
+                Ok(added_ids) => {
+                    if !added_ids.is_empty() {
+                        ws_server.into_inner().send(ws_server::MembersAdded {
+                            conversation_id,
+                            user_ids: added_ids.clone(),
+                            added_by: user_id
+                        }).await.unwrap();
+                    }
+                    HttpResponse::Ok().json(added_ids)
+                }
+                Err(_) => {
+                    HttpResponse::InternalServerError().finish()
+                }
+            }
+        }
+        _ => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/conversation/{conversation_id}/members/{user_id}/kick")]
+pub async fn kick_member(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (conversation_id, target_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if target_id == user_id {
+        return HttpResponse::BadRequest().finish();
+    }
+    let actor = match models::member::Member::check_permission(&user_id, &conversation_id, Permission::KickMembers, &conn) {
+        Ok(actor) => actor,
+        Err(e) => return e.error_response()
+    };
+    let target = match models::member::Member::get_member_or_throw(&target_id, &conversation_id, &conn) {
+        Ok(target) => target,
+        Err(_) => return HttpResponse::NotFound().finish()
+    };
+    if !actor.outranks(&target) {
+        return PermissionError::Forbidden.error_response();
+    }
+    match models::conversation::Conversation::fetch_by_id(&conversation_id, &conn) {
+        Ok(conversation) if conversation.is_group => {}
+        Ok(_) => return HttpResponse::BadRequest().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    match models::member::Member::kick(&target_id, &conversation_id, &conn) {
+        Ok(_) => {
+            ws_server.into_inner().send(ws_server::MemberRemoved {
+                conversation_id,
+                user_id: target_id,
+                removed_by: user_id,
+                reason: ws_server::MemberRemovedReason::KICKED
+            }).await.unwrap();
+            HttpResponse::Ok().json(true)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/conversation/{conversation_id}/members/{user_id}/ban")]
+pub async fn ban_member(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (conversation_id, target_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if target_id == user_id {
+        return HttpResponse::BadRequest().finish();
+    }
+    let actor = match models::member::Member::check_permission(&user_id, &conversation_id, Permission::BanMembers, &conn) {
+        Ok(actor) => actor,
+        Err(e) => return e.error_response()
+    };
+    if models::user::User::find_by_id(&target_id, &conn).is_err() {
+        return HttpResponse::NotFound().finish();
+    }
+    let target = models::member::Member::get_member_or_throw(&target_id, &conversation_id, &conn).ok();
+    if let Some(target) = &target {
+        if !actor.outranks(target) {
+            return PermissionError::Forbidden.error_response();
+        }
+    }
+    match models::conversation::Conversation::fetch_by_id(&conversation_id, &conn) {
+        Ok(conversation) if conversation.is_group => {}
+        Ok(_) => return HttpResponse::BadRequest().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    match models::member::Member::ban(&target_id, &conversation_id, &conn) {
+        Ok(_) => {
+            if target.is_some() {
+                ws_server.into_inner().send(ws_server::MemberRemoved {
+                    conversation_id,
+                    user_id: target_id,
+                    removed_by: user_id,
+                    reason: ws_server::MemberRemovedReason::BANNED
+                }).await.unwrap();
+            }
+            HttpResponse::Ok().json(true)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[delete("/conversation/{conversation_id}/members/{user_id}/ban")]
+pub async fn unban_member(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (conversation_id, target_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::BanMembers, &conn) {
+        return e.error_response();
+    }
+    match models::member::Member::unban(&target_id, &conversation_id, &conn) {
+        Ok(affected_rows) => {
+            HttpResponse::Ok().json(affected_rows > 0)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/conversation/{conversation_id}/bans")]
+pub async fn get_conversation_bans(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::ViewBans, &conn) {
+        return e.error_response();
+    }
+    match models::member::Member::fetch_banned_by_conversation(&conversation_id, &conn) {
+        Ok(banned) => {
+            HttpResponse::Ok().json(banned)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/conversation/{conversation_id}/leave")]
+pub async fn leave_conversation(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    let member = models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn);
+    let conversation = models::conversation::Conversation::fetch_by_id(&conversation_id, &conn);
+    match (member, conversation) {
+        (Ok(_), Ok(conversation)) => {
+            if !conversation.is_group {
+                return HttpResponse::BadRequest().finish();
+            }
+            match models::member::Member::leave(&user_id, &conversation_id, &conn) {
+                Ok(new_owner_id) => {
+                    let ws_server = ws_server.into_inner();
+                    ws_server.send(ws_server::MemberRemoved {
+                        conversation_id,
+                        user_id,
+                        removed_by: user_id,
+                        reason: ws_server::MemberRemovedReason::LEFT
+                    }).await.unwrap();
+                    if let Some(new_owner_id) = new_owner_id {
+                        ws_server.send(ws_server::MemberRoleUpdated {
+                            conversation_id,
+                            user_id: new_owner_id,
+                            role: Role::Owner,
+                            updated_by: user_id
+                        }).await.unwrap();
+                    }
+                    HttpResponse::Ok().json(true)
+                }
+                Err(_) => {
+                    HttpResponse::InternalServerError().finish()
+                }
+            }
+        }
+        (Err(_), _) => {
+            PermissionError::NotMember.error_response()
+        }
+        _ => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[put("/conversation/{conversation_id}/members/{user_id}/role")]
+pub async fn set_member_role(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    input: web::Json<models::conversation::SetRoleBody>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (conversation_id, target_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let new_role = input.into_inner().role;
+
+    if new_role == Role::Owner {
+        let errors = vec![ErrorField {path: String::from("role"), messages: vec![String::from("use ownership transfer to assign an owner")]}];
+        return HttpResponse::BadRequest().json(errors);
+    }
+    let actor = match models::member::Member::check_permission(&user_id, &conversation_id, Permission::ManageRoles, &conn) {
+        Ok(actor) => actor,
+        Err(e) => return e.error_response()
+    };
+    let target = match models::member::Member::get_member_or_throw(&target_id, &conversation_id, &conn) {
+        Ok(target) => target,
+        Err(_) => return HttpResponse::NotFound().finish()
+    };
+    if !actor.outranks(&target) || new_role >= actor.role() {
+        return PermissionError::Forbidden.error_response();
+    }
+    match models::member::Member::set_role(&target_id, &conversation_id, new_role, &conn) {
+        Ok(member) => {
+            ws_server.into_inner().send(ws_server::MemberRoleUpdated {
+                conversation_id,
+                user_id: target_id,
+                role: new_role,
+                updated_by: user_id
+            }).await.unwrap();
+            HttpResponse::Ok().json(member)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/conversation/{conversation_id}/transfer-ownership")]
+pub async fn transfer_ownership(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    input: web::Json<models::conversation::TransferOwnershipBody>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let target_id = input.into_inner().user_id;
+
+    if target_id == user_id {
+        return HttpResponse::BadRequest().finish();
+    }
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::TransferOwnership, &conn) {
+        return e.error_response();
+    }
+    if models::member::Member::get_member_or_throw(&target_id, &conversation_id, &conn).is_err() {
+        return HttpResponse::NotFound().finish();
+    }
+    match models::member::Member::transfer_ownership(&conversation_id, &user_id, &target_id, &conn) {
+        Ok(_) => {
+            let ws_server = ws_server.into_inner();
+            ws_server.send(ws_server::MemberRoleUpdated {
+                conversation_id,
+                user_id: target_id,
+                role: Role::Owner,
+                updated_by: user_id
+            }).await.unwrap();
+            ws_server.send(ws_server::MemberRoleUpdated {
+                conversation_id,
+                user_id,
+                role: Role::Admin,
+                updated_by: user_id
+            }).await.unwrap();
+            HttpResponse::Ok().json(true)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[put("/conversation/{conversation_id}/profile")]
+pub async fn update_member_profile(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    input: web::Json<models::member::UpdateProfileBody>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let input = input.into_inner();
+    let nick = input.nick.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+    let avatar = input.avatar.map(|a| a.trim().to_string()).filter(|a| !a.is_empty());
+
+    let mut errors: Vec<ErrorField> = Vec::new();
+    if nick.as_ref().is_some_and(|n| n.len() > 255) {
+        errors.push(ErrorField {path: String::from("nick"), messages: vec![String::from("nick length must be less than 255")]});
+    }
+    if avatar.as_ref().is_some_and(|a| a.len() > 255) {
+        errors.push(ErrorField {path: String::from("avatar"), messages: vec![String::from("avatar length must be less than 255")]});
+    }
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors);
+    }
+
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    let member = models::member::Member::update_profile(&user_id, &conversation_id, nick, avatar, &conn);
+    let user = models::user::User::find_by_id(&user_id, &conn);
+    match (member, user) {
+        (Ok(member), Ok(user)) => {
+            let identity = models::member::MemberIdentity::resolve(&user, Some(&member));
+            ws_server.into_inner().send(ws_server::MemberProfileUpdated {
+                conversation_id,
+                member: member.clone(),
+                identity: identity.clone()
+            }).await.unwrap();
+            HttpResponse::Ok().json(models::member::MemberWithIdentity { member, identity })
+        }
+        _ => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/conversation/{conversation_id}/read")]
+pub async fn mark_conversation_read(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    input: Option<web::Json<models::member::MarkReadBody>>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let message_id = input.and_then(|input| input.into_inner().message_id);
+
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+
+    let result = ws_server.into_inner().send(ws_server::MarkRead {
+        user_id,
+        conversation_id,
+        message_id
+    }).await.unwrap();
+    match result {
+        Some(member) => {
+            HttpResponse::Ok().json(member)
+        }
+        None => {
+            HttpResponse::BadRequest().finish()
+        }
+    }
+}
+
+#[put("/conversation/{conversation_id}/archive")]
+pub async fn archive_conversation(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    match models::member::Member::set_archived(&user_id, &conversation_id, true, &conn) {
+        Ok(affected_rows) => {
+            HttpResponse::Ok().json(affected_rows > 0)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[delete("/conversation/{conversation_id}/archive")]
+pub async fn unarchive_conversation(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    match models::member::Member::set_archived(&user_id, &conversation_id, false, &conn) {
+        Ok(affected_rows) => {
+            HttpResponse::Ok().json(affected_rows > 0)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
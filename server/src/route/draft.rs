@@ -0,0 +1,106 @@
+use actix_web::{
+   get, put, delete, web, HttpRequest, HttpResponse
+};
+use actix::*;
+use crate::lib::{DbPool, ErrorField};
+use crate::models;
+use crate::models::draft::{self, Draft, SaveDraftBody};
+use crate::models::permission::PermissionError;
+use crate::ws_server;
+
+/// The caller's draft for the conversation, or `null` when there is none.
+#[get("/conversation/{conversation_id}/draft")]
+pub async fn get_draft(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    match Draft::find(&user_id, &conversation_id, &conn) {
+        Ok(found) => {
+            HttpResponse::Ok().json(found)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Saves the draft and hands it to the caller's other sessions. Blank content clears it.
+#[put("/conversation/{conversation_id}/draft")]
+pub async fn save_draft(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    input: web::Json<SaveDraftBody>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    if input.content.chars().count() > draft::MAX_CONTENT_CHARS {
+        let errors = vec![ErrorField {path: String::from("content"), messages: vec![
+            format!("drafts are limited to {} characters", draft::MAX_CONTENT_CHARS)
+        ]}];
+        return HttpResponse::BadRequest().json(errors);
+    }
+    let saved = if input.content.trim().is_empty() {
+        Draft::delete_one(&user_id, &conversation_id, &conn).map(|_| None)
+    } else {
+        Draft::save(&user_id, &conversation_id, &input.content, &conn).map(Some)
+    };
+    match saved {
+        Ok(saved) => {
+            ws_server.into_inner().send(ws_server::DraftUpdated {
+                user_id,
+                conversation_id,
+                draft: saved.clone()
+            }).await.unwrap();
+            HttpResponse::Ok().json(saved)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[delete("/conversation/{conversation_id}/draft")]
+pub async fn delete_draft(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    match Draft::delete_one(&user_id, &conversation_id, &conn) {
+        Ok(affected_rows) => {
+            if affected_rows > 0 {
+                ws_server.into_inner().send(ws_server::DraftUpdated {
+                    user_id,
+                    conversation_id,
+                    draft: None
+                }).await.unwrap();
+            }
+            HttpResponse::Ok().json(affected_rows > 0)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use actix_web::{
+   get, post, delete, web, HttpRequest, HttpResponse
+};
+use serde::{Deserialize, Serialize};
+use crate::emoji_catalogue::{self, CatalogueEmoji, EmojiCatalogue};
+use crate::lib::{DbPool, ErrorField, ErrorResponse};
+use crate::models;
+use crate::models::custom_emoji::{CreateCustomEmojiBody, CustomEmoji, CustomEmojiView};
+use crate::models::permission::{self, Permission, PermissionError};
+
+#[derive(Deserialize)]
+pub struct EmojiSearchQuery {
+    q: String,
+    limit: Option<usize>,
+    /// Also suggest the custom emojis usable in this conversation.
+    conversation_id: Option<uuid::Uuid>
+}
+
+#[derive(Deserialize)]
+pub struct FrequentEmojiQuery {
+    limit: Option<usize>,
+    conversation_id: Option<uuid::Uuid>
+}
+
+/// A built-in or custom emoji offered to the user. Built-in ones carry `unicode`, custom ones `url`.
+#[derive(Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct EmojiSuggestion {
+    name: String,
+    unicode: Option<String>,
+    skins: Vec<String>,
+    url: Option<String>,
+    /// How many times the user reacted with it; only for the frequently used list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<i64>
+}
+
+impl EmojiSuggestion {
+    fn builtin(emoji: CatalogueEmoji) -> EmojiSuggestion {
+        EmojiSuggestion { name: emoji.name, unicode: Some(emoji.unicode), skins: emoji.skins, url: None, count: None }
+    }
+
+    fn custom(emoji: CustomEmoji) -> EmojiSuggestion {
+        let url = emoji.url();
+        EmojiSuggestion { name: emoji.name, unicode: None, skins: Vec::new(), url: Some(url), count: None }
+    }
+}
+
+fn search_limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(emoji_catalogue::DEFAULT_SEARCH_LIMIT).clamp(1, emoji_catalogue::MAX_SEARCH_LIMIT)
+}
+
+/// Custom emojis of a conversation the user is a member of; nothing without a conversation.
+fn usable_custom_emojis(
+    user_id: &uuid::Uuid,
+    conversation_id: Option<uuid::Uuid>,
+    conn: &diesel::PgConnection
+) -> Result<Vec<CustomEmoji>, PermissionError> {
+    let conversation_id = match conversation_id {
+        Some(conversation_id) => conversation_id,
+        None => return Ok(Vec::new())
+    };
+    if models::member::Member::get_member_or_throw(user_id, &conversation_id, conn).is_err() {
+        return Err(PermissionError::NotMember);
+    }
+    CustomEmoji::fetch_usable(&conversation_id, conn).map_err(PermissionError::Database)
+}
+
+fn create_custom_emoji(
+    input: &CreateCustomEmojiBody,
+    conversation_id: Option<&uuid::Uuid>,
+    user_id: &uuid::Uuid,
+    catalogue: &EmojiCatalogue,
+    conn: &diesel::PgConnection
+) -> HttpResponse {
+    match CustomEmoji::validate(input, conversation_id, user_id, catalogue, conn) {
+        Ok(errors) if !errors.is_empty() => return HttpResponse::BadRequest().json(errors),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    match CustomEmoji::insert_one(input, conversation_id, user_id, conn) {
+        Ok(emoji) => {
+            HttpResponse::Created().json(emoji.view())
+        }
+        // Someone else took the name since it was checked.
+        Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _)) => {
+            HttpResponse::BadRequest().json(vec![ErrorField {path: String::from("name"), messages: vec![String::from("name is already taken")]}])
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Custom emojis usable in the conversation, its own and the server-wide ones, for the picker.
+#[get("/conversation/{conversation_id}/emojis")]
+pub async fn get_conversation_emojis(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    match CustomEmoji::fetch_usable(&conversation_id, &conn) {
+        Ok(emojis) => {
+            HttpResponse::Ok().json(emojis.into_iter().map(CustomEmoji::view).collect::<Vec<CustomEmojiView>>())
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/conversation/{conversation_id}/emojis")]
+pub async fn create_conversation_emoji(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    input: web::Json<CreateCustomEmojiBody>,
+    emoji_catalogue: web::Data<EmojiCatalogue>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::ManageEmojis, &conn) {
+        return e.error_response();
+    }
+    create_custom_emoji(&input.into_inner(), Some(&conversation_id), &user_id, &emoji_catalogue, &conn)
+}
+
+#[delete("/conversation/{conversation_id}/emojis/{emoji_id}")]
+pub async fn delete_conversation_emoji(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (conversation_id, emoji_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::ManageEmojis, &conn) {
+        return e.error_response();
+    }
+    match CustomEmoji::delete_one(&emoji_id, Some(&conversation_id), &conn) {
+        Ok(0) => HttpResponse::NotFound().finish(),
+        Ok(_) => HttpResponse::Ok().json(true),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+#[get("/emojis")]
+pub async fn get_server_emojis(
+    _: models::auth::Auth,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    match CustomEmoji::fetch_server_wide(&conn) {
+        Ok(emojis) => {
+            HttpResponse::Ok().json(emojis.into_iter().map(CustomEmoji::view).collect::<Vec<CustomEmojiView>>())
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/emojis")]
+pub async fn create_server_emoji(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    input: web::Json<CreateCustomEmojiBody>,
+    emoji_catalogue: web::Data<EmojiCatalogue>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if !permission::is_server_admin(&user_id) {
+        return PermissionError::Forbidden.error_response();
+    }
+    create_custom_emoji(&input.into_inner(), None, &user_id, &emoji_catalogue, &conn)
+}
+
+#[delete("/emojis/{emoji_id}")]
+pub async fn delete_server_emoji(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let emoji_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if !permission::is_server_admin(&user_id) {
+        return PermissionError::Forbidden.error_response();
+    }
+    match CustomEmoji::delete_one(&emoji_id, None, &conn) {
+        Ok(0) => HttpResponse::NotFound().finish(),
+        Ok(_) => HttpResponse::Ok().json(true),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+/// Every built-in emoji by category, for the picker.
+#[get("/emojis/catalogue")]
+pub async fn get_emoji_catalogue(
+    _: models::auth::Auth,
+    emoji_catalogue: web::Data<EmojiCatalogue>
+) -> HttpResponse {
+    HttpResponse::Ok()
+        .append_header((actix_web::http::header::CACHE_CONTROL, "private, max-age=300"))
+        .json(emoji_catalogue.categories())
+}
+
+/// Re-reads the catalogue file right away instead of waiting for the next check.
+#[post("/emojis/catalogue/reload")]
+pub async fn reload_emoji_catalogue(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    emoji_catalogue: web::Data<EmojiCatalogue>
+) -> HttpResponse {
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if !permission::is_server_admin(&user_id) {
+        return PermissionError::Forbidden.error_response();
+    }
+    match emoji_catalogue.reload() {
+        Ok(count) => HttpResponse::Ok().json(count),
+        Err(e) => HttpResponse::UnprocessableEntity().json(ErrorResponse {message: e.to_string()})
+    }
+}
+
+/// Autocomplete for `:shortcodes`. Custom emojis of the conversation come first.
+#[get("/emojis/search")]
+pub async fn search_emojis(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    query: web::Query<EmojiSearchQuery>,
+    emoji_catalogue: web::Data<EmojiCatalogue>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let query = query.into_inner();
+    let limit = search_limit(query.limit);
+    let needle = query.q.trim().trim_matches(':').to_lowercase();
+
+    let custom_emojis = match usable_custom_emojis(&user_id, query.conversation_id, &conn) {
+        Ok(custom_emojis) => custom_emojis,
+        Err(e) => return e.error_response()
+    };
+    let mut suggestions: Vec<EmojiSuggestion> = Vec::new();
+    if !needle.is_empty() {
+        let mut matching: Vec<CustomEmoji> = custom_emojis.into_iter()
+            .filter(|emoji| emoji.name.split('_').any(|word| word.starts_with(&needle)) || emoji.name.starts_with(&needle))
+            .collect();
+        matching.sort_by_key(|emoji| (emoji.name != needle, !emoji.name.starts_with(&needle)));
+        suggestions.extend(matching.into_iter().take(limit).map(EmojiSuggestion::custom));
+    }
+    let remaining = limit - suggestions.len();
+    suggestions.extend(emoji_catalogue.search(&needle, remaining).into_iter().map(EmojiSuggestion::builtin));
+    HttpResponse::Ok().json(suggestions)
+}
+
+/// The emojis the user reacts with most. Custom ones are only included for the given conversation.
+#[get("/emojis/frequent")]
+pub async fn get_frequent_emojis(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    query: web::Query<FrequentEmojiQuery>,
+    emoji_catalogue: web::Data<EmojiCatalogue>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let query = query.into_inner();
+    let limit = search_limit(query.limit);
+
+    let custom_emojis = match usable_custom_emojis(&user_id, query.conversation_id, &conn) {
+        Ok(custom_emojis) => custom_emojis,
+        Err(e) => return e.error_response()
+    };
+    // Some of the most used may not be usable here, so look a little further.
+    let usages = match models::reaction::Reaction::most_used_emojis(&user_id, emoji_catalogue::MAX_SEARCH_LIMIT, &conn) {
+        Ok(usages) => usages,
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    let suggestions: Vec<EmojiSuggestion> = usages.into_iter()
+        .filter_map(|usage| {
+            let suggestion = match emoji_catalogue.get(&usage.emoji) {
+                Some(emoji) => EmojiSuggestion::builtin(emoji),
+                None => EmojiSuggestion::custom(custom_emojis.iter().find(|emoji| emoji.name == usage.emoji)?.clone())
+            };
+            Some(EmojiSuggestion { count: Some(usage.count), ..suggestion })
+        })
+        .take(limit)
+        .collect();
+    HttpResponse::Ok().json(suggestions)
+}
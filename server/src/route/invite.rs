@@ -0,0 +1,349 @@
+use actix_web::{
+   get, post, put, delete, web, HttpRequest, HttpResponse
+};
+use serde::{Serialize};
+use actix::*;
+use crate::lib::{DbPool, ErrorField, ErrorResponse, json_option_time};
+use crate::models;
+use crate::models::invite::{Invite, JoinError, JoinOutcome, JoinRequest};
+use crate::models::permission::{Permission, PermissionError};
+use crate::ws_server;
+
+#[derive(Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct InvitePreview {
+    code: String,
+    conversation_id: uuid::Uuid,
+    name: Option<String>,
+    member_count: usize,
+    join_approval: bool,
+    #[serde(with = "json_option_time")]
+    expires_at: Option<chrono::NaiveDateTime>
+}
+
+#[derive(Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct JoinResponse {
+    outcome: JoinOutcome,
+    conversation_id: uuid::Uuid
+}
+
+fn invite_gone() -> HttpResponse {
+    HttpResponse::Gone().json(ErrorResponse {message: String::from("invite is expired, revoked or used up")})
+}
+
+fn banned() -> HttpResponse {
+    HttpResponse::Forbidden().json(ErrorResponse {message: String::from("you are banned from this conversation")})
+}
+
+#[post("/conversation/{conversation_id}/invites")]
+pub async fn create_invite(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    input: web::Json<models::invite::CreateInviteBody>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let input = input.into_inner();
+
+    let mut errors: Vec<ErrorField> = Vec::new();
+    if input.expires_in.is_some_and(|seconds| seconds <= 0) {
+        errors.push(ErrorField {path: String::from("expiresIn"), messages: vec![String::from("expiresIn must be greater than 0")]});
+    }
+    if input.max_uses.is_some_and(|max_uses| max_uses <= 0) {
+        errors.push(ErrorField {path: String::from("maxUses"), messages: vec![String::from("maxUses must be greater than 0")]});
+    }
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors);
+    }
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::ManageInvites, &conn) {
+        return e.error_response();
+    }
+    match models::conversation::Conversation::fetch_by_id(&conversation_id, &conn) {
+        Ok(conversation) if conversation.is_group => {}
+        Ok(_) => return HttpResponse::BadRequest().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    let expires_at = input.expires_in.map(|seconds| {
+        chrono::Utc::now().naive_utc() + chrono::Duration::seconds(seconds)
+    });
+    let invite = Invite::insert_one(&models::invite::NewInvite {
+        code: Invite::generate_code(),
+        conversation_id,
+        created_by: user_id,
+        expires_at,
+        max_uses: input.max_uses
+    }, &conn);
+    match invite {
+        Ok(invite) => {
+            HttpResponse::Created().json(invite)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/conversation/{conversation_id}/invites")]
+pub async fn get_conversation_invites(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::ManageInvites, &conn) {
+        return e.error_response();
+    }
+    match Invite::fetch_by_conversation(&conversation_id, &conn) {
+        Ok(invites) => {
+            HttpResponse::Ok().json(invites)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[delete("/conversation/{conversation_id}/invites/{invite_id}")]
+pub async fn revoke_invite(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (conversation_id, invite_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::ManageInvites, &conn) {
+        return e.error_response();
+    }
+    match Invite::revoke(&invite_id, &conversation_id, &conn) {
+        Ok(affected_rows) => {
+            HttpResponse::Ok().json(affected_rows > 0)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[put("/conversation/{conversation_id}/join-approval")]
+pub async fn set_join_approval(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    input: web::Json<models::conversation::JoinApprovalBody>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::ManageInvites, &conn) {
+        return e.error_response();
+    }
+    match models::conversation::Conversation::set_join_approval(&conversation_id, input.enabled, &conn) {
+        Ok(conversation) => {
+            ws_server.into_inner().send(ws_server::ConversationUpdated {
+                conversation: conversation.clone(),
+                updated_by: user_id
+            }).await.unwrap();
+            HttpResponse::Ok().json(conversation)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/invite/{code}")]
+pub async fn get_invite(
+    _: models::auth::Auth,
+    path: web::Path<String>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let code = path.into_inner();
+
+    let invite = match Invite::fetch_by_code(&code, &conn) {
+        Ok(invite) => invite,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    if !invite.is_usable() {
+        return invite_gone();
+    }
+    let conversation = models::conversation::Conversation::fetch_by_id(&invite.conversation_id, &conn);
+    let member_ids = models::member::Member::fetch_user_ids(&invite.conversation_id, &conn);
+    match (conversation, member_ids) {
+        (Ok(conversation), Ok(member_ids)) => {
+            HttpResponse::Ok().json(InvitePreview {
+                code: invite.code,
+                conversation_id: conversation.id,
+                name: conversation.name,
+                member_count: member_ids.len(),
+                join_approval: conversation.join_approval,
+                expires_at: invite.expires_at
+            })
+        }
+        _ => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/invite/{code}/join")]
+pub async fn join_by_invite(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let code = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    let invite = match Invite::fetch_by_code(&code, &conn) {
+        Ok(invite) => invite,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    let conversation_id = invite.conversation_id;
+    let conversation = match models::conversation::Conversation::fetch_by_id(&conversation_id, &conn) {
+        Ok(conversation) => conversation,
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+
+    match invite.redeem(&user_id, conversation.join_approval, &conn) {
+        Ok(outcome) => {
+            let ws_server = ws_server.into_inner();
+            match outcome {
+                JoinOutcome::Joined => {
+                    ws_server.send(ws_server::MembersAdded {
+                        conversation_id,
+                        user_ids: vec![user_id],
+                        added_by: user_id
+                    }).await.unwrap();
+                }
+                JoinOutcome::Requested => {
+                    ws_server.send(ws_server::JoinRequested {
+                        conversation_id,
+                        user_id
+                    }).await.unwrap();
+                }
+                JoinOutcome::AlreadyMember => {}
+            }
+            HttpResponse::Ok().json(JoinResponse { outcome, conversation_id })
+        }
+        Err(JoinError::InviteUnusable) => {
+            invite_gone()
+        }
+        Err(JoinError::Banned) => {
+            banned()
+        }
+        Err(JoinError::Database(_)) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/conversation/{conversation_id}/join-requests")]
+pub async fn get_join_requests(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::ReviewJoinRequests, &conn) {
+        return e.error_response();
+    }
+    match JoinRequest::fetch_pending_by_conversation(&conversation_id, &conn) {
+        Ok(requests) => {
+            HttpResponse::Ok().json(requests)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+async fn resolve_join_request(
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>,
+    approve: bool
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (conversation_id, requester_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::ReviewJoinRequests, &conn) {
+        return e.error_response();
+    }
+    match JoinRequest::resolve(&conversation_id, &requester_id, &user_id, approve, &conn) {
+        Ok((request, added)) => {
+            let ws_server = ws_server.into_inner();
+            ws_server.send(ws_server::JoinRequestResolved {
+                conversation_id,
+                user_id: requester_id,
+                approved: approve,
+                resolved_by: user_id
+            }).await.unwrap();
+            if added {
+                ws_server.send(ws_server::MembersAdded {
+                    conversation_id,
+                    user_ids: vec![requester_id],
+                    added_by: user_id
+                }).await.unwrap();
+            }
+            HttpResponse::Ok().json(request)
+        }
+        Err(JoinError::Database(diesel::result::Error::NotFound)) => {
+            HttpResponse::NotFound().finish()
+        }
+        Err(JoinError::Banned) => {
+            PermissionError::Forbidden.error_response()
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/conversation/{conversation_id}/join-requests/{user_id}/approve")]
+pub async fn approve_join_request(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    resolve_join_request(req, path, pool, ws_server, true).await
+}
+
+#[post("/conversation/{conversation_id}/join-requests/{user_id}/deny")]
+pub async fn deny_join_request(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    resolve_join_request(req, path, pool, ws_server, false).await
+}
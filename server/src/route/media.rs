@@ -0,0 +1,291 @@
+use actix_web::{
+   get, post, delete, web, http, HttpRequest, HttpResponse
+};
+use futures::StreamExt;
+use serde::{Deserialize};
+use crate::lib::{DbPool, ErrorField, ErrorResponse};
+use crate::models;
+use crate::models::permission::PermissionError;
+use crate::models::upload::{NewUpload, StoredThumbnail, Upload, UploadQuery};
+use crate::storage::{self, processing, Storage, StorageError, StoredContent, StoredObject};
+
+#[derive(Deserialize)]
+pub struct MediaQuery {
+    access_token: Option<String>
+}
+
+fn upload_error(path: &str, message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(vec![ErrorField {path: String::from(path), messages: vec![message]}])
+}
+
+/// Keeps header-unsafe characters out of `Content-Disposition`.
+fn sanitize_filename(filename: &str) -> String {
+    filename.chars()
+        .filter(|c| !c.is_control() && *c != '"' && *c != '\\' && *c != '/')
+        .take(255)
+        .collect()
+}
+
+/// Best-effort cleanup of objects stored for an upload that didn't make it.
+async fn discard(storage: &dyn Storage, objects: &[StoredObject]) {
+    for object in objects {
+        let _ = storage.delete(object).await;
+    }
+}
+
+/// Uploads the raw request body. The MIME type comes from `Content-Type` and the original
+/// name from `?filename=`; attach the result to a message by its id. Images are stripped of
+/// their metadata and get thumbnails, dimensions and a blurhash on the way in.
+#[post("/media/upload")]
+pub async fn upload_media(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    query: web::Query<UploadQuery>,
+    mut payload: web::Payload,
+    storage: web::Data<dyn Storage>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let query = query.into_inner();
+    let mime_type = match req.headers().get(http::header::CONTENT_TYPE).and_then(|value| value.to_str().ok()) {
+        Some(value) => value.split(';').next().unwrap_or("").trim().to_ascii_lowercase(),
+        None => String::new()
+    };
+    if mime_type.is_empty() || !storage::is_allowed_mime_type(&mime_type) {
+        return upload_error("content_type", format!("{} uploads are not allowed", mime_type));
+    }
+
+    let max_bytes = storage::max_upload_bytes();
+    let mut data: Vec<u8> = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        match chunk {
+            Ok(chunk) => {
+                if (data.len() + chunk.len()) as i64 > max_bytes {
+                    return HttpResponse::PayloadTooLarge().json(ErrorResponse {
+                        message: format!("uploads are limited to {} bytes", max_bytes)
+                    });
+                }
+                data.extend_from_slice(&chunk);
+            }
+            Err(_) => return HttpResponse::BadRequest().finish()
+        }
+    }
+    if data.is_empty() {
+        return upload_error("body", String::from("upload cannot be empty"));
+    }
+
+    let quota = storage::user_quota_bytes();
+    let conn = pool.get().unwrap();
+    if let Some(quota) = quota {
+        match Upload::used_bytes(&user_id, &conn) {
+            Ok(used) if used + data.len() as i64 > quota => {
+                return upload_error("body", String::from("storage quota exceeded"));
+            }
+            Ok(_) => {}
+            Err(_) => return HttpResponse::InternalServerError().finish()
+        }
+    }
+
+    let (data, mime_type, image) = if processing::is_processable(&mime_type) {
+        match web::block(move || processing::process(data, &mime_type)).await {
+            Ok(Ok(mut image)) => {
+                let original = std::mem::take(&mut image.original.data);
+                (original, image.original.mime_type.to_string(), Some(image))
+            }
+            Ok(Err(e)) => return upload_error("body", e.to_string()),
+            Err(_) => return HttpResponse::InternalServerError().finish()
+        }
+    } else {
+        (data, mime_type, None)
+    };
+    let size = data.len() as i64;
+
+    let key = format!("{}/{}", user_id, uuid::Uuid::new_v4());
+    let stored = match storage.put(&key, &mime_type, data).await {
+        Ok(stored) => stored,
+        Err(e) => {
+            log::error!("storing upload failed: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let mut stored_objects = vec![stored.clone()];
+    let (width, height, blurhash, pending_thumbnails) = match image {
+        Some(image) => (Some(image.original.width as i32), Some(image.original.height as i32), image.blurhash, image.thumbnails),
+        None => (None, None, None, Vec::new())
+    };
+    let mut thumbnails: Vec<StoredThumbnail> = Vec::new();
+    for (thumbnail_size, thumbnail) in pending_thumbnails {
+        let thumbnail_key = format!("{}-{}", key, thumbnail_size);
+        match storage.put(&thumbnail_key, thumbnail.mime_type, thumbnail.data).await {
+            Ok(stored_thumbnail) => {
+                stored_objects.push(stored_thumbnail.clone());
+                thumbnails.push(StoredThumbnail {
+                    size: thumbnail_size as i32,
+                    width: thumbnail.width as i32,
+                    height: thumbnail.height as i32,
+                    storage_key: stored_thumbnail.key,
+                    url: stored_thumbnail.url,
+                    mime_type: thumbnail.mime_type.to_string()
+                });
+            }
+            Err(e) => {
+                log::error!("storing thumbnail failed: {}", e);
+                discard(&**storage, &stored_objects).await;
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    let new_upload = NewUpload {
+        user_id,
+        backend: storage.backend().to_string(),
+        storage_key: stored.key.to_owned(),
+        url: stored.url.to_owned(),
+        mime_type,
+        size,
+        filename: query.filename.as_deref().map(sanitize_filename).filter(|name| !name.is_empty()),
+        purpose: query.purpose,
+        width,
+        height,
+        blurhash,
+        thumbnails
+    };
+    match Upload::insert_within_quota(&new_upload, quota, &conn) {
+        Ok(Some(upload)) => HttpResponse::Created().json(upload.view()),
+        Ok(None) => {
+            // Another upload used up the quota in the meantime.
+            discard(&**storage, &stored_objects).await;
+            upload_error("body", String::from("storage quota exceeded"))
+        }
+        Err(_) => {
+            discard(&**storage, &stored_objects).await;
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Looks up an upload for whoever is asking, answering 404 whether it's missing or hidden.
+/// Browsers can't set headers on `<img>` requests, so the token may come as `?access_token=`.
+fn find_viewable(upload_id: &uuid::Uuid, req: &HttpRequest, query: &MediaQuery, storage: &dyn Storage, pool: &DbPool) -> Result<Upload, http::StatusCode> {
+    let user_id = match models::user::User::get_id_from_req(req)
+        .or_else(|| query.access_token.as_deref().and_then(models::user::User::verify_access_token)) {
+        Some(user_id) => user_id,
+        None => return Err(http::StatusCode::UNAUTHORIZED)
+    };
+    let conn = pool.get().unwrap();
+    let upload = match Upload::fetch_by_id(upload_id, &conn) {
+        Ok(upload) => upload,
+        Err(diesel::result::Error::NotFound) => return Err(http::StatusCode::NOT_FOUND),
+        Err(_) => return Err(http::StatusCode::INTERNAL_SERVER_ERROR)
+    };
+    match upload.can_view(&user_id, &conn) {
+        Ok(true) => {}
+        // Don't reveal that the upload exists.
+        Ok(false) => return Err(http::StatusCode::NOT_FOUND),
+        Err(_) => return Err(http::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+    // Uploads made under a different backend can't be served by the current one.
+    if upload.backend != storage.backend() {
+        return Err(http::StatusCode::NOT_FOUND);
+    }
+    Ok(upload)
+}
+
+/// Only images we decoded and re-encoded ourselves are shown inline; everything else is
+/// downloaded, and the sandbox keeps whatever a browser does open from running scripts.
+async fn serve(storage: &dyn Storage, object: &StoredObject, mime_type: &str, filename: &str, inline: bool) -> HttpResponse {
+    match storage.get(object).await {
+        Ok(StoredContent::Bytes(data)) => {
+            let disposition = if inline { "inline" } else { "attachment" };
+            HttpResponse::Ok()
+                .content_type(mime_type)
+                .append_header((http::header::CONTENT_DISPOSITION, format!("{}; filename=\"{}\"", disposition, filename)))
+                .append_header((http::header::CACHE_CONTROL, "private, max-age=86400"))
+                .append_header((http::header::CONTENT_SECURITY_POLICY, "sandbox"))
+                .append_header(("X-Content-Type-Options", "nosniff"))
+                .body(data)
+        }
+        Ok(StoredContent::Redirect(url)) => {
+            HttpResponse::Found()
+                .append_header((http::header::LOCATION, url))
+                .append_header((http::header::CONTENT_SECURITY_POLICY, "sandbox"))
+                .finish()
+        }
+        Err(StorageError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            log::error!("reading {} failed: {}", object.key, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Serves an upload to anyone allowed to see it.
+#[get("/media/{upload_id}")]
+pub async fn get_media(
+    path: web::Path<uuid::Uuid>,
+    req: HttpRequest,
+    query: web::Query<MediaQuery>,
+    storage: web::Data<dyn Storage>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let upload = match find_viewable(&path.into_inner(), &req, &query, &**storage, &pool) {
+        Ok(upload) => upload,
+        Err(status) => return HttpResponse::build(status).finish()
+    };
+    let filename = upload.filename.as_deref().map(sanitize_filename).unwrap_or_else(|| upload.id.to_string());
+    serve(&**storage, &upload.stored_object(), &upload.mime_type, &filename, processing::is_processable(&upload.mime_type)).await
+}
+
+/// Serves one of an image upload's thumbnails, by the longest side it was scaled to.
+#[get("/media/{upload_id}/thumbnails/{size}")]
+pub async fn get_media_thumbnail(
+    path: web::Path<(uuid::Uuid, i32)>,
+    req: HttpRequest,
+    query: web::Query<MediaQuery>,
+    storage: web::Data<dyn Storage>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let (upload_id, size) = path.into_inner();
+    let upload = match find_viewable(&upload_id, &req, &query, &**storage, &pool) {
+        Ok(upload) => upload,
+        Err(status) => return HttpResponse::build(status).finish()
+    };
+    let thumbnail = match upload.stored_thumbnails().into_iter().find(|thumbnail| thumbnail.size == size) {
+        Some(thumbnail) => thumbnail,
+        None => return HttpResponse::NotFound().finish()
+    };
+    let filename = format!("{}-{}", upload.id, size);
+    serve(&**storage, &thumbnail.stored_object(), &thumbnail.mime_type, &filename, true).await
+}
+
+#[delete("/media/{upload_id}")]
+pub async fn delete_media(
+    _: models::auth::Auth,
+    path: web::Path<uuid::Uuid>,
+    req: HttpRequest,
+    storage: web::Data<dyn Storage>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let conn = pool.get().unwrap();
+    let upload = match Upload::fetch_by_id(&path.into_inner(), &conn) {
+        Ok(upload) => upload,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    if upload.user_id != user_id {
+        return PermissionError::Forbidden.error_response();
+    }
+    if upload.backend == storage.backend() {
+        if let Err(e) = storage.delete(&upload.stored_object()).await {
+            log::error!("deleting upload {} failed: {}", upload.id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+        let thumbnails: Vec<StoredObject> = upload.stored_thumbnails().iter().map(StoredThumbnail::stored_object).collect();
+        discard(&**storage, &thumbnails).await;
+    }
+    match Upload::delete_one(&upload.id, &conn) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
@@ -1,13 +1,16 @@
-use std::collections::HashMap;
 use actix_web::{
-   get, web, http, HttpRequest, post, put, delete, HttpResponse
+   get, web, HttpRequest, post, put, patch, delete, HttpResponse
 };
 use serde::{Serialize, Deserialize};
 use std::env;
-use crate::lib::{DbPool};
+use crate::lib::{DbPool, ErrorField, ErrorResponse};
+use diesel::{Connection, PgConnection};
 use actix::*;
 use crate::models;
+use crate::models::permission::{Permission, PermissionError};
 use crate::ws_server;
+use crate::unfurl;
+use crate::emoji_catalogue::EmojiCatalogue;
 
 #[delete("/message/{message_id}/conversation/{conversation_id}")]
 pub async fn delete_message(
@@ -22,15 +25,35 @@ pub async fn delete_message(
     let member = models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn);
 
     match member {
-        Ok(_) => {
-            let result = models::message::Message::delete_message(&message_id, &user_id, &conn);
+        Ok(member) => {
+            let message = match models::message::Message::fetch_by_id(&message_id, &conn) {
+                Ok(message) if message.conversation_id == conversation_id => message,
+                Ok(_) | Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+                Err(_) => return HttpResponse::InternalServerError().finish()
+            };
+            let result = if message.author_id == user_id {
+                models::message::Message::delete_message(&message_id, &user_id, &conn)
+            } else if member.role().can(Permission::DeleteMessages) {
+                models::message::Message::delete_message_in_conversation(&message_id, &conversation_id, &conn)
+            } else {
+                return PermissionError::Forbidden.error_response();
+            };
             match result {
                 Ok(affected_rows) => {
                     if affected_rows > 0 {
+                        let thread = message.thread_root_id
+                            .and_then(|root_id| models::message::Message::fetch_by_id(&root_id, &conn).ok())
+                            .map(|root| models::message::ThreadSummary {
+                                root_id: root.id,
+                                reply_count: root.thread_reply_count,
+                                last_reply_at: root.thread_last_reply_at
+                            });
                         ws_server.into_inner().send(ws_server::MessageDeleted {
                             message_id,
-                            author_id: user_id,
-                            conversation_id
+                            author_id: message.author_id,
+                            conversation_id,
+                            deleted_by: user_id,
+                            thread
                         }).await.unwrap();
                         return HttpResponse::Ok().json(true);
                     } else {
@@ -40,6 +63,212 @@ pub async fn delete_message(
                 _ => return HttpResponse::InternalServerError().finish()
             }
         }
+        Err(_) => {
+            PermissionError::NotMember.error_response()
+        }
+    }
+}
+
+#[patch("/message/{message_id}/conversation/{conversation_id}")]
+pub async fn edit_message(
+    _: models::auth::Auth,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    input: web::Json<models::message::EditMessageBody>,
+    req: HttpRequest, pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>,
+    link_preview_fetcher: web::Data<unfurl::LinkPreviewFetcher>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (message_id, conversation_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let new_content = input.into_inner().content;
+
+    if new_content.trim().is_empty() {
+        return HttpResponse::BadRequest().finish();
+    }
+    let member = match models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn) {
+        Ok(member) => member,
+        Err(_) => return PermissionError::NotMember.error_response()
+    };
+    match models::message::Message::edit(&message_id, &conversation_id, &user_id, &new_content, &conn) {
+        Ok(message) => {
+            if let Err(e) = models::conversation::Conversation::update_last_message_content(
+                &conversation_id, &message.id, &message.plain_text(), &conn
+            ) {
+                log::error!("updating the last message of {} after an edit failed: {}", conversation_id, e);
+            }
+            // The edit is already saved, so it still goes out without its mentions.
+            let mentions = models::mention::Mention::sync_for_message(&message, &conn)
+                .unwrap_or_else(|e| {
+                    log::error!("syncing mentions for message {} failed: {}", message.id, e);
+                    Vec::new()
+                });
+            let ws_server = ws_server.into_inner();
+            ws_server.send(ws_server::MessageEdited {
+                message: message.clone()
+            }).await.unwrap();
+            unfurl::spawn_for_message(
+                message.clone(), true, link_preview_fetcher.into_inner(), pool.get_ref().clone(), (*ws_server).clone()
+            );
+            if !mentions.is_empty() {
+                if let Ok(author) = models::user::User::find_by_id(&user_id, &conn) {
+                    ws_server.send(ws_server::Mentioned {
+                        message: message.clone(),
+                        author_identity: models::member::MemberIdentity::resolve(&author, Some(&member)),
+                        attachments: models::attachment::Attachment::fetch_by_message_id(&message.id, &conn).unwrap_or_default(),
+                        mentions
+                    }).await.unwrap();
+                }
+            }
+            HttpResponse::Ok().json(message)
+        }
+        Err(models::message::EditError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(models::message::EditError::NotAuthor) => PermissionError::Forbidden.error_response(),
+        Err(models::message::EditError::NotEditable) => HttpResponse::BadRequest().finish(),
+        Err(models::message::EditError::WindowExpired) => {
+            HttpResponse::Forbidden().json(ErrorResponse {message: String::from("edit window has expired")})
+        }
+        Err(models::message::EditError::Database(_)) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+#[get("/message/{message_id}/conversation/{conversation_id}/edits")]
+pub async fn get_message_edits(
+    _: models::auth::Auth,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    req: HttpRequest, pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (message_id, conversation_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    match models::message::Message::fetch_by_id(&message_id, &conn) {
+        Ok(message) if message.conversation_id == conversation_id => {}
+        Ok(_) | Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    match models::message::Message::fetch_edits(&message_id, &conn) {
+        Ok(edits) => {
+            HttpResponse::Ok().json(edits)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/mentions")]
+pub async fn get_mentions(
+    _: models::auth::Auth,
+    req: HttpRequest, query: web::Query<models::pagination::Options>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let options = query.into_inner();
+    match models::mention::Mention::fetch_inbox(&user_id, &options, &conn) {
+        Ok(messages) => {
+            match models::message::MessageView::from_mixed_messages(&user_id, messages.items, &conn) {
+                Ok(items) => {
+                    HttpResponse::Ok().json(models::pagination::Result {
+                        items,
+                        next_cursor: messages.next_cursor,
+                        prev_cursor: messages.prev_cursor,
+                        next_cursor_id: messages.next_cursor_id,
+                        prev_cursor_id: messages.prev_cursor_id
+                    })
+                }
+                Err(_) => {
+                    HttpResponse::InternalServerError().finish()
+                }
+            }
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/message/search")]
+pub async fn search_messages(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    search: web::Query<models::search::MessageSearchQuery>,
+    query: web::Query<models::pagination::Options>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    let search = search.into_inner();
+
+    if search.q.trim().is_empty() {
+        let errors = vec![ErrorField {path: String::from("q"), messages: vec![String::from("search query cannot be blank")]}];
+        return HttpResponse::BadRequest().json(errors);
+    }
+    let options = query.into_inner();
+    if !search.has_valid_cursor(&options) {
+        let errors = vec![ErrorField {path: String::from("before_rank"), messages: vec![String::from("before_id needs before and before_rank")]}];
+        return HttpResponse::BadRequest().json(errors);
+    }
+    if let Some(conversation_id) = search.conversation_id {
+        if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+            return PermissionError::NotMember.error_response();
+        }
+    }
+    match search.search(&user_id, &options, &conn) {
+        Ok(result) => {
+            HttpResponse::Ok().json(result)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/message/{message_id}/conversation/{conversation_id}/thread")]
+pub async fn get_thread_messages(
+    _: models::auth::Auth,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    req: HttpRequest, query: web::Query<models::pagination::Options>,
+    list_query: web::Query<models::message::MessageListQuery>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (message_id, conversation_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    match models::message::Message::fetch_thread_root(&message_id, &conversation_id, &conn) {
+        Ok(_) => {}
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    let options = query.into_inner();
+    match models::message::Message::fetch_thread(&message_id, &options, list_query.around, &conn) {
+        Ok(messages) => {
+            match models::message::MessageView::from_messages(&conversation_id, &user_id, messages.items, &conn) {
+                Ok(items) => {
+                    HttpResponse::Ok().json(models::pagination::Result {
+                        items,
+                        next_cursor: messages.next_cursor,
+                        prev_cursor: messages.prev_cursor,
+                        next_cursor_id: messages.next_cursor_id,
+                        prev_cursor_id: messages.prev_cursor_id
+                    })
+                }
+                Err(_) => {
+                    HttpResponse::InternalServerError().finish()
+                }
+            }
+        }
+        Err(diesel::result::Error::NotFound) => {
+            HttpResponse::NotFound().finish()
+        }
         Err(_) => {
             HttpResponse::InternalServerError().finish()
         }
@@ -51,51 +280,53 @@ pub async fn create_reaction(
     _: models::auth::Auth, 
     path: web::Path<(uuid::Uuid, uuid::Uuid, String)>, 
     req: HttpRequest, pool: web::Data<DbPool>,
-    emoji_map: web::Data<HashMap<String, String>>,
+    emoji_catalogue: web::Data<EmojiCatalogue>,
     ws_server: web::Data<Addr<ws_server::WsServer>>
 ) -> HttpResponse {
     let conn = pool.get().unwrap();
     let (message_id, conversation_id, emoji_name) = path.into_inner();
-    let emoji_map = emoji_map.into_inner();
-    let emoji_unicode = emoji_map.get(&emoji_name);
-    match emoji_unicode {
-        Some(unicode) => {
-            println!("unicode found {}", unicode);
-            let user_id = models::user::User::get_id_from_req(&req);
-            let member = models::member::Member::get_member_or_throw(&user_id.unwrap(), &conversation_id, &conn);
-            match member {
-                Ok(_) => {
-                    let result = models::message::Message::create_reaction(
-                        &user_id.unwrap(), &conversation_id, &message_id, &emoji_name, &conn
-                    );
-                    match result {
-                        Ok(affected_rows) => {
-                            if affected_rows > 0 {
-                                ws_server.into_inner().send(ws_server::ReactionUpdated {
-                                    conversation_id,
-                                    message_id,
-                                    user_id: user_id.unwrap(),
-                                    emoji_name,
-                                    update_type: ws_server::ReactionUpdateType::CREATED
-                                }).await.unwrap();
-                                return HttpResponse::build(http::StatusCode::OK).json(true);
-                            } else {
-                                return HttpResponse::build(http::StatusCode::OK).json(false);
-                            }
-                        }
-                        Err(_) => {
-                            HttpResponse::InternalServerError().finish()
-                        }
-                    }
-                }
-                Err(_) => {
-                    HttpResponse::InternalServerError().finish()
-                }
-            }
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    // Aliases are stored under the emoji's own name so they count together.
+    let emoji_name = match emoji_catalogue.canonical_name(&emoji_name) {
+        Some(canonical) => canonical,
+        None => match models::custom_emoji::CustomEmoji::find_usable(&conversation_id, &emoji_name, &conn) {
+            Ok(Some(_)) => emoji_name,
+            Ok(None) => return HttpResponse::BadRequest().finish(),
+            Err(_) => return HttpResponse::InternalServerError().finish()
+        }
+    };
+    match models::reaction::Reaction::create(&user_id, &conversation_id, &message_id, &emoji_name, &conn) {
+        Ok(true) => {
+            let count = match models::reaction::Reaction::count(&message_id, &emoji_name, &conn) {
+                Ok(count) => count,
+                Err(_) => return HttpResponse::InternalServerError().finish()
+            };
+            ws_server.into_inner().send(ws_server::ReactionUpdated {
+                conversation_id,
+                message_id,
+                user_id,
+                emoji_name,
+                count,
+                update_type: ws_server::ReactionUpdateType::CREATED
+            }).await.unwrap();
+            HttpResponse::Ok().json(true)
+        }
+        Ok(false) => {
+            HttpResponse::Ok().json(false)
         }
-        None => {
-            println!("unicode not found");
-            HttpResponse::BadRequest().finish()
+        Err(models::reaction::ReactionError::NotFound) => {
+            HttpResponse::NotFound().finish()
+        }
+        Err(models::reaction::ReactionError::LimitReached) => {
+            HttpResponse::UnprocessableEntity().json(ErrorResponse {
+                message: format!("a message can have at most {} different reactions", models::reaction::distinct_emoji_limit())
+            })
+        }
+        Err(models::reaction::ReactionError::Database(_)) => {
+            HttpResponse::InternalServerError().finish()
         }
     }
 }
@@ -105,39 +336,71 @@ pub async fn delete_reaction(
     _: models::auth::Auth, 
     path: web::Path<(uuid::Uuid, uuid::Uuid, String)>, 
     req: HttpRequest, pool: web::Data<DbPool>,
+    emoji_catalogue: web::Data<EmojiCatalogue>,
     ws_server: web::Data<Addr<ws_server::WsServer>>
 ) -> HttpResponse {
     let conn = pool.get().unwrap();
     let (message_id, conversation_id, emoji_name) = path.into_inner();
-    let user_id = models::user::User::get_id_from_req(&req);
-    let member = models::member::Member::get_member_or_throw(&user_id.unwrap(), &conversation_id, &conn);
-    match member {
-        Ok(_) => {
-            let result = models::message::Message::delete_reaction(
-                &user_id.unwrap(), &conversation_id, &message_id, &emoji_name, &conn
-            );
-            match result {
-                Ok(affected_rows) => {
-                    if affected_rows > 0 {
-                        ws_server.into_inner().send(ws_server::ReactionUpdated {
-                            conversation_id,
-                            message_id,
-                            user_id: user_id.unwrap(),
-                            emoji_name,
-                            update_type: ws_server::ReactionUpdateType::DELETED
-                        }).await.unwrap();
-                        return HttpResponse::Ok().json(true);
-                    } else {
-                        return HttpResponse::Ok().json(false);
-                    }
-                }
-                Err(err) => {
-                    println!("delete reaction error {}", err);
-                    HttpResponse::InternalServerError().finish()
-                }
+    let emoji_name = emoji_catalogue.canonical_name(&emoji_name).unwrap_or(emoji_name);
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    let result = models::reaction::Reaction::delete(&user_id, &conversation_id, &message_id, &emoji_name, &conn)
+        .and_then(|affected_rows| {
+            if affected_rows > 0 {
+                models::reaction::Reaction::count(&message_id, &emoji_name, &conn).map(Some)
+            } else {
+                Ok(None)
             }
+        });
+    match result {
+        Ok(Some(count)) => {
+            ws_server.into_inner().send(ws_server::ReactionUpdated {
+                conversation_id,
+                message_id,
+                user_id,
+                emoji_name,
+                count,
+                update_type: ws_server::ReactionUpdateType::DELETED
+            }).await.unwrap();
+            HttpResponse::Ok().json(true)
         }
-        _ => {
+        Ok(None) => {
+            HttpResponse::Ok().json(false)
+        }
+        Err(err) => {
+            log::error!("delete reaction error {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/message/{message_id}/conversation/{conversation_id}/reaction/{emoji_name}/users")]
+pub async fn get_reaction_users(
+    _: models::auth::Auth,
+    path: web::Path<(uuid::Uuid, uuid::Uuid, String)>,
+    req: HttpRequest, query: web::Query<models::pagination::Options>,
+    pool: web::Data<DbPool>,
+    emoji_catalogue: web::Data<EmojiCatalogue>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (message_id, conversation_id, emoji_name) = path.into_inner();
+    let emoji_name = emoji_catalogue.canonical_name(&emoji_name).unwrap_or(emoji_name);
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    match models::message::Message::fetch_by_id(&message_id, &conn) {
+        Ok(message) if message.conversation_id == conversation_id => {}
+        Ok(_) | Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    match models::reaction::Reaction::fetch_users(&conversation_id, &message_id, &emoji_name, &query.into_inner(), &conn) {
+        Ok(users) => {
+            HttpResponse::Ok().json(users)
+        }
+        Err(_) => {
             HttpResponse::InternalServerError().finish()
         }
     }
@@ -149,6 +412,7 @@ pub async fn get_messages_by_conversation(
     _: models::auth::Auth, 
     path: web::Path<uuid::Uuid>, 
     req: HttpRequest, query: web::Query<models::pagination::Options>, 
+    list_query: web::Query<models::message::MessageListQuery>,
     pool: web::Data<DbPool>
 ) -> HttpResponse {   
     let conn = pool.get().unwrap();
@@ -156,12 +420,28 @@ pub async fn get_messages_by_conversation(
     let user_id = models::user::User::get_id_from_req(&req);
     let member = models::member::Member::get_member_or_throw(&user_id.unwrap(), &conversation_id, &conn);
     let options = query.into_inner();
-    let messages = models::message::Message::fetch_by_conversation(&conversation_id,&options, &conn);
+    let messages = models::message::Message::fetch_by_conversation(&conversation_id,&options, list_query.around, &conn);
     match member {
         Ok(_) => {
             match messages {
                 Ok(messages) => {
-                    HttpResponse::Ok().json(messages)
+                    match models::message::MessageView::from_messages(&conversation_id, &user_id.unwrap(), messages.items, &conn) {
+                        Ok(items) => {
+                            HttpResponse::Ok().json(models::pagination::Result {
+                                items,
+                                next_cursor: messages.next_cursor,
+                                prev_cursor: messages.prev_cursor,
+                                next_cursor_id: messages.next_cursor_id,
+                                prev_cursor_id: messages.prev_cursor_id
+                            })
+                        }
+                        Err(_) => {
+                            HttpResponse::InternalServerError().finish()
+                        }
+                    }
+                }
+                Err(diesel::result::Error::NotFound) => {
+                    HttpResponse::NotFound().finish()
                 }
                 Err(_) => {
                     HttpResponse::InternalServerError().finish()
@@ -178,7 +458,8 @@ pub async fn get_messages_by_conversation(
 pub async fn create_message(_: models::auth::Auth ,
     input: web::Json<models::message::CreateMessageBody>, 
     req: HttpRequest, pool: web::Data<DbPool>, 
-    ws_server: web::Data<Addr<ws_server::WsServer>>
+    ws_server: web::Data<Addr<ws_server::WsServer>>,
+    link_preview_fetcher: web::Data<unfurl::LinkPreviewFetcher>
 ) -> HttpResponse {
     let conn = pool.get().unwrap();
     let user_id = models::user::User::get_id_from_req(&req).unwrap();
@@ -186,54 +467,263 @@ pub async fn create_message(_: models::auth::Auth ,
     let new_message = models::message::NewMessage::new(models::message::NewMessage {
         author_id: user_id,
         conversation_id: input.conversation_id,
-        content: input.content.to_owned(),
-        is_image: input.is_image
+        content: input.content.to_owned().filter(|text| !text.trim().is_empty()),
+        reply_to_id: input.reply_to_id,
+        thread_root_id: input.thread_root_id
     });
-    if message_content.is_none() {
-        return HttpResponse::BadRequest().finish();
-    } else if message_content.unwrap().trim().is_empty() {
+    // A message needs either text or at least one attachment; text is the caption otherwise.
+    let has_text = message_content.is_some_and(|text| !text.trim().is_empty());
+    if !has_text && input.attachments.is_empty() {
         return HttpResponse::BadRequest().finish();
     }
+    let mut attachment_bodies = input.attachments.clone();
+    match models::upload::Upload::fill_attachment_bodies(&user_id, &mut attachment_bodies, &conn) {
+        Ok(errors) if !errors.is_empty() => return HttpResponse::BadRequest().json(errors),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    let errors = models::attachment::AttachmentBody::validate(&attachment_bodies);
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors);
+    }
     let member = models::member::Member::get_member_or_throw(&user_id, &input.conversation_id, &conn);
     
     match member {
-        Ok(_) => {
-            let message = models::message::Message::insert_one(&new_message, &conn);
+        Ok(member) => {
+            let reply_to = match input.reply_to_id {
+                Some(reply_to_id) => {
+                    match models::message::Message::fetch_reply_preview(&reply_to_id, &input.conversation_id, &conn) {
+                        Ok(reply_to) => Some(reply_to),
+                        Err(diesel::result::Error::NotFound) => {
+                            let errors = vec![ErrorField {path: String::from("reply_to_id"), messages: vec![String::from("replied message is not in this conversation")]}];
+                            return HttpResponse::BadRequest().json(errors);
+                        }
+                        Err(_) => return HttpResponse::InternalServerError().finish()
+                    }
+                }
+                None => None
+            };
+            // Thread replies stay out of the main timeline, so they don't touch last_message.
+            if let Some(root_id) = input.thread_root_id {
+                match models::message::Message::fetch_thread_root(&root_id, &input.conversation_id, &conn) {
+                    Ok(_) => {}
+                    Err(diesel::result::Error::NotFound) => {
+                        let errors = vec![ErrorField {path: String::from("thread_root_id"), messages: vec![String::from("thread root is not a top-level message in this conversation")]}];
+                        return HttpResponse::BadRequest().json(errors);
+                    }
+                    Err(_) => return HttpResponse::InternalServerError().finish()
+                }
+            }
+            let message = models::message::Message::create(&new_message, &attachment_bodies, &conn);
             match message {
-                Ok(message) => {
+                Ok((message, attachments, thread)) => {
                     println!("get message");
                     let author = models::user::User::find_by_id(&user_id, &conn);
                     match author {
                         Ok(author) => {
-                            models::conversation::Conversation::update_last_message(
-                                &message.conversation_id, 
-                                &message.id, &models::conversation::LastMessageDisplay {
-                                    content: message.content.to_owned(),
-                                    user_id: Some(message.author_id),
-                                    created_at: Some(message.created_at),
-                                    user_name: Some(author.username.to_owned())
-                                }, &conn).expect("update last message failure");
-                            #[allow(dead_code)]
-                            ws_server.into_inner().send(ws_server::NewMessage {
+                            let author_identity = models::member::MemberIdentity::resolve(&author, Some(&member));
+                            // The message is already saved, so it still goes out without its mentions.
+                            let mentions = models::mention::Mention::create_for_message(&message, &conn)
+                                .unwrap_or_else(|e| {
+                                    log::error!("creating mentions for message {} failed: {}", message.id, e);
+                                    Vec::new()
+                                });
+                            let ws_server = ws_server.into_inner();
+                            announce_new_message(ws_server::NewMessage {
                                 message: message.clone(),
-                                author: author
-                            }).await.unwrap();
+                                author,
+                                author_identity: author_identity.clone(),
+                                reply_to: reply_to.clone(),
+                                attachments: attachments.clone(),
+                                thread,
+                                forwarded_from: None
+                            }, &ws_server, &conn).await;
+                            if !mentions.is_empty() {
+                                ws_server.send(ws_server::Mentioned {
+                                    message: message.clone(),
+                                    author_identity: author_identity.clone(),
+                                    attachments: attachments.clone(),
+                                    mentions
+                                }).await.unwrap();
+                            }
+                            // Sending from one device clears the draft on the others.
+                            if let Ok(affected_rows) = models::draft::Draft::delete_one(&user_id, &message.conversation_id, &conn) {
+                                if affected_rows > 0 {
+                                    ws_server.do_send(ws_server::DraftUpdated {
+                                        user_id,
+                                        conversation_id: message.conversation_id,
+                                        draft: None
+                                    });
+                                }
+                            }
+                            unfurl::spawn_for_message(
+                                message.clone(), false, link_preview_fetcher.into_inner(), pool.get_ref().clone(), (*ws_server).clone()
+                            );
+                            HttpResponse::Ok().json(models::message::MessageView {
+                                message,
+                                author_identity: Some(author_identity),
+                                reply_to,
+                                attachments,
+                                link_previews: Vec::new(),
+                                reactions: Vec::new(),
+                                forwarded_from: None
+                            })
                         },
-                        _ => {}
+                        _ => {
+                            HttpResponse::Ok().json(models::message::MessageView {
+                                message,
+                                author_identity: None,
+                                reply_to,
+                                attachments,
+                                link_previews: Vec::new(),
+                                reactions: Vec::new(),
+                                forwarded_from: None
+                            })
+                        }
                     }
-                    HttpResponse::Ok().json(message)
                 }
                 Err(_) => {
                     HttpResponse::InternalServerError().finish()
                 }
             }
         }
-        _ => {
+        // Kicked and banned members are no longer members.
+        Err(diesel::result::Error::NotFound) => {
+            PermissionError::NotMember.error_response()
+        }
+        Err(_) => {
             HttpResponse::InternalServerError().finish()
         }
     }
 }
 
+/// Publishes a freshly inserted message: top-level messages become the conversation's last
+/// message, then the other members get `new_message`. The message is already saved, so it
+/// still goes out if the last message can't be updated.
+pub(crate) async fn announce_new_message(
+    event: ws_server::NewMessage,
+    ws_server: &Addr<ws_server::WsServer>,
+    conn: &PgConnection
+) {
+    // Thread replies stay out of the main timeline, so they don't touch last_message.
+    if event.thread.is_none() {
+        if let Err(e) = models::conversation::Conversation::update_last_message(
+            &event.message.conversation_id,
+            &event.message.id, &models::conversation::LastMessageDisplay {
+                content: event.message.plain_text(),
+                user_id: Some(event.message.author_id),
+                created_at: Some(event.message.created_at),
+                user_name: Some(event.author_identity.display_name.to_owned()),
+                attachments: event.attachments.clone()
+            }, conn) {
+            log::error!("updating the last message of {} failed: {}", event.message.conversation_id, e);
+        }
+    }
+    ws_server.send(event).await.unwrap();
+}
+
+#[post("/message/{message_id}/conversation/{conversation_id}/forward")]
+pub async fn forward_message(
+    _: models::auth::Auth,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    input: web::Json<models::message::ForwardMessageBody>,
+    req: HttpRequest, pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>,
+    link_preview_fetcher: web::Data<unfurl::LinkPreviewFetcher>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (message_id, conversation_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    let source = match models::message::Message::fetch_by_id(&message_id, &conn) {
+        Ok(message) if message.conversation_id == conversation_id && !message.is_deleted => message,
+        Ok(_) | Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    let mut target_ids: Vec<uuid::Uuid> = Vec::new();
+    for target_id in input.conversation_ids.iter() {
+        if !target_ids.contains(target_id) {
+            target_ids.push(*target_id);
+        }
+    }
+    if target_ids.is_empty() || target_ids.len() > models::message::MAX_FORWARD_TARGETS {
+        let errors = vec![ErrorField {path: String::from("conversation_ids"), messages: vec![
+            format!("forward to between 1 and {} conversations", models::message::MAX_FORWARD_TARGETS)
+        ]}];
+        return HttpResponse::BadRequest().json(errors);
+    }
+    let mut targets: Vec<models::member::Member> = Vec::new();
+    for target_id in target_ids.iter() {
+        match models::member::Member::get_member_or_throw(&user_id, target_id, &conn) {
+            Ok(member) => targets.push(member),
+            Err(diesel::result::Error::NotFound) => return PermissionError::NotMember.error_response(),
+            Err(_) => return HttpResponse::InternalServerError().finish()
+        }
+    }
+    let author = match models::user::User::find_by_id(&user_id, &conn) {
+        Ok(author) => author,
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    let source_view = match models::message::MessageView::from_messages(&conversation_id, &user_id, vec![source.clone()], &conn) {
+        Ok(mut views) => views.remove(0),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    // Whoever forwards can see the source, so they always get the original conversation and author back.
+    let forwarded_from = match source_view.forwarded_from {
+        Some(provenance) => provenance,
+        None => models::forward::Provenance::Known {
+            message_id: Some(source.id),
+            conversation_id: source.conversation_id,
+            conversation_name: match models::conversation::Conversation::fetch_by_id(&source.conversation_id, &conn) {
+                Ok(conversation) => conversation.name,
+                Err(_) => return HttpResponse::InternalServerError().finish()
+            },
+            author: source_view.author_identity
+        }
+    };
+    let ws_server = ws_server.into_inner();
+    let link_preview_fetcher = link_preview_fetcher.into_inner();
+    // Either every target gets its copy or none does, so a retry never duplicates messages.
+    let copies = conn.transaction(|| {
+        targets.iter()
+            .map(|member| models::message::Message::forward(&source, &member.conversation_id, &user_id, &conn))
+            .collect::<diesel::QueryResult<Vec<_>>>()
+    });
+    let copies = match copies {
+        Ok(copies) => copies,
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    let mut views: Vec<models::message::MessageView> = Vec::new();
+    for (member, (message, attachments)) in targets.iter().zip(copies) {
+        let author_identity = models::member::MemberIdentity::resolve(&author, Some(member));
+        announce_new_message(ws_server::NewMessage {
+            message: message.clone(),
+            author: author.clone(),
+            author_identity: author_identity.clone(),
+            reply_to: None,
+            attachments: attachments.clone(),
+            thread: None,
+            forwarded_from: Some(forwarded_from.clone())
+        }, &ws_server, &conn).await;
+        unfurl::spawn_for_message(
+            message.clone(), false, link_preview_fetcher.clone(), pool.get_ref().clone(), (*ws_server).clone()
+        );
+        views.push(models::message::MessageView {
+            message,
+            author_identity: Some(author_identity),
+            reply_to: None,
+            attachments,
+            link_previews: Vec::new(),
+            reactions: Vec::new(),
+            forwarded_from: Some(forwarded_from.clone())
+        });
+    }
+    HttpResponse::Ok().json(views)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchGiphyQuery {
     q: String
@@ -1,4 +1,10 @@
 pub mod auth;
 pub mod user;
 pub mod conversation;
-pub mod message;
\ No newline at end of file
+pub mod message;
+pub mod invite;
+pub mod media;
+pub mod emoji;
+pub mod pin;
+pub mod scheduled_message;
+pub mod draft;
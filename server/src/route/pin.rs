@@ -0,0 +1,119 @@
+use actix_web::{
+   get, put, delete, web, HttpRequest, HttpResponse
+};
+use actix::*;
+use crate::lib::{DbPool, ErrorResponse};
+use crate::models;
+use crate::models::permission::{Permission, PermissionError};
+use crate::models::pin::{PinError, PinnedMessage};
+use crate::ws_server;
+
+#[get("/conversation/{conversation_id}/pins")]
+pub async fn get_pinned_messages(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let conversation_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if models::member::Member::get_member_or_throw(&user_id, &conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    match PinnedMessage::fetch_by_conversation(&conversation_id, &user_id, &conn) {
+        Ok(pins) => {
+            HttpResponse::Ok().json(pins)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[put("/message/{message_id}/conversation/{conversation_id}/pin")]
+pub async fn pin_message(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (message_id, conversation_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if let Err(e) = models::member::Member::check_permission(&user_id, &conversation_id, Permission::PinMessages, &conn) {
+        return e.error_response();
+    }
+    match PinnedMessage::create(&user_id, &conversation_id, &message_id, &conn) {
+        Ok(Some(pin)) => {
+            let message = match models::message::Message::fetch_by_id(&message_id, &conn) {
+                Ok(message) => message,
+                Err(_) => return HttpResponse::InternalServerError().finish()
+            };
+            ws_server.into_inner().send(ws_server::MessagePinned {
+                conversation_id,
+                pin,
+                message
+            }).await.unwrap();
+            HttpResponse::Ok().json(true)
+        }
+        Ok(None) => {
+            HttpResponse::Ok().json(false)
+        }
+        Err(PinError::NotFound) => {
+            HttpResponse::NotFound().finish()
+        }
+        Err(PinError::LimitReached) => {
+            HttpResponse::UnprocessableEntity().json(ErrorResponse {
+                message: format!("a conversation can have at most {} pinned messages", models::pin::limit())
+            })
+        }
+        Err(PinError::Database(_)) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Members may unpin what they pinned themselves; other pins need `Permission::ManagePins`.
+#[delete("/message/{message_id}/conversation/{conversation_id}/pin")]
+pub async fn unpin_message(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    pool: web::Data<DbPool>,
+    ws_server: web::Data<Addr<ws_server::WsServer>>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let (message_id, conversation_id) = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    let member = match models::member::Member::check_permission(&user_id, &conversation_id, Permission::PinMessages, &conn) {
+        Ok(member) => member,
+        Err(e) => return e.error_response()
+    };
+    match PinnedMessage::find(&message_id, &conversation_id, &conn) {
+        Ok(pin) if pin.pinned_by == user_id || member.role().can(Permission::ManagePins) => {}
+        Ok(_) => return PermissionError::Forbidden.error_response(),
+        Err(diesel::result::Error::NotFound) => return HttpResponse::Ok().json(false),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    match PinnedMessage::delete(&message_id, &conversation_id, &conn) {
+        Ok(affected_rows) if affected_rows > 0 => {
+            ws_server.into_inner().send(ws_server::MessageUnpinned {
+                conversation_id,
+                message_id,
+                unpinned_by: user_id
+            }).await.unwrap();
+            HttpResponse::Ok().json(true)
+        }
+        Ok(_) => {
+            HttpResponse::Ok().json(false)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
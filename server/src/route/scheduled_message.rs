@@ -0,0 +1,141 @@
+use actix_web::{
+   get, post, patch, delete, web, HttpRequest, HttpResponse
+};
+use crate::lib::{DbPool, ErrorField};
+use crate::models;
+use crate::models::permission::PermissionError;
+use crate::models::scheduled_message::{
+    self, EditScheduledMessageBody, ScheduleMessageBody, ScheduledMessage, ScheduledMessageListQuery
+};
+
+#[post("/scheduled-messages")]
+pub async fn schedule_message(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    input: web::Json<ScheduleMessageBody>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    if models::member::Member::get_member_or_throw(&user_id, &input.conversation_id, &conn).is_err() {
+        return PermissionError::NotMember.error_response();
+    }
+    let text = scheduled_message::normalize_content(input.content.as_deref());
+    let mut errors = scheduled_message::validate(text.as_deref(), !input.attachments.is_empty(), &input.send_at);
+    // Uploads are resolved again on delivery; this only reports bad attachments early.
+    let mut attachment_bodies = input.attachments.clone();
+    match models::upload::Upload::fill_attachment_bodies(&user_id, &mut attachment_bodies, &conn) {
+        Ok(upload_errors) => errors.extend(upload_errors),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    if errors.is_empty() {
+        errors.extend(models::attachment::AttachmentBody::validate(&attachment_bodies));
+    }
+    match ScheduledMessage::count_pending(&user_id, &conn) {
+        Ok(count) if count >= scheduled_message::limit() => {
+            errors.push(ErrorField {path: String::from("send_at"), messages: vec![
+                format!("there can be at most {} scheduled messages", scheduled_message::limit())
+            ]});
+        }
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    }
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors);
+    }
+    match ScheduledMessage::insert_one(&user_id, &input, &conn) {
+        Ok(scheduled) => {
+            HttpResponse::Created().json(scheduled)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// The caller's messages that have not gone out yet, soonest first.
+#[get("/scheduled-messages")]
+pub async fn get_scheduled_messages(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    query: web::Query<ScheduledMessageListQuery>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    match ScheduledMessage::fetch_pending(&user_id, query.conversation_id.as_ref(), &conn) {
+        Ok(scheduled) => {
+            HttpResponse::Ok().json(scheduled)
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[patch("/scheduled-messages/{scheduled_message_id}")]
+pub async fn edit_scheduled_message(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    input: web::Json<EditScheduledMessageBody>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let scheduled_message_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    let scheduled = match ScheduledMessage::find_pending(&scheduled_message_id, &user_id, &conn) {
+        Ok(scheduled) => scheduled,
+        Err(diesel::result::Error::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    let text = match &input.content {
+        Some(text) => scheduled_message::normalize_content(Some(text)),
+        None => scheduled.content.to_owned()
+    };
+    let send_at = input.send_at.unwrap_or(scheduled.send_at);
+    let has_attachments = scheduled.attachments.as_array().is_some_and(|bodies| !bodies.is_empty());
+    let errors = scheduled_message::validate(text.as_deref(), has_attachments, &send_at);
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors);
+    }
+    match ScheduledMessage::update(&scheduled_message_id, &user_id, text, &send_at, &conn) {
+        Ok(scheduled) => {
+            HttpResponse::Ok().json(scheduled)
+        }
+        // Delivered while the edit was being checked.
+        Err(diesel::result::Error::NotFound) => {
+            HttpResponse::NotFound().finish()
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[delete("/scheduled-messages/{scheduled_message_id}")]
+pub async fn cancel_scheduled_message(
+    _: models::auth::Auth,
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    pool: web::Data<DbPool>
+) -> HttpResponse {
+    let conn = pool.get().unwrap();
+    let scheduled_message_id = path.into_inner();
+    let user_id = models::user::User::get_id_from_req(&req).unwrap();
+
+    match ScheduledMessage::delete_one(&scheduled_message_id, &user_id, &conn) {
+        Ok(affected_rows) if affected_rows > 0 => {
+            HttpResponse::Ok().json(true)
+        }
+        Ok(_) => {
+            HttpResponse::NotFound().finish()
+        }
+        Err(_) => {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
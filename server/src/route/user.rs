@@ -1,13 +1,12 @@
 use actix_web::{
   web, HttpRequest, get, HttpResponse, post
 };
-use crate::lib::{DbPool, ErrorField};
+use crate::lib::{DbPool, ErrorField, ErrorResponse};
 use serde::{Serialize, Deserialize};
 use crate::models;
+use crate::storage::cloudinary;
 use chrono::Utc;
-use sha1::{Sha1, Digest};
 use std::env;
-use std::{fmt::Write};
 
 #[get("/user/list")]
 pub async fn list_user(
@@ -30,14 +29,6 @@ pub struct ChangeAvatarBody {
     pub avatar_url: String
 }
 
-pub fn encode_hex(bytes: &[u8]) -> String {
-    let mut s = String::with_capacity(bytes.len() * 2);
-    for &b in bytes {
-        write!(&mut s, "{:02x}", b).unwrap();
-    }
-    s
-}
-
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all="camelCase")]
 pub struct SignedSignature {
@@ -46,17 +37,18 @@ pub struct SignedSignature {
     pub timestamp: i64,
 }
 
+/// Signs a direct browser-to-Cloudinary upload. Prefer `/media/upload`, which works with
+/// every storage backend and enforces size, type and quota limits.
 #[get("/image/signed-signature")]
-pub async fn get_signed_signature() -> HttpResponse {
-    let cloudinary_api_key = env::var("CLOUDINARY_API_KEY").expect("CLOUDINARY_API_KEY is not set in .env file");
-    let cloudinary_api_secret = env::var("CLOUDINARY_API_SECRET").expect("CLOUDINARY_API_SECRET is not set in .env file");
+pub async fn get_signed_signature(_: models::auth::Auth) -> HttpResponse {
+    let (cloudinary_api_key, cloudinary_api_secret) = match (env::var("CLOUDINARY_API_KEY"), env::var("CLOUDINARY_API_SECRET")) {
+        (Ok(api_key), Ok(api_secret)) => (api_key, api_secret),
+        _ => {
+            return HttpResponse::NotFound().json(ErrorResponse {message: String::from("cloudinary uploads are not configured")});
+        }
+    };
     let timestamp = Utc::now().timestamp();
-    println!("timestamp {}, cloudinary_api_secret {}", timestamp, cloudinary_api_secret);
-    let signed_params = format!("folder=tinychat&timestamp={}{}",timestamp, cloudinary_api_secret);
-    let mut hasher = Sha1::new();
-    hasher.update(signed_params.as_bytes());
-    let hashed_signature: [u8; 20] = hasher.finalize().as_slice().try_into().expect("wrong length");
-    let signature = encode_hex(&hashed_signature);
+    let signature = cloudinary::sign(&format!("folder=tinychat&timestamp={}", timestamp), &cloudinary_api_secret);
     let signed_signature_resp = SignedSignature {
         signature,
         api_key: cloudinary_api_key,
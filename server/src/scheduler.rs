@@ -0,0 +1,127 @@
+use actix::Addr;
+use diesel::{PgConnection, QueryResult};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::lib::DbPool;
+use crate::models;
+use crate::models::scheduled_message::{DeliveredMessage, Delivery, ScheduledMessage};
+use crate::route::message::announce_new_message;
+use crate::unfurl::{self, LinkPreviewFetcher};
+use crate::ws_server;
+
+const DEFAULT_POLL_SECONDS: u64 = 5;
+
+/// Delivers scheduled messages as they come due.
+pub struct Scheduler {
+	pool: DbPool,
+	ws_server: Addr<ws_server::WsServer>,
+	fetcher: Arc<LinkPreviewFetcher>
+}
+
+impl Scheduler {
+	pub fn new(pool: DbPool, ws_server: Addr<ws_server::WsServer>, fetcher: Arc<LinkPreviewFetcher>) -> Scheduler {
+		Scheduler { pool, ws_server, fetcher }
+	}
+
+	/// Looks for due messages every `SCHEDULED_MESSAGE_POLL_SECONDS` (5 by default). Pending messages
+	/// live in the database, so whatever came due while the server was down goes out on the first
+	/// tick after a restart.
+	pub fn spawn(self) {
+		let seconds = env::var("SCHEDULED_MESSAGE_POLL_SECONDS")
+			.ok()
+			.and_then(|value| value.parse::<u64>().ok())
+			.unwrap_or(DEFAULT_POLL_SECONDS)
+			.max(1);
+		actix_web::rt::spawn(async move {
+			let mut interval = actix_web::rt::time::interval(Duration::from_secs(seconds));
+			loop {
+				interval.tick().await;
+				self.deliver_due().await;
+			}
+		});
+	}
+
+	async fn deliver_due(&self) {
+		let conn = match self.pool.get() {
+			Ok(conn) => conn,
+			Err(e) => {
+				log::error!("no connection for scheduled messages: {}", e);
+				return;
+			}
+		};
+		loop {
+			match ScheduledMessage::deliver_next(&conn) {
+				Ok(Some(Delivery::Delivered(delivered))) => {
+					let message_id = delivered.message.id;
+					if let Err(e) = self.announce(*delivered, &conn).await {
+						log::error!("announcing scheduled message {} failed: {}", message_id, e);
+					}
+				}
+				Ok(Some(Delivery::Dropped(scheduled))) => {
+					log::info!("dropped scheduled message {} of {}", scheduled.id, scheduled.author_id);
+					self.ws_server.do_send(ws_server::ScheduledMessageDropped {
+						scheduled_message_id: scheduled.id,
+						conversation_id: scheduled.conversation_id,
+						author_id: scheduled.author_id
+					});
+				}
+				Ok(Some(Delivery::Failed { scheduled, error, gave_up })) => {
+					if gave_up {
+						log::error!("giving up on scheduled message {}: {}", scheduled.id, error);
+						self.ws_server.do_send(ws_server::ScheduledMessageDropped {
+							scheduled_message_id: scheduled.id,
+							conversation_id: scheduled.conversation_id,
+							author_id: scheduled.author_id
+						});
+					} else {
+						log::warn!("postponing scheduled message {} after a failed delivery: {}", scheduled.id, error);
+					}
+				}
+				Ok(None) => return,
+				Err(e) => {
+					log::error!("delivering scheduled messages failed: {}", e);
+					return;
+				}
+			}
+		}
+	}
+
+	/// Does for a delivered message what `create_message` does after the insert.
+	async fn announce(&self, delivered: DeliveredMessage, conn: &PgConnection) -> QueryResult<()> {
+		let DeliveredMessage { scheduled_message_id, message, attachments, member } = delivered;
+		let author = models::user::User::find_by_id(&message.author_id, conn)?;
+		let author_identity = models::member::MemberIdentity::resolve(&author, Some(&member));
+		// The message is already saved, so it still goes out without its mentions.
+		let mentions = models::mention::Mention::create_for_message(&message, conn)
+			.unwrap_or_else(|e| {
+				log::error!("creating mentions for message {} failed: {}", message.id, e);
+				Vec::new()
+			});
+		announce_new_message(ws_server::NewMessage {
+			message: message.clone(),
+			author,
+			author_identity: author_identity.clone(),
+			reply_to: None,
+			attachments: attachments.clone(),
+			thread: None,
+			forwarded_from: None
+		}, &self.ws_server, conn).await;
+		if !mentions.is_empty() {
+			self.ws_server.do_send(ws_server::Mentioned {
+				message: message.clone(),
+				author_identity: author_identity.clone(),
+				attachments: attachments.clone(),
+				mentions
+			});
+		}
+		self.ws_server.do_send(ws_server::ScheduledMessageSent {
+			scheduled_message_id,
+			message: message.clone(),
+			author_identity,
+			attachments
+		});
+		unfurl::spawn_for_message(message, false, self.fetcher.clone(), self.pool.clone(), self.ws_server.clone());
+		Ok(())
+	}
+}
@@ -1,3 +1,22 @@
+table! {
+    attachments (id) {
+        id -> Uuid,
+        message_id -> Uuid,
+        kind -> Text,
+        url -> Text,
+        mime_type -> Nullable<Text>,
+        size -> Nullable<Int8>,
+        width -> Nullable<Int4>,
+        height -> Nullable<Int4>,
+        filename -> Nullable<Text>,
+        position -> Int4,
+        created_at -> Timestamptz,
+        upload_id -> Nullable<Uuid>,
+        blurhash -> Nullable<Text>,
+        thumbnails -> Jsonb,
+    }
+}
+
 table! {
     conversations (id) {
         id -> Uuid,
@@ -8,6 +27,66 @@ table! {
         created_at -> Timestamptz,
         is_group -> Bool,
         withs -> Nullable<Jsonb>,
+        join_approval -> Bool,
+    }
+}
+
+table! {
+    custom_emojis (id) {
+        id -> Uuid,
+        name -> Text,
+        conversation_id -> Nullable<Uuid>,
+        upload_id -> Uuid,
+        created_by -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    drafts (user_id, conversation_id) {
+        user_id -> Uuid,
+        conversation_id -> Uuid,
+        content -> Text,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    invites (id) {
+        id -> Uuid,
+        code -> Varchar,
+        conversation_id -> Uuid,
+        created_by -> Uuid,
+        expires_at -> Nullable<Timestamptz>,
+        max_uses -> Nullable<Int4>,
+        uses -> Int4,
+        is_revoked -> Bool,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    join_requests (conversation_id, user_id) {
+        conversation_id -> Uuid,
+        user_id -> Uuid,
+        invite_id -> Nullable<Uuid>,
+        status -> Text,
+        created_at -> Timestamptz,
+        resolved_by -> Nullable<Uuid>,
+        resolved_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    link_previews (id) {
+        id -> Uuid,
+        url -> Text,
+        status -> Text,
+        title -> Nullable<Text>,
+        description -> Nullable<Text>,
+        image_url -> Nullable<Text>,
+        site_name -> Nullable<Text>,
+        fetched_at -> Timestamptz,
     }
 }
 
@@ -21,6 +100,35 @@ table! {
         avatar -> Nullable<Varchar>,
         is_kicked -> Bool,
         is_banned -> Bool,
+        role -> Text,
+        is_archived -> Bool,
+    }
+}
+
+table! {
+    message_link_previews (message_id, link_preview_id) {
+        message_id -> Uuid,
+        link_preview_id -> Uuid,
+        position -> Int4,
+    }
+}
+
+table! {
+    message_mentions (message_id, user_id) {
+        message_id -> Uuid,
+        user_id -> Uuid,
+        conversation_id -> Uuid,
+        kind -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    message_edits (id) {
+        id -> Uuid,
+        message_id -> Uuid,
+        content -> Nullable<Text>,
+        edited_at -> Timestamptz,
     }
 }
 
@@ -33,8 +141,67 @@ table! {
         is_deleted -> Bool,
         created_at -> Timestamptz,
         updated_at -> Nullable<Timestamptz>,
-        reactions -> Jsonb,
-        is_image -> Bool,
+        reply_to_id -> Nullable<Uuid>,
+        thread_root_id -> Nullable<Uuid>,
+        thread_reply_count -> Int4,
+        thread_last_reply_at -> Nullable<Timestamptz>,
+        content_ast -> Nullable<Jsonb>,
+        is_forwarded -> Bool,
+        forwarded_from_message_id -> Nullable<Uuid>,
+        forwarded_from_conversation_id -> Nullable<Uuid>,
+        forwarded_from_author_id -> Nullable<Uuid>,
+    }
+}
+
+table! {
+    pinned_messages (message_id) {
+        message_id -> Uuid,
+        conversation_id -> Uuid,
+        pinned_by -> Uuid,
+        pinned_at -> Timestamptz,
+    }
+}
+
+table! {
+    reactions (message_id, user_id, emoji) {
+        message_id -> Uuid,
+        user_id -> Uuid,
+        emoji -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    scheduled_messages (id) {
+        id -> Uuid,
+        conversation_id -> Uuid,
+        author_id -> Uuid,
+        content -> Nullable<Text>,
+        attachments -> Jsonb,
+        send_at -> Timestamptz,
+        created_at -> Timestamptz,
+        updated_at -> Nullable<Timestamptz>,
+        failed_attempts -> Int4,
+    }
+}
+
+table! {
+    uploads (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        backend -> Text,
+        storage_key -> Text,
+        url -> Nullable<Text>,
+        mime_type -> Text,
+        size -> Int8,
+        filename -> Nullable<Text>,
+        purpose -> Text,
+        created_at -> Timestamptz,
+        width -> Nullable<Int4>,
+        height -> Nullable<Int4>,
+        blurhash -> Nullable<Text>,
+        thumbnails -> Jsonb,
+        used_as_emoji -> Bool,
     }
 }
 
@@ -51,14 +218,53 @@ table! {
     }
 }
 
+joinable!(attachments -> messages (message_id));
+joinable!(attachments -> uploads (upload_id));
+joinable!(custom_emojis -> conversations (conversation_id));
+joinable!(custom_emojis -> uploads (upload_id));
+joinable!(custom_emojis -> users (created_by));
+joinable!(drafts -> conversations (conversation_id));
+joinable!(drafts -> users (user_id));
+joinable!(invites -> conversations (conversation_id));
+joinable!(invites -> users (created_by));
+joinable!(join_requests -> conversations (conversation_id));
+joinable!(join_requests -> invites (invite_id));
+joinable!(join_requests -> users (user_id));
 joinable!(members -> conversations (conversation_id));
 joinable!(members -> users (user_id));
+joinable!(message_edits -> messages (message_id));
+joinable!(message_link_previews -> link_previews (link_preview_id));
+joinable!(message_link_previews -> messages (message_id));
+joinable!(message_mentions -> conversations (conversation_id));
+joinable!(message_mentions -> messages (message_id));
+joinable!(message_mentions -> users (user_id));
 joinable!(messages -> conversations (conversation_id));
 joinable!(messages -> users (author_id));
+joinable!(pinned_messages -> conversations (conversation_id));
+joinable!(pinned_messages -> messages (message_id));
+joinable!(pinned_messages -> users (pinned_by));
+joinable!(reactions -> messages (message_id));
+joinable!(reactions -> users (user_id));
+joinable!(scheduled_messages -> conversations (conversation_id));
+joinable!(scheduled_messages -> users (author_id));
+joinable!(uploads -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
+    attachments,
     conversations,
+    custom_emojis,
+    drafts,
+    invites,
+    join_requests,
+    link_previews,
     members,
+    message_edits,
+    message_link_previews,
+    message_mentions,
     messages,
+    pinned_messages,
+    reactions,
+    scheduled_messages,
+    uploads,
     users,
 );
@@ -137,6 +137,23 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketSession {
 							})
 							.wait(ctx)
 					},
+					"mark_read" => {
+						let user_id = match self.user_id {
+							Some(user_id) => user_id,
+							None => {
+								ctx.text(format!("!!! not authenticated: {:?}", m.op));
+								return;
+							}
+						};
+						match serde_json::from_value::<ws_server::MarkRead>(m.d) {
+							Ok(mark_read) => {
+								self.addr.do_send(ws_server::MarkRead { user_id, ..mark_read });
+							}
+							Err(_) => {
+								ctx.text(format!("!!! invalid payload: {:?}", m.op));
+							}
+						}
+					},
 					_ => {
 						ctx.text(format!("!!! unknown command: {:?}", m.op));
 					}
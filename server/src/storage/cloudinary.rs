@@ -0,0 +1,119 @@
+use chrono::Utc;
+use serde::Deserialize;
+use sha1::{Sha1, Digest};
+use std::env;
+use std::fmt::Write;
+use super::{Storage, StorageError, StorageFuture, StoredContent, StoredObject};
+
+/// Stores uploads in Cloudinary. Needs `CLOUDINARY_CLOUD_NAME`, `CLOUDINARY_API_KEY` and
+/// `CLOUDINARY_API_SECRET`; `CLOUDINARY_FOLDER` defaults to `tinychat`.
+pub struct CloudinaryStorage {
+	cloud_name: String,
+	api_key: String,
+	api_secret: String,
+	folder: String,
+	client: reqwest::Client
+}
+
+#[derive(Deserialize)]
+struct UploadResponse {
+	public_id: String,
+	secure_url: String,
+	resource_type: String
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+	let mut s = String::with_capacity(bytes.len() * 2);
+	for &b in bytes {
+		write!(&mut s, "{:02x}", b).unwrap();
+	}
+	s
+}
+
+/// Signs alphabetically sorted `key=value&...` parameters the way Cloudinary expects.
+pub fn sign(params: &str, api_secret: &str) -> String {
+	let mut hasher = Sha1::new();
+	hasher.update(format!("{}{}", params, api_secret).as_bytes());
+	encode_hex(hasher.finalize().as_slice())
+}
+
+impl CloudinaryStorage {
+	pub fn from_env() -> CloudinaryStorage {
+		CloudinaryStorage {
+			cloud_name: env::var("CLOUDINARY_CLOUD_NAME").expect("CLOUDINARY_CLOUD_NAME is not set in .env file"),
+			api_key: env::var("CLOUDINARY_API_KEY").expect("CLOUDINARY_API_KEY is not set in .env file"),
+			api_secret: env::var("CLOUDINARY_API_SECRET").expect("CLOUDINARY_API_SECRET is not set in .env file"),
+			folder: env::var("CLOUDINARY_FOLDER").unwrap_or_else(|_| String::from("tinychat")),
+			client: reqwest::Client::new()
+		}
+	}
+
+	fn endpoint(&self, resource_type: &str, action: &str) -> String {
+		format!("https://api.cloudinary.com/v1_1/{}/{}/{}", self.cloud_name, resource_type, action)
+	}
+}
+
+fn remote_error(e: reqwest::Error) -> StorageError {
+	StorageError::Remote(e.to_string())
+}
+
+impl Storage for CloudinaryStorage {
+	fn backend(&self) -> &'static str {
+		"cloudinary"
+	}
+
+	fn put<'a>(&'a self, _key: &'a str, mime_type: &'a str, data: Vec<u8>) -> StorageFuture<'a, StoredObject> {
+		Box::pin(async move {
+			let timestamp = Utc::now().timestamp().to_string();
+			let signature = sign(&format!("folder={}&timestamp={}", self.folder, timestamp), &self.api_secret);
+			let file = format!("data:{};base64,{}", mime_type, base64::encode(&data));
+			let response = self.client
+				.post(self.endpoint("auto", "upload"))
+				.form(&[
+					("file", file.as_str()),
+					("folder", self.folder.as_str()),
+					("timestamp", timestamp.as_str()),
+					("api_key", self.api_key.as_str()),
+					("signature", signature.as_str())
+				])
+				.send().await.map_err(remote_error)?
+				.error_for_status().map_err(remote_error)?
+				.json::<UploadResponse>().await.map_err(remote_error)?;
+			Ok(StoredObject {
+				key: format!("{}/{}", response.resource_type, response.public_id),
+				url: Some(response.secure_url)
+			})
+		})
+	}
+
+	fn get<'a>(&'a self, object: &'a StoredObject) -> StorageFuture<'a, StoredContent> {
+		Box::pin(async move {
+			match &object.url {
+				Some(url) => Ok(StoredContent::Redirect(url.to_owned())),
+				None => Err(StorageError::NotFound)
+			}
+		})
+	}
+
+	fn delete<'a>(&'a self, object: &'a StoredObject) -> StorageFuture<'a, ()> {
+		Box::pin(async move {
+			let (resource_type, public_id) = match object.key.split_once('/') {
+				Some(parts) => parts,
+				None => return Err(StorageError::NotFound)
+			};
+			let timestamp = Utc::now().timestamp().to_string();
+			let signature = sign(&format!("public_id={}&timestamp={}", public_id, timestamp), &self.api_secret);
+			self.client
+				.post(self.endpoint(resource_type, "destroy"))
+				.form(&[
+					("public_id", public_id),
+					("timestamp", timestamp.as_str()),
+					("api_key", self.api_key.as_str()),
+					("signature", signature.as_str())
+				])
+				.send().await.map_err(remote_error)?
+				.error_for_status().map_err(remote_error)?;
+			Ok(())
+		})
+	}
+}
@@ -0,0 +1,77 @@
+use actix_web::web;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use super::{Storage, StorageError, StorageFuture, StoredContent, StoredObject};
+
+/// Keeps uploads on the local filesystem under `STORAGE_LOCAL_DIR` (defaults to `uploads`).
+pub struct LocalStorage {
+	root: PathBuf
+}
+
+impl LocalStorage {
+	pub fn new(root: PathBuf) -> LocalStorage {
+		LocalStorage { root }
+	}
+
+	pub fn from_env() -> LocalStorage {
+		let root = env::var("STORAGE_LOCAL_DIR").unwrap_or_else(|_| String::from("uploads"));
+		LocalStorage::new(PathBuf::from(root))
+	}
+
+	/// Resolves a key below the storage root, refusing anything that could escape it.
+	fn path_for(&self, key: &str) -> Result<PathBuf, StorageError> {
+		let relative = Path::new(key);
+		let is_plain = relative.components().all(|component| matches!(component, Component::Normal(_)));
+		if key.is_empty() || !is_plain {
+			return Err(StorageError::NotFound);
+		}
+		Ok(self.root.join(relative))
+	}
+}
+
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T, StorageError> + Send + 'static) -> Result<T, StorageError> {
+	match web::block(f).await {
+		Ok(result) => result,
+		Err(e) => Err(StorageError::Io(std::io::Error::other(e.to_string())))
+	}
+}
+
+impl Storage for LocalStorage {
+	fn backend(&self) -> &'static str {
+		"local"
+	}
+
+	fn put<'a>(&'a self, key: &'a str, _mime_type: &'a str, data: Vec<u8>) -> StorageFuture<'a, StoredObject> {
+		Box::pin(async move {
+			let path = self.path_for(key)?;
+			blocking(move || {
+				if let Some(parent) = path.parent() {
+					fs::create_dir_all(parent)?;
+				}
+				fs::write(&path, data)?;
+				Ok(())
+			}).await?;
+			Ok(StoredObject { key: key.to_string(), url: None })
+		})
+	}
+
+	fn get<'a>(&'a self, object: &'a StoredObject) -> StorageFuture<'a, StoredContent> {
+		Box::pin(async move {
+			let path = self.path_for(&object.key)?;
+			let data = blocking(move || Ok(fs::read(path)?)).await?;
+			Ok(StoredContent::Bytes(data))
+		})
+	}
+
+	fn delete<'a>(&'a self, object: &'a StoredObject) -> StorageFuture<'a, ()> {
+		Box::pin(async move {
+			let path = self.path_for(&object.key)?;
+			blocking(move || match fs::remove_file(path) {
+				Ok(()) => Ok(()),
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+				Err(e) => Err(e.into())
+			}).await
+		})
+	}
+}
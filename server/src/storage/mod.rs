@@ -0,0 +1,114 @@
+use futures::future::BoxFuture;
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+
+pub mod cloudinary;
+pub mod local;
+pub mod processing;
+
+const DEFAULT_MAX_UPLOAD_BYTES: i64 = 25 * 1024 * 1024;
+const DEFAULT_USER_QUOTA_BYTES: i64 = 1024 * 1024 * 1024;
+const DEFAULT_ALLOWED_MIME_TYPES: &str = "image/*,video/*,audio/*,application/pdf,text/plain,application/zip";
+/// Types a browser may run scripts from. These are refused even when an allowed family covers them.
+const BLOCKED_MIME_TYPES: &[&str] = &["image/svg+xml", "text/html", "application/xhtml+xml", "application/xml", "text/xml"];
+
+/// Where an object ended up after `Storage::put`.
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+	pub key: String,
+	/// Set by backends that serve files themselves, e.g. a CDN URL.
+	pub url: Option<String>
+}
+
+/// How to hand a stored object back to the client.
+pub enum StoredContent {
+	Bytes(Vec<u8>),
+	Redirect(String)
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+	NotFound,
+	Io(std::io::Error),
+	Remote(String)
+}
+
+impl fmt::Display for StorageError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			StorageError::NotFound => write!(f, "object not found"),
+			StorageError::Io(e) => write!(f, "storage io error: {}", e),
+			StorageError::Remote(message) => write!(f, "remote storage error: {}", message)
+		}
+	}
+}
+
+impl From<std::io::Error> for StorageError {
+	fn from(e: std::io::Error) -> Self {
+		match e.kind() {
+			std::io::ErrorKind::NotFound => StorageError::NotFound,
+			_ => StorageError::Io(e)
+		}
+	}
+}
+
+pub type StorageFuture<'a, T> = BoxFuture<'a, Result<T, StorageError>>;
+
+/// A place to keep uploaded media. Keys are chosen by the caller and are unique per upload.
+pub trait Storage: Send + Sync {
+	/// Stored on each upload so objects can be traced back to the backend that holds them.
+	fn backend(&self) -> &'static str;
+
+	fn put<'a>(&'a self, key: &'a str, mime_type: &'a str, data: Vec<u8>) -> StorageFuture<'a, StoredObject>;
+
+	fn get<'a>(&'a self, object: &'a StoredObject) -> StorageFuture<'a, StoredContent>;
+
+	fn delete<'a>(&'a self, object: &'a StoredObject) -> StorageFuture<'a, ()>;
+}
+
+/// Picks the backend from `STORAGE_BACKEND` (`local` or `cloudinary`, defaults to `local`).
+pub fn from_env() -> Arc<dyn Storage> {
+	let backend = env::var("STORAGE_BACKEND").unwrap_or_else(|_| String::from("local"));
+	match backend.as_str() {
+		"cloudinary" => Arc::new(cloudinary::CloudinaryStorage::from_env()),
+		"local" => Arc::new(local::LocalStorage::from_env()),
+		other => panic!("unknown `STORAGE_BACKEND` {}", other)
+	}
+}
+
+fn env_bytes(name: &str, default: i64) -> i64 {
+	env::var(name)
+		.ok()
+		.and_then(|value| value.parse::<i64>().ok())
+		.unwrap_or(default)
+}
+
+/// Largest single upload, from `STORAGE_MAX_UPLOAD_BYTES`.
+pub fn max_upload_bytes() -> i64 {
+	env_bytes("STORAGE_MAX_UPLOAD_BYTES", DEFAULT_MAX_UPLOAD_BYTES)
+}
+
+/// Total bytes a user may keep stored, from `STORAGE_USER_QUOTA_BYTES`; `0` means no quota.
+pub fn user_quota_bytes() -> Option<i64> {
+	match env_bytes("STORAGE_USER_QUOTA_BYTES", DEFAULT_USER_QUOTA_BYTES) {
+		quota if quota <= 0 => None,
+		quota => Some(quota)
+	}
+}
+
+/// Checks `mime_type` against the comma separated `STORAGE_ALLOWED_MIME_TYPES`,
+/// where entries may end in `/*` to allow a whole family. SVG and markup types are never allowed.
+pub fn is_allowed_mime_type(mime_type: &str) -> bool {
+	let allowed = env::var("STORAGE_ALLOWED_MIME_TYPES").unwrap_or_else(|_| String::from(DEFAULT_ALLOWED_MIME_TYPES));
+	let mime_type = mime_type.trim().to_ascii_lowercase();
+	if BLOCKED_MIME_TYPES.contains(&mime_type.as_str()) {
+		return false;
+	}
+	allowed.split(',').map(|entry| entry.trim().to_ascii_lowercase()).any(|entry| {
+		match entry.strip_suffix("/*") {
+			Some(family) => mime_type.split('/').next() == Some(family),
+			None => entry == mime_type
+		}
+	})
+}
@@ -0,0 +1,149 @@
+use ::image::io::{Limits, Reader};
+use ::image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use std::env;
+use std::fmt;
+use std::io::Cursor;
+
+const DEFAULT_THUMBNAIL_SIZES: &str = "320,960";
+const MAX_IMAGE_DIMENSION: u32 = 12_000;
+const JPEG_QUALITY: u8 = 85;
+const BLURHASH_SAMPLE_SIZE: u32 = 32;
+
+/// An encoded image ready to be handed to `Storage::put`.
+pub struct EncodedImage {
+	pub data: Vec<u8>,
+	pub mime_type: &'static str,
+	pub width: u32,
+	pub height: u32
+}
+
+pub struct ProcessedImage {
+	/// The upload itself, re-encoded without metadata.
+	pub original: EncodedImage,
+	/// Keyed by the longest side they were scaled down to, smallest first.
+	pub thumbnails: Vec<(u32, EncodedImage)>,
+	pub blurhash: Option<String>
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+	Unreadable(String),
+	Encode(String)
+}
+
+impl fmt::Display for ImageError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ImageError::Unreadable(message) => write!(f, "could not read image: {}", message),
+			ImageError::Encode(message) => write!(f, "could not encode image: {}", message)
+		}
+	}
+}
+
+fn format_for(mime_type: &str) -> Option<ImageFormat> {
+	match mime_type {
+		"image/jpeg" | "image/jpg" => Some(ImageFormat::Jpeg),
+		"image/png" => Some(ImageFormat::Png),
+		"image/gif" => Some(ImageFormat::Gif),
+		"image/webp" => Some(ImageFormat::WebP),
+		_ => None
+	}
+}
+
+/// Whether uploads of `mime_type` go through `process`; other files are stored as they are.
+pub fn is_processable(mime_type: &str) -> bool {
+	format_for(mime_type).is_some()
+}
+
+/// Longest sides to scale thumbnails down to, from the comma separated `STORAGE_THUMBNAIL_SIZES`.
+pub fn thumbnail_sizes() -> Vec<u32> {
+	let sizes = env::var("STORAGE_THUMBNAIL_SIZES").unwrap_or_else(|_| String::from(DEFAULT_THUMBNAIL_SIZES));
+	let mut sizes: Vec<u32> = sizes.split(',')
+		.filter_map(|size| size.trim().parse::<u32>().ok())
+		.filter(|size| *size > 0)
+		.collect();
+	sizes.sort_unstable();
+	sizes.dedup();
+	sizes
+}
+
+/// Reads the EXIF orientation so it can be applied before the metadata is dropped.
+fn exif_orientation(data: &[u8]) -> u32 {
+	exif::Reader::new()
+		.read_from_container(&mut Cursor::new(data))
+		.ok()
+		.and_then(|exif| exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY).and_then(|field| field.value.get_uint(0)))
+		.unwrap_or(1)
+}
+
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+	match orientation {
+		2 => image.fliph(),
+		3 => image.rotate180(),
+		4 => image.flipv(),
+		5 => image.rotate90().fliph(),
+		6 => image.rotate90(),
+		7 => image.rotate270().fliph(),
+		8 => image.rotate270(),
+		_ => image
+	}
+}
+
+/// Encodes as JPEG, or as PNG when the image needs to keep its transparency.
+/// Only pixels are written, so nothing from the source's EXIF or GPS data survives.
+fn encode(image: &DynamicImage, keep_alpha: bool) -> Result<EncodedImage, ImageError> {
+	let mut data: Vec<u8> = Vec::new();
+	let mime_type = if keep_alpha {
+		image.write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+			.map_err(|e| ImageError::Encode(e.to_string()))?;
+		"image/png"
+	} else {
+		DynamicImage::ImageRgb8(image.to_rgb8())
+			.write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Jpeg(JPEG_QUALITY))
+			.map_err(|e| ImageError::Encode(e.to_string()))?;
+		"image/jpeg"
+	};
+	Ok(EncodedImage { data, mime_type, width: image.width(), height: image.height() })
+}
+
+fn blurhash_for(image: &DynamicImage) -> Option<String> {
+	let sample = image.thumbnail(BLURHASH_SAMPLE_SIZE, BLURHASH_SAMPLE_SIZE).to_rgba8();
+	let (components_x, components_y) = if sample.width() >= sample.height() { (4, 3) } else { (3, 4) };
+	blurhash::encode(components_x, components_y, sample.width(), sample.height(), sample.as_raw()).ok()
+}
+
+/// Strips metadata from an uploaded image and works out its dimensions, thumbnails and blurhash.
+/// CPU bound, so run it inside `web::block`.
+pub fn process(data: Vec<u8>, mime_type: &str) -> Result<ProcessedImage, ImageError> {
+	let format = match format_for(mime_type) {
+		Some(format) => format,
+		None => return Err(ImageError::Unreadable(format!("{} images are not supported", mime_type)))
+	};
+	let mut reader = Reader::with_format(Cursor::new(&data), format);
+	let mut limits = Limits::default();
+	limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+	limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+	reader.limits(limits);
+	let decoded = reader.decode().map_err(|e| ImageError::Unreadable(e.to_string()))?;
+	let image = match format {
+		ImageFormat::Jpeg => apply_orientation(decoded, exif_orientation(&data)),
+		_ => decoded
+	};
+	let keep_alpha = image.color().has_alpha();
+
+	let original = match format {
+		// GIFs carry no EXIF, and re-encoding would only keep the first frame.
+		ImageFormat::Gif => EncodedImage { data, mime_type: "image/gif", width: image.width(), height: image.height() },
+		ImageFormat::Jpeg => encode(&image, false)?,
+		// There's no WebP encoder in this build, so WebP comes back as PNG.
+		_ => encode(&image, true)?
+	};
+
+	let longest_side = image.width().max(image.height());
+	let mut thumbnails: Vec<(u32, EncodedImage)> = Vec::new();
+	for size in thumbnail_sizes().into_iter().filter(|size| *size < longest_side) {
+		thumbnails.push((size, encode(&image.thumbnail(size, size), keep_alpha)?));
+	}
+
+	Ok(ProcessedImage { original, thumbnails, blurhash: blurhash_for(&image) })
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+/// The bits of a page's markup a preview is built from.
+#[derive(Debug, Default)]
+pub struct PageMetadata {
+	/// `<meta property|name=... content=...>` values by lowercased key; the first one wins.
+	pub meta: HashMap<String, String>,
+	pub title: Option<String>,
+	/// From `<link rel="alternate" type="application/json+oembed" href=...>`.
+	pub oembed_url: Option<String>
+}
+
+impl PageMetadata {
+	pub fn get(&self, keys: &[&str]) -> Option<String> {
+		keys.iter().find_map(|key| self.meta.get(*key).cloned())
+	}
+}
+
+/// Pulls `<meta>`, `<title>` and oEmbed discovery links out of possibly truncated HTML.
+/// Deliberately forgiving: anything it can't make sense of is skipped.
+pub fn parse(html: &str) -> PageMetadata {
+	let mut page = PageMetadata::default();
+	let lower = html.to_ascii_lowercase();
+	let mut cursor = 0;
+	while let Some(offset) = lower[cursor..].find('<') {
+		let start = cursor + offset;
+		let rest = &lower[start..];
+		if rest.starts_with("<!--") {
+			cursor = match rest.find("-->") {
+				Some(end) => start + end + 3,
+				None => break
+			};
+			continue;
+		}
+		let name: String = rest[1..].chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+		let tag_end = match rest.find('>') {
+			Some(end) => start + end,
+			None => break
+		};
+		let attributes = parse_attributes(&html[start + 1 + name.len()..tag_end]);
+		cursor = tag_end + 1;
+		match name.as_str() {
+			"meta" => {
+				let key = attributes.get("property").or_else(|| attributes.get("name"));
+				if let (Some(key), Some(value)) = (key, attributes.get("content")) {
+					page.meta.entry(key.to_ascii_lowercase()).or_insert_with(|| clean_text(value));
+				}
+			}
+			"link" => {
+				let rel = attributes.get("rel").map(|rel| rel.to_ascii_lowercase()).unwrap_or_default();
+				let kind = attributes.get("type").map(|kind| kind.to_ascii_lowercase()).unwrap_or_default();
+				if page.oembed_url.is_none() && rel.split_whitespace().any(|part| part == "alternate") && kind == "application/json+oembed" {
+					page.oembed_url = attributes.get("href").map(|href| decode_entities(href.trim()));
+				}
+			}
+			"title" => {
+				if let Some(end) = lower[cursor..].find("</title") {
+					if page.title.is_none() {
+						page.title = Some(clean_text(&html[cursor..cursor + end]));
+					}
+					cursor += end;
+				}
+			}
+			// Their contents aren't markup, so don't go looking for tags in them.
+			"script" | "style" => {
+				let closing = format!("</{}", name);
+				cursor = match lower[cursor..].find(&closing) {
+					Some(end) => cursor + end,
+					None => break
+				};
+			}
+			_ => {}
+		}
+	}
+	page
+}
+
+fn parse_attributes(source: &str) -> HashMap<String, String> {
+	let mut attributes: HashMap<String, String> = HashMap::new();
+	let mut chars = source.char_indices().peekable();
+	loop {
+		while chars.next_if(|(_, c)| c.is_whitespace() || *c == '/').is_some() {}
+		let name_start = match chars.peek() {
+			Some((index, _)) => *index,
+			None => break
+		};
+		while chars.next_if(|(_, c)| !c.is_whitespace() && *c != '=' && *c != '/').is_some() {}
+		let name_end = chars.peek().map_or(source.len(), |(index, _)| *index);
+		let name = source[name_start..name_end].to_ascii_lowercase();
+		while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+		let mut value = String::new();
+		if chars.next_if(|(_, c)| *c == '=').is_some() {
+			while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+			match chars.next_if(|(_, c)| *c == '"' || *c == '\'') {
+				Some((_, quote)) => {
+					for (_, c) in chars.by_ref() {
+						if c == quote {
+							break;
+						}
+						value.push(c);
+					}
+				}
+				None => {
+					while let Some((_, c)) = chars.next_if(|(_, c)| !c.is_whitespace()) {
+						value.push(c);
+					}
+				}
+			}
+		}
+		if !name.is_empty() {
+			attributes.entry(name).or_insert(value);
+		}
+	}
+	attributes
+}
+
+/// Decodes the handful of entities that show up in titles and descriptions.
+pub fn decode_entities(text: &str) -> String {
+	let mut decoded = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find('&') {
+		decoded.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let end = match rest.find(';') {
+			Some(end) if end <= 10 => end,
+			_ => {
+				decoded.push('&');
+				rest = &rest[1..];
+				continue;
+			}
+		};
+		let entity = &rest[1..end];
+		let replacement = match entity {
+			"amp" => Some('&'),
+			"lt" => Some('<'),
+			"gt" => Some('>'),
+			"quot" => Some('"'),
+			"apos" => Some('\''),
+			"nbsp" => Some(' '),
+			_ => entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X"))
+				.and_then(|hex| u32::from_str_radix(hex, 16).ok())
+				.or_else(|| entity.strip_prefix('#').and_then(|decimal| decimal.parse::<u32>().ok()))
+				.and_then(char::from_u32)
+		};
+		match replacement {
+			Some(c) => {
+				decoded.push(c);
+				rest = &rest[end + 1..];
+			}
+			None => {
+				decoded.push('&');
+				rest = &rest[1..];
+			}
+		}
+	}
+	decoded.push_str(rest);
+	decoded
+}
+
+/// Decodes entities and collapses runs of whitespace.
+pub fn clean_text(text: &str) -> String {
+	decode_entities(text).split_whitespace().collect::<Vec<&str>>().join(" ")
+}
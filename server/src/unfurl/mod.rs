@@ -0,0 +1,426 @@
+use actix::Addr;
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use ::url::Url;
+use crate::lib::DbPool;
+use crate::models::link_preview::{LinkMetadata, LinkPreview};
+use crate::models::message::Message;
+use crate::ws_server;
+
+pub mod html;
+pub mod net;
+
+/// Only the first few links in a message get a preview.
+pub const MAX_PREVIEWS_PER_MESSAGE: usize = 3;
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+const MAX_REDIRECTS: usize = 3;
+const MAX_TITLE_CHARS: usize = 300;
+const MAX_DESCRIPTION_CHARS: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct FetcherConfig {
+	pub enabled: bool,
+	/// Per request, for connecting and reading the whole response.
+	pub timeout: Duration,
+	/// Responses are cut off after this many bytes; page metadata lives near the top anyway.
+	pub max_body_bytes: usize,
+	/// Lets the fetcher reach loopback and private addresses. Only for tests and local development.
+	pub allow_private_networks: bool,
+	pub user_agent: String
+}
+
+impl Default for FetcherConfig {
+	fn default() -> Self {
+		FetcherConfig {
+			enabled: true,
+			timeout: Duration::from_millis(DEFAULT_TIMEOUT_MS),
+			max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+			allow_private_networks: false,
+			user_agent: String::from("tinychat-link-preview/1.0")
+		}
+	}
+}
+
+impl FetcherConfig {
+	/// Reads `LINK_PREVIEWS_ENABLED`, `LINK_PREVIEW_TIMEOUT_MS`, `LINK_PREVIEW_MAX_BYTES` and
+	/// `LINK_PREVIEW_ALLOW_PRIVATE_NETWORKS`, falling back to the defaults.
+	pub fn from_env() -> FetcherConfig {
+		let defaults = FetcherConfig::default();
+		let flag = |name: &str, default: bool| env::var(name).map(|value| value == "true" || value == "1").unwrap_or(default);
+		FetcherConfig {
+			enabled: flag("LINK_PREVIEWS_ENABLED", defaults.enabled),
+			timeout: env::var("LINK_PREVIEW_TIMEOUT_MS").ok()
+				.and_then(|value| value.parse::<u64>().ok())
+				.map_or(defaults.timeout, Duration::from_millis),
+			max_body_bytes: env::var("LINK_PREVIEW_MAX_BYTES").ok()
+				.and_then(|value| value.parse::<usize>().ok())
+				.unwrap_or(defaults.max_body_bytes),
+			allow_private_networks: flag("LINK_PREVIEW_ALLOW_PRIVATE_NETWORKS", defaults.allow_private_networks),
+			user_agent: defaults.user_agent
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+	InvalidUrl,
+	/// The host resolves to an address the fetcher may not reach.
+	Blocked,
+	TooManyRedirects,
+	Status(u16),
+	/// The page isn't HTML or has nothing worth previewing.
+	NoMetadata,
+	Http(String)
+}
+
+impl fmt::Display for FetchError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FetchError::InvalidUrl => write!(f, "invalid url"),
+			FetchError::Blocked => write!(f, "address is not allowed"),
+			FetchError::TooManyRedirects => write!(f, "too many redirects"),
+			FetchError::Status(status) => write!(f, "unexpected status {}", status),
+			FetchError::NoMetadata => write!(f, "no metadata"),
+			FetchError::Http(message) => write!(f, "http error: {}", message)
+		}
+	}
+}
+
+fn http_error(e: reqwest::Error) -> FetchError {
+	FetchError::Http(e.to_string())
+}
+
+#[derive(Deserialize)]
+struct OEmbed {
+	title: Option<String>,
+	provider_name: Option<String>,
+	thumbnail_url: Option<String>
+}
+
+struct FetchedBody {
+	url: Url,
+	content_type: String,
+	body: Vec<u8>
+}
+
+/// Fetches OpenGraph and oEmbed metadata for links in messages.
+pub struct LinkPreviewFetcher {
+	config: FetcherConfig
+}
+
+impl LinkPreviewFetcher {
+	pub fn new(config: FetcherConfig) -> LinkPreviewFetcher {
+		LinkPreviewFetcher { config }
+	}
+
+	pub fn from_env() -> LinkPreviewFetcher {
+		LinkPreviewFetcher::new(FetcherConfig::from_env())
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.config.enabled
+	}
+
+	/// Resolves the URL's host and picks an address the fetcher is allowed to connect to.
+	async fn resolve(&self, url: &Url) -> Result<SocketAddr, FetchError> {
+		let host = url.host_str().ok_or(FetchError::InvalidUrl)?;
+		let port = url.port_or_known_default().ok_or(FetchError::InvalidUrl)?;
+		let host = host.trim_start_matches('[').trim_end_matches(']');
+		let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await
+			.map_err(|e| FetchError::Http(e.to_string()))?
+			.collect();
+		if addresses.is_empty() {
+			return Err(FetchError::InvalidUrl);
+		}
+		// Refuse the host outright if any of its addresses is internal, rather than picking around it.
+		if !self.config.allow_private_networks && addresses.iter().any(|address| !net::is_public_ip(&address.ip())) {
+			return Err(FetchError::Blocked);
+		}
+		Ok(addresses[0])
+	}
+
+	/// GETs `url`, following redirects by hand so every hop is checked, and reads at most
+	/// `max_body_bytes` of the body.
+	async fn get(&self, url: &Url, accept: &str) -> Result<FetchedBody, FetchError> {
+		let mut current = url.clone();
+		for _ in 0..=MAX_REDIRECTS {
+			if !matches!(current.scheme(), "http" | "https") || !current.username().is_empty() || current.password().is_some() {
+				return Err(FetchError::InvalidUrl);
+			}
+			let address = self.resolve(&current).await?;
+			let host = current.host_str().ok_or(FetchError::InvalidUrl)?.to_string();
+			// Connect to the address that was checked, so a second DNS answer can't point elsewhere.
+			let client = reqwest::Client::builder()
+				.redirect(reqwest::redirect::Policy::none())
+				.timeout(self.config.timeout)
+				.connect_timeout(self.config.timeout)
+				.user_agent(self.config.user_agent.as_str())
+				.resolve(&host, address)
+				.build()
+				.map_err(http_error)?;
+			let mut response = client.get(current.clone())
+				.header(reqwest::header::ACCEPT, accept)
+				.send().await.map_err(http_error)?;
+			if response.status().is_redirection() {
+				let location = response.headers().get(reqwest::header::LOCATION)
+					.and_then(|value| value.to_str().ok())
+					.ok_or(FetchError::Status(response.status().as_u16()))?;
+				current = current.join(location).map_err(|_| FetchError::InvalidUrl)?;
+				continue;
+			}
+			if !response.status().is_success() {
+				return Err(FetchError::Status(response.status().as_u16()));
+			}
+			let content_type = response.headers().get(reqwest::header::CONTENT_TYPE)
+				.and_then(|value| value.to_str().ok())
+				.unwrap_or("")
+				.to_ascii_lowercase();
+			let mut body: Vec<u8> = Vec::new();
+			while let Some(chunk) = response.chunk().await.map_err(http_error)? {
+				let remaining = self.config.max_body_bytes - body.len();
+				if chunk.len() >= remaining {
+					body.extend_from_slice(&chunk[..remaining]);
+					break;
+				}
+				body.extend_from_slice(&chunk);
+			}
+			return Ok(FetchedBody { url: current, content_type, body });
+		}
+		Err(FetchError::TooManyRedirects)
+	}
+
+	async fn fetch_oembed(&self, url: &Url) -> Result<OEmbed, FetchError> {
+		let fetched = self.get(url, "application/json").await?;
+		serde_json::from_slice::<OEmbed>(&fetched.body).map_err(|_| FetchError::NoMetadata)
+	}
+
+	/// Builds a preview from the page's OpenGraph tags, filling gaps from its oEmbed endpoint,
+	/// Twitter cards and finally the plain `<title>`.
+	pub async fn fetch(&self, url: &Url) -> Result<LinkMetadata, FetchError> {
+		let fetched = self.get(url, "text/html,application/xhtml+xml").await?;
+		if !fetched.content_type.starts_with("text/html") && !fetched.content_type.starts_with("application/xhtml+xml") {
+			return Err(FetchError::NoMetadata);
+		}
+		let page = html::parse(&String::from_utf8_lossy(&fetched.body));
+		let absolute = |link: String| fetched.url.join(&link).ok()
+			.filter(|joined| matches!(joined.scheme(), "http" | "https"))
+			.map(|joined| joined.to_string());
+
+		let mut metadata = LinkMetadata {
+			title: page.get(&["og:title", "twitter:title"]),
+			description: page.get(&["og:description", "twitter:description", "description"]),
+			image_url: page.get(&["og:image", "og:image:url", "og:image:secure_url", "twitter:image"]).and_then(absolute),
+			site_name: page.get(&["og:site_name"])
+		};
+		if metadata.title.is_none() || metadata.image_url.is_none() || metadata.site_name.is_none() {
+			if let Some(oembed_url) = page.oembed_url.as_ref().and_then(|link| fetched.url.join(link).ok()) {
+				if let Ok(oembed) = self.fetch_oembed(&oembed_url).await {
+					metadata.title = metadata.title.or_else(|| oembed.title.map(|title| html::clean_text(&title)));
+					metadata.image_url = metadata.image_url.or_else(|| oembed.thumbnail_url.and_then(absolute));
+					metadata.site_name = metadata.site_name.or(oembed.provider_name);
+				}
+			}
+		}
+		metadata.title = metadata.title.or(page.title).filter(|title| !title.is_empty());
+		if metadata.title.is_none() && metadata.description.is_none() && metadata.image_url.is_none() {
+			return Err(FetchError::NoMetadata);
+		}
+		metadata.title = metadata.title.map(|title| truncate(title, MAX_TITLE_CHARS));
+		metadata.description = metadata.description.map(|description| truncate(description, MAX_DESCRIPTION_CHARS));
+		metadata.site_name = metadata.site_name.or_else(|| fetched.url.host_str().map(String::from));
+		Ok(metadata)
+	}
+}
+
+fn truncate(text: String, max_chars: usize) -> String {
+	match text.char_indices().nth(max_chars) {
+		Some((index, _)) => format!("{}…", text[..index].trim_end()),
+		None => text
+	}
+}
+
+/// The http(s) links in a message, in order, without duplicates or fragments.
+pub fn extract_urls(text: &str) -> Vec<Url> {
+	let mut urls: Vec<Url> = Vec::new();
+	for word in text.split_whitespace() {
+		let start = match word.find("https://").or_else(|| word.find("http://")) {
+			Some(start) => start,
+			None => continue
+		};
+		let candidate = word[start..].trim_end_matches(|c: char| ".,;:!?)]}>'\"*_~`".contains(c));
+		if let Ok(mut url) = Url::parse(candidate) {
+			url.set_fragment(None);
+			if url.host_str().is_some() && !urls.contains(&url) {
+				urls.push(url);
+			}
+		}
+		if urls.len() == MAX_PREVIEWS_PER_MESSAGE {
+			break;
+		}
+	}
+	urls
+}
+
+/// Unfurls the links in `message` in the background and tells the conversation once the
+/// previews are attached. With `replace_existing` (after an edit) a message that lost its
+/// links also loses its previews.
+pub fn spawn_for_message(
+	message: Message,
+	replace_existing: bool,
+	fetcher: Arc<LinkPreviewFetcher>,
+	pool: DbPool,
+	ws_server: Addr<ws_server::WsServer>
+) {
+	if !fetcher.is_enabled() {
+		return;
+	}
+	let urls = message.content.as_deref().map(extract_urls).unwrap_or_default();
+	if urls.is_empty() && !replace_existing {
+		return;
+	}
+	actix_web::rt::spawn(async move {
+		let mut preview_ids: Vec<uuid::Uuid> = Vec::new();
+		for url in urls {
+			let cached = match pool.get() {
+				Ok(conn) => LinkPreview::fetch_fresh(url.as_str(), &conn),
+				Err(_) => return
+			};
+			let preview = match cached {
+				Ok(Some(preview)) => preview,
+				Ok(None) => {
+					let metadata = match fetcher.fetch(&url).await {
+						Ok(metadata) => Some(metadata),
+						Err(e) => {
+							log::debug!("no preview for {}: {}", url, e);
+							None
+						}
+					};
+					let conn = match pool.get() {
+						Ok(conn) => conn,
+						Err(_) => return
+					};
+					match LinkPreview::upsert(url.as_str(), metadata.as_ref(), &conn) {
+						Ok(preview) => preview,
+						Err(_) => continue
+					}
+				}
+				Err(_) => continue
+			};
+			if preview.is_ok() {
+				preview_ids.push(preview.id);
+			}
+		}
+
+		let conn = match pool.get() {
+			Ok(conn) => conn,
+			Err(_) => return
+		};
+		// The message may have been edited or deleted while the pages were loading.
+		let current = match Message::fetch_by_id(&message.id, &conn) {
+			Ok(current) if !current.is_deleted && current.content == message.content => current,
+			_ => return
+		};
+		let removed = match LinkPreview::replace_for_message(&current.id, &preview_ids, &conn) {
+			Ok(removed) => removed,
+			Err(_) => return
+		};
+		if preview_ids.is_empty() && removed == 0 {
+			return;
+		}
+		if let Ok(link_previews) = LinkPreview::fetch_by_message_id(&current.id, &conn) {
+			ws_server.do_send(ws_server::MessageUpdated { message: current, link_previews });
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{BufRead, BufReader, Write};
+	use std::net::TcpListener;
+
+	/// Serves canned responses on a loopback port, picking them by request path.
+	fn serve(routes: fn(&str) -> String) -> Url {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+		std::thread::spawn(move || {
+			for stream in listener.incoming() {
+				let mut stream = match stream {
+					Ok(stream) => stream,
+					Err(_) => return
+				};
+				let mut request_line = String::new();
+				let mut reader = BufReader::new(stream.try_clone().unwrap());
+				reader.read_line(&mut request_line).unwrap();
+				let mut header = String::new();
+				while reader.read_line(&mut header).unwrap() > 2 {
+					header.clear();
+				}
+				let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+				let _ = stream.write_all(routes(path).as_bytes());
+			}
+		});
+		base
+	}
+
+	fn html(body: &str) -> String {
+		format!("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+	}
+
+	fn redirect(location: &str) -> String {
+		format!("HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", location)
+	}
+
+	fn local_fetcher(max_body_bytes: usize) -> LinkPreviewFetcher {
+		LinkPreviewFetcher::new(FetcherConfig {
+			allow_private_networks: true,
+			max_body_bytes,
+			..FetcherConfig::default()
+		})
+	}
+
+	fn routes(path: &str) -> String {
+		match path {
+			"/start" => redirect("/hop"),
+			"/hop" => redirect("/page"),
+			"/page" => html(r#"<html><head><meta property="og:title" content="Landed"></head></html>"#),
+			"/loop" => redirect("/loop"),
+			"/big" => html(&format!("<html><head><title>Big</title></head><body>{}</body></html>", "x".repeat(64 * 1024))),
+			_ => String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+		}
+	}
+
+	#[actix_rt::test]
+	async fn follows_redirects_to_the_page() {
+		let base = serve(routes);
+		let metadata = local_fetcher(DEFAULT_MAX_BODY_BYTES).fetch(&base.join("/start").unwrap()).await.unwrap();
+		assert_eq!(metadata.title.as_deref(), Some("Landed"));
+		assert_eq!(metadata.site_name.as_deref(), Some("127.0.0.1"));
+
+		let looped = local_fetcher(DEFAULT_MAX_BODY_BYTES).fetch(&base.join("/loop").unwrap()).await;
+		assert!(matches!(looped, Err(FetchError::TooManyRedirects)));
+	}
+
+	#[actix_rt::test]
+	async fn cuts_the_body_off_at_the_size_cap() {
+		let base = serve(routes);
+		let fetcher = local_fetcher(1024);
+		let fetched = fetcher.get(&base.join("/big").unwrap(), "text/html").await.unwrap();
+		assert_eq!(fetched.body.len(), 1024);
+		let metadata = fetcher.fetch(&base.join("/big").unwrap()).await.unwrap();
+		assert_eq!(metadata.title.as_deref(), Some("Big"));
+	}
+
+	#[actix_rt::test]
+	async fn refuses_loopback_unless_allowed() {
+		let base = serve(routes);
+		let fetcher = LinkPreviewFetcher::new(FetcherConfig::default());
+		let blocked = fetcher.fetch(&base.join("/page").unwrap()).await;
+		assert!(matches!(blocked, Err(FetchError::Blocked)));
+	}
+}
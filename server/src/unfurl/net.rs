@@ -0,0 +1,61 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Whether `ip` is reachable on the public internet. Everything else (loopback, private
+/// ranges, link-local, carrier-grade NAT, documentation and reserved blocks) is off limits
+/// to the fetcher, so a crafted link can't make the server probe its own network.
+pub fn is_public_ip(ip: &IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => is_public_ipv4(ip),
+		IpAddr::V6(ip) => match ip.to_ipv4() {
+			// Mapped and compatible addresses reach the embedded IPv4 address.
+			Some(embedded) => is_public_ipv4(&embedded),
+			None => is_public_ipv6(ip)
+		}
+	}
+}
+
+fn is_public_ipv4(ip: &Ipv4Addr) -> bool {
+	let [a, b, c, _] = ip.octets();
+	!(ip.is_private()
+		|| ip.is_loopback()
+		|| ip.is_link_local()
+		|| ip.is_broadcast()
+		|| ip.is_documentation()
+		|| ip.is_unspecified()
+		|| ip.is_multicast()
+		|| a == 0
+		|| (a == 100 && (64..128).contains(&b))
+		|| (a == 192 && b == 0 && c == 0)
+		|| (a == 198 && (b == 18 || b == 19))
+		|| a >= 240)
+}
+
+fn is_public_ipv6(ip: &Ipv6Addr) -> bool {
+	let segments = ip.segments();
+	!(ip.is_loopback()
+		|| ip.is_unspecified()
+		|| ip.is_multicast()
+		|| (segments[0] & 0xfe00) == 0xfc00
+		|| (segments[0] & 0xffc0) == 0xfe80
+		|| (segments[0] & 0xffc0) == 0xfec0
+		|| (segments[0] == 0x2001 && segments[1] == 0x0db8)
+		// NAT64, 6to4 and Teredo addresses can embed any IPv4 address, private ones included.
+		|| (segments[0] == 0x0064 && segments[1] == 0xff9b)
+		|| segments[0] == 0x2002
+		|| (segments[0] == 0x2001 && segments[1] == 0x0000))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rejects_ipv6_ranges_that_embed_ipv4() {
+		for address in ["64:ff9b::a00:1", "2002:a00:1::1", "2001:0:4136:e378::1", "::ffff:10.0.0.1", "fd00::1"] {
+			assert!(!is_public_ip(&address.parse().unwrap()), "{} should be blocked", address);
+		}
+		for address in ["2606:4700::1111", "2001:4860:4860::8888", "1.1.1.1"] {
+			assert!(is_public_ip(&address.parse().unwrap()), "{} should be allowed", address);
+		}
+	}
+}
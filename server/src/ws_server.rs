@@ -10,7 +10,7 @@ use serde::{Serialize, Deserialize};
 use actix::prelude::*;
 use rand::{self, rngs::ThreadRng, Rng};
 use std::fmt;
-use crate::lib::DbPool;
+use crate::lib::{DbPool, json_option_time};
 use crate::models;
 
 #[derive(Message)]
@@ -32,9 +32,16 @@ pub struct Disconnect {
 
 #[derive(Message, Deserialize, Serialize)]
 #[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
 pub struct NewMessage {
 	pub message: models::message::Message,
-	pub author: models::user::User
+	pub author: models::user::User,
+	pub author_identity: models::member::MemberIdentity,
+	pub reply_to: Option<models::message::ReplyPreview>,
+	pub attachments: Vec<models::attachment::Attachment>,
+	pub thread: Option<models::message::ThreadSummary>,
+	/// The provenance as the author sees it; narrowed to `Unknown` for recipients outside the original conversation.
+	pub forwarded_from: Option<models::forward::Provenance>
 }
 
 #[derive(Message, Deserialize, Serialize)]
@@ -49,7 +56,10 @@ pub struct NewUser {
 pub struct MessageDeleted {
 	pub message_id: uuid::Uuid,
 	pub conversation_id: uuid::Uuid,
-	pub author_id: uuid::Uuid
+	pub author_id: uuid::Uuid,
+	pub deleted_by: uuid::Uuid,
+	/// The updated thread badge when the deleted message was a thread reply.
+	pub thread: Option<models::message::ThreadSummary>
 }
 
 #[derive(Serialize)]
@@ -66,9 +76,186 @@ pub struct ReactionUpdated {
 	pub emoji_name: String,
 	pub message_id: uuid::Uuid,
 	pub conversation_id: uuid::Uuid,
+	/// How many users reacted with `emoji_name` after this update.
+	pub count: i64,
 	pub update_type: ReactionUpdateType
 }
 
+/// Keeps a user's sessions in step with their draft for a conversation; `draft` is `None` once it is cleared.
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct DraftUpdated {
+	pub user_id: uuid::Uuid,
+	pub conversation_id: uuid::Uuid,
+	pub draft: Option<models::draft::Draft>
+}
+
+/// Tells the author's sessions that one of their scheduled messages went out; the other
+/// members get a regular `new_message`.
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct ScheduledMessageSent {
+	pub scheduled_message_id: uuid::Uuid,
+	pub message: models::message::Message,
+	pub author_identity: models::member::MemberIdentity,
+	pub attachments: Vec<models::attachment::Attachment>
+}
+
+/// Tells the author that a scheduled message was thrown away instead of sent.
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct ScheduledMessageDropped {
+	pub scheduled_message_id: uuid::Uuid,
+	pub conversation_id: uuid::Uuid,
+	pub author_id: uuid::Uuid
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct MessagePinned {
+	pub conversation_id: uuid::Uuid,
+	pub pin: models::pin::PinnedMessage,
+	pub message: models::message::Message
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct MessageUnpinned {
+	pub conversation_id: uuid::Uuid,
+	pub message_id: uuid::Uuid,
+	pub unpinned_by: uuid::Uuid
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct ConversationCreated {
+	pub conversation: models::conversation::Conversation,
+	pub created_by: uuid::Uuid
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct ConversationUpdated {
+	pub conversation: models::conversation::Conversation,
+	pub updated_by: uuid::Uuid
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct MembersAdded {
+	pub conversation_id: uuid::Uuid,
+	pub user_ids: Vec<uuid::Uuid>,
+	pub added_by: uuid::Uuid
+}
+
+#[derive(Serialize)]
+pub enum MemberRemovedReason {
+	KICKED = 0,
+	BANNED = 1,
+	LEFT = 2
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct MemberRemoved {
+	pub conversation_id: uuid::Uuid,
+	pub user_id: uuid::Uuid,
+	pub removed_by: uuid::Uuid,
+	pub reason: MemberRemovedReason
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct MemberRoleUpdated {
+	pub conversation_id: uuid::Uuid,
+	pub user_id: uuid::Uuid,
+	pub role: models::permission::Role,
+	pub updated_by: uuid::Uuid
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct MemberProfileUpdated {
+	pub conversation_id: uuid::Uuid,
+	pub member: models::member::Member,
+	pub identity: models::member::MemberIdentity
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct JoinRequested {
+	pub conversation_id: uuid::Uuid,
+	pub user_id: uuid::Uuid
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct JoinRequestResolved {
+	pub conversation_id: uuid::Uuid,
+	pub user_id: uuid::Uuid,
+	pub approved: bool,
+	pub resolved_by: uuid::Uuid
+}
+
+/// Advances a member's read marker, either to `message_id` or to now.
+#[derive(Message, Deserialize)]
+#[rtype(result = "Option<models::member::Member>")]
+#[serde(rename_all="camelCase")]
+pub struct MarkRead {
+	#[serde(skip)]
+	pub user_id: uuid::Uuid,
+	pub conversation_id: uuid::Uuid,
+	pub message_id: Option<uuid::Uuid>
+}
+
+#[derive(Serialize)]
+#[serde(rename_all="camelCase")]
+pub struct ReadReceipt {
+	pub conversation_id: uuid::Uuid,
+	pub user_id: uuid::Uuid,
+	#[serde(with = "json_option_time")]
+	pub last_read_at: Option<chrono::NaiveDateTime>
+}
+
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct MessageEdited {
+	pub message: models::message::Message
+}
+
+/// Sent to the whole conversation once link previews for a message are ready or have changed.
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct MessageUpdated {
+	pub message: models::message::Message,
+	pub link_previews: Vec<models::link_preview::LinkPreview>
+}
+
+/// Sent to each mentioned user; `kind` tells clients whether it was `@name`, `@everyone` or `@here`.
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+#[serde(rename_all="camelCase")]
+pub struct Mentioned {
+	pub message: models::message::Message,
+	pub author_identity: models::member::MemberIdentity,
+	pub attachments: Vec<models::attachment::Attachment>,
+	pub mentions: Vec<models::mention::Mention>
+}
 
 #[derive(Message, Serialize, Deserialize)]
 #[rtype(result = "()")]
@@ -147,6 +334,31 @@ impl WsServer {
 		}
 	}
 
+	pub fn send_conversation_message(&self, conversation_id: &uuid::Uuid, message: &ClientMessage) {
+		let conn = self.pool.get().unwrap();
+		if let Ok(user_ids) = models::member::Member::fetch_user_ids(conversation_id, &conn) {
+			for user_id in user_ids {
+				self.send_message(&user_id, message);
+			}
+		}
+	}
+
+	pub fn send_conversation_message_with_permission(
+		&self,
+		conversation_id: &uuid::Uuid,
+		permission: models::permission::Permission,
+		message: &ClientMessage
+	) {
+		let conn = self.pool.get().unwrap();
+		if let Ok(members) = models::member::Member::fetch_by_conversation(conversation_id, &conn) {
+			for member in members {
+				if member.role().can(permission) {
+					self.send_message(&member.user_id, message);
+				}
+			}
+		}
+	}
+
 	pub fn broadcast(&self, message: &ClientMessage) {
 		for (_, addr) in &self.sessions {
 			addr.do_send(Message(serde_json::to_string(message).unwrap()));
@@ -199,12 +411,77 @@ impl Handler<ReactionUpdated> for WsServer {
 	}
 }
 
+impl Handler<DraftUpdated> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: DraftUpdated, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "draft_updated".to_string(),
+			d: Some(d)
+		};
+		self.send_message(&msg.user_id, &message);
+	}
+}
+
+impl Handler<ScheduledMessageSent> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: ScheduledMessageSent, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "scheduled_message_sent".to_string(),
+			d: Some(d)
+		};
+		self.send_message(&msg.message.author_id, &message);
+	}
+}
+
+impl Handler<ScheduledMessageDropped> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: ScheduledMessageDropped, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "scheduled_message_dropped".to_string(),
+			d: Some(d)
+		};
+		self.send_message(&msg.author_id, &message);
+	}
+}
+
+impl Handler<MessagePinned> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: MessagePinned, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "message_pinned".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message(&msg.conversation_id, &message);
+	}
+}
+
+impl Handler<MessageUnpinned> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: MessageUnpinned, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "message_unpinned".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message(&msg.conversation_id, &message);
+	}
+}
+
 impl Handler<MessageDeleted> for WsServer {
 	type Result = ();
 
 	fn handle(&mut self, msg: MessageDeleted, _: &mut Context<Self>) -> Self::Result {
 		let conn = self.pool.get().unwrap();
-		let user_id = msg.author_id;
+		let user_id = msg.deleted_by;
 		let conversation_id = msg.conversation_id;
 		let user_ids = models::user::User::fetch_user_ids_by_conversation(&conversation_id, &user_id, &conn);
 		match user_ids {
@@ -233,13 +510,35 @@ impl Handler<NewMessage> for WsServer {
 		let user_ids = models::user::User::fetch_user_ids_by_conversation(&conversation_id, &user_id, &conn);
 		match user_ids {
 			Ok(user_ids) => {
+				let source_member_ids = match &msg.forwarded_from {
+					Some(models::forward::Provenance::Known { conversation_id: source_cid, .. }) => {
+						models::member::Member::fetch_user_ids(source_cid, &conn).unwrap_or_default()
+					}
+					_ => Vec::new()
+				};
 				let d : serde_json::Value = serde_json::to_value(&msg).unwrap();
-				let msg: ClientMessage = ClientMessage {
+				let message: ClientMessage = ClientMessage {
 					op: "new_message".to_string(),
 					d: Some(d)
 				};
+				let unknown_source = match msg.forwarded_from {
+					Some(models::forward::Provenance::Known { .. }) => {
+						let d: serde_json::Value = serde_json::to_value(&NewMessage {
+							forwarded_from: Some(models::forward::Provenance::Unknown),
+							..msg
+						}).unwrap();
+						Some(ClientMessage {
+							op: "new_message".to_string(),
+							d: Some(d)
+						})
+					}
+					_ => None
+				};
 				for user_id in user_ids {
-					self.send_message(&user_id, &msg);
+					match &unknown_source {
+						Some(unknown_source) if !source_member_ids.contains(&user_id) => self.send_message(&user_id, unknown_source),
+						_ => self.send_message(&user_id, &message)
+					}
 				}
 			}
 			_ => {}
@@ -247,6 +546,210 @@ impl Handler<NewMessage> for WsServer {
 	}
 }
 
+impl Handler<ConversationCreated> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: ConversationCreated, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "conversation_created".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message(&msg.conversation.id, &message);
+	}
+}
+
+impl Handler<ConversationUpdated> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: ConversationUpdated, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "conversation_updated".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message(&msg.conversation.id, &message);
+	}
+}
+
+impl Handler<MembersAdded> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: MembersAdded, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "members_added".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message(&msg.conversation_id, &message);
+	}
+}
+
+impl Handler<MemberRemoved> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: MemberRemoved, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "member_removed".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message(&msg.conversation_id, &message);
+		// the removed user is no longer an active member, so notify them directly
+		self.send_message(&msg.user_id, &message);
+	}
+}
+
+impl Handler<MemberRoleUpdated> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: MemberRoleUpdated, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "member_role_updated".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message(&msg.conversation_id, &message);
+	}
+}
+
+impl Handler<MemberProfileUpdated> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: MemberProfileUpdated, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "member_profile_updated".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message(&msg.conversation_id, &message);
+	}
+}
+
+impl Handler<JoinRequested> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: JoinRequested, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "join_requested".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message_with_permission(
+			&msg.conversation_id, models::permission::Permission::ReviewJoinRequests, &message
+		);
+	}
+}
+
+impl Handler<JoinRequestResolved> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: JoinRequestResolved, _: &mut Context<Self>) -> Self::Result {
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "join_request_resolved".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message_with_permission(
+			&msg.conversation_id, models::permission::Permission::ReviewJoinRequests, &message
+		);
+		self.send_message(&msg.user_id, &message);
+	}
+}
+
+impl Handler<MarkRead> for WsServer {
+	type Result = Option<models::member::Member>;
+
+	fn handle(&mut self, msg: MarkRead, _: &mut Context<Self>) -> Self::Result {
+		let conn = self.pool.get().unwrap();
+		let read_at = match msg.message_id {
+			Some(message_id) => {
+				match models::message::Message::fetch_by_id(&message_id, &conn) {
+					Ok(message) if message.conversation_id == msg.conversation_id => message.created_at,
+					_ => return None
+				}
+			}
+			None => chrono::Utc::now().naive_utc()
+		};
+		let member = models::member::Member::mark_read(&msg.user_id, &msg.conversation_id, &read_at, &conn).ok()?;
+		let receipt = ReadReceipt {
+			conversation_id: msg.conversation_id,
+			user_id: msg.user_id,
+			last_read_at: member.last_read_at
+		};
+		let d: serde_json::Value = serde_json::to_value(&receipt).unwrap();
+		let message: ClientMessage = ClientMessage {
+			op: "read_receipt".to_string(),
+			d: Some(d)
+		};
+		self.send_conversation_message(&msg.conversation_id, &message);
+		Some(member)
+	}
+}
+
+impl Handler<MessageEdited> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: MessageEdited, _: &mut Context<Self>) -> Self::Result {
+		let conn = self.pool.get().unwrap();
+		let user_id = msg.message.author_id;
+		let conversation_id = msg.message.conversation_id;
+		let user_ids = models::user::User::fetch_user_ids_by_conversation(&conversation_id, &user_id, &conn);
+		if let Ok(user_ids) = user_ids {
+			let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+			let message: ClientMessage = ClientMessage {
+				op: "message_edited".to_string(),
+				d: Some(d)
+			};
+			for user_id in user_ids {
+				self.send_message(&user_id, &message);
+			}
+		}
+	}
+}
+
+#[derive(Serialize)]
+#[serde(rename_all="camelCase")]
+struct MentionPayload<'a> {
+	message: &'a models::message::Message,
+	author_identity: &'a models::member::MemberIdentity,
+	attachments: &'a [models::attachment::Attachment],
+	kind: &'a str
+}
+
+impl Handler<MessageUpdated> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: MessageUpdated, _: &mut Context<Self>) -> Self::Result {
+		let conversation_id = msg.message.conversation_id;
+		let d: serde_json::Value = serde_json::to_value(&msg).unwrap();
+		self.send_conversation_message(&conversation_id, &ClientMessage {
+			op: "message_updated".to_string(),
+			d: Some(d)
+		});
+	}
+}
+
+impl Handler<Mentioned> for WsServer {
+	type Result = ();
+
+	fn handle(&mut self, msg: Mentioned, _: &mut Context<Self>) -> Self::Result {
+		for mention in msg.mentions.iter() {
+			let d: serde_json::Value = serde_json::to_value(&MentionPayload {
+				message: &msg.message,
+				author_identity: &msg.author_identity,
+				attachments: &msg.attachments,
+				kind: &mention.kind
+			}).unwrap();
+			let message: ClientMessage = ClientMessage {
+				op: "mentioned".to_string(),
+				d: Some(d)
+			};
+			self.send_message(&mention.user_id, &message);
+		}
+	}
+}
+
 impl Handler<NewUser> for WsServer {
 	type Result = ();
 
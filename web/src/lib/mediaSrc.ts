@@ -0,0 +1,9 @@
+import { useTokenStore } from './useTokenStore';
+const baseUrl = import.meta.env.VITE_API_URL;
+
+// Media served by the api needs the access token, which <img> and friends can't send as a header.
+export const mediaSrc = (url: string) => {
+  if (!url.startsWith(`${baseUrl}/media/`)) return url;
+  const accessToken = useTokenStore.getState().accessToken;
+  return `${url}?access_token=${encodeURIComponent(accessToken)}`;
+};
@@ -16,6 +16,7 @@ export type Conversation = {
     userName: string | null;
     content: string | null;
     createdAt: string | null;
+    attachments?: Attachment[];
   };
   withs: Array<{
     userId: string;
@@ -26,6 +27,14 @@ export type Conversation = {
   }> | null;
   createdAt: string;
   isGroup: boolean;
+  hasDraft?: boolean;
+};
+
+export type Draft = {
+  userId: string;
+  conversationId: string;
+  content: string;
+  updatedAt: string;
 };
 
 export type Member = {
@@ -43,6 +52,8 @@ export type Pagination<T> = {
   items: T[];
   nextCursor: string | null;
   prevCursor: string | null;
+  nextCursorId?: string;
+  prevCursorId?: string;
 };
 
 export type Message = {
@@ -53,9 +64,161 @@ export type Message = {
   isDeleted: boolean;
   createdAt: string;
   updatedAt: string | null;
-  reactions: Record<string, { createdAt: string; name: string }>;
+  reactions: ReactionSummary[];
   loading?: boolean;
-  isImage: boolean;
+  attachments: Attachment[];
+  linkPreviews?: LinkPreview[];
+  contentAst?: Block[] | null;
+  isForwarded?: boolean;
+  forwardedFrom?: Provenance | null;
+};
+
+export type Provenance =
+  | {
+      kind: 'known';
+      messageId: string | null;
+      conversationId: string;
+      conversationName: string | null;
+      author: {
+        userId: string;
+        username: string;
+        displayName: string;
+        avatarUrl: string | null;
+      } | null;
+    }
+  | { kind: 'unknown' };
+
+export type Block =
+  | { type: 'paragraph'; children: Inline[] }
+  | { type: 'codeBlock'; language: string | null; code: string }
+  | { type: 'quote'; children: Block[] };
+
+export type Inline =
+  | { type: 'text'; text: string }
+  | { type: 'bold'; children: Inline[] }
+  | { type: 'italic'; children: Inline[] }
+  | { type: 'spoiler'; children: Inline[] }
+  | { type: 'code'; code: string }
+  | { type: 'link'; url: string; children: Inline[] }
+  | { type: 'customEmoji'; id: string; name: string; url: string }
+  | { type: 'lineBreak' };
+
+export type CustomEmoji = {
+  id: string;
+  name: string;
+  conversationId: string | null;
+  uploadId: string;
+  createdBy: string;
+  createdAt: string;
+  url: string;
+};
+
+export type EmojiSuggestion = {
+  name: string;
+  unicode: string | null;
+  skins: string[];
+  url: string | null;
+  count?: number;
+};
+
+export type ReactionSummary = {
+  emoji: string;
+  count: number;
+  me: boolean;
+};
+
+export type ReactionUser = {
+  userId: string;
+  identity: {
+    userId: string;
+    username: string;
+    displayName: string;
+    avatarUrl: string | null;
+  } | null;
+  createdAt: string;
+};
+
+export type PinnedMessage = {
+  pinnedBy: string;
+  pinnedByIdentity: {
+    userId: string;
+    username: string;
+    displayName: string;
+    avatarUrl: string | null;
+  } | null;
+  pinnedAt: string;
+  message: Message;
+};
+
+export type ScheduledMessage = {
+  id: string;
+  conversationId: string;
+  authorId: string;
+  content: string | null;
+  attachments: AttachmentInput[];
+  sendAt: string;
+  createdAt: string;
+  updatedAt: string | null;
+  failedAttempts: number;
+};
+
+export type LinkPreview = {
+  url: string;
+  title: string | null;
+  description: string | null;
+  imageUrl: string | null;
+  siteName: string | null;
+};
+
+export type AttachmentKind = 'image' | 'gif' | 'video' | 'audio' | 'file';
+
+export type Attachment = {
+  id: string;
+  messageId: string;
+  kind: AttachmentKind;
+  url: string;
+  mimeType: string | null;
+  size: number | null;
+  width: number | null;
+  height: number | null;
+  filename: string | null;
+  position: number;
+  createdAt: string;
+  uploadId: string | null;
+  blurhash: string | null;
+  thumbnails: AttachmentThumbnail[];
+};
+
+export type AttachmentThumbnail = {
+  url: string;
+  width: number;
+  height: number;
+};
+
+export type AttachmentInput = {
+  kind: AttachmentKind;
+  url: string;
+  mime_type?: string;
+  size?: number;
+  width?: number;
+  height?: number;
+  filename?: string;
+  upload_id?: string;
+};
+
+export type Upload = {
+  id: string;
+  userId: string;
+  mimeType: string;
+  size: number;
+  filename: string | null;
+  purpose: 'attachment' | 'avatar';
+  createdAt: string;
+  width: number | null;
+  height: number | null;
+  blurhash: string | null;
+  mediaUrl: string;
+  thumbnails: AttachmentThumbnail[];
 };
 
 export type LoginInput = {
@@ -80,8 +243,8 @@ export type GifObject = {
 
 export type CreateMessageInput = {
   conversation_id: string;
-  content: string;
-  is_image: boolean;
+  content?: string;
+  attachments?: AttachmentInput[];
 };
 
 export type RefreshTokenResponse = {
@@ -7,11 +7,17 @@ import {
   RefreshTokenInput,
   RefreshTokenResponse,
   Conversation,
+  Draft,
   Member,
   Pagination,
   Message,
+  PinnedMessage,
+  ScheduledMessage,
   CreateMessageInput,
   GifObject,
+  ReactionUser,
+  CustomEmoji,
+  EmojiSuggestion,
 } from './models';
 
 export const wrap = (fetcher: Fetcher) => ({
@@ -20,8 +26,24 @@ export const wrap = (fetcher: Fetcher) => ({
     users: (): Promise<BaseUser[]> => fetcher.query('/user/list'),
     conversationByRecipient: (recipientId: string): Promise<Conversation> =>
       fetcher.query(`/conversation/recipient/${recipientId}`),
-    conversations: (): Promise<Conversation[]> =>
-      fetcher.query('/conversation/list'),
+    // The sidebar shows every conversation, so follow the cursors to the last page.
+    conversations: async (): Promise<Conversation[]> => {
+      const conversations: Conversation[] = [];
+      let cursor: { before?: string; before_id?: string } = {};
+      for (;;) {
+        const page: Pagination<Conversation> = await fetcher.query(
+          `/conversation/list?${new URLSearchParams({ limit: '50', ...cursor })}`
+        );
+        conversations.push(...page.items);
+        if (!page.nextCursor) {
+          return conversations;
+        }
+        cursor = {
+          before: page.nextCursor,
+          ...(page.nextCursorId ? { before_id: page.nextCursorId } : {}),
+        };
+      }
+    },
     conversation: (conversationId: string): Promise<Conversation> =>
       fetcher.query(`/conversation/${conversationId}`),
     conversationMembers: (conversationId: string): Promise<Member[]> =>
@@ -30,13 +52,55 @@ export const wrap = (fetcher: Fetcher) => ({
       fetcher.query(`/conversation/${conversationId}/users`),
     messages: (
       conversationId: string,
-      options: { before?: string; after?: string; limit?: string }
+      options: {
+        before?: string;
+        before_id?: string;
+        after?: string;
+        after_id?: string;
+        around?: string;
+        limit?: string;
+      }
     ): Promise<Pagination<Message>> =>
       fetcher.query(
         `/message/conversation/${conversationId}/list?${new URLSearchParams(
           options
         )}`
       ),
+    searchEmojis: (
+      q: string,
+      conversationId?: string
+    ): Promise<EmojiSuggestion[]> =>
+      fetcher.query(
+        `/emojis/search?${new URLSearchParams(
+          conversationId ? { q, conversation_id: conversationId } : { q }
+        )}`
+      ),
+    frequentEmojis: (conversationId?: string): Promise<EmojiSuggestion[]> =>
+      fetcher.query(
+        `/emojis/frequent${
+          conversationId ? `?conversation_id=${conversationId}` : ''
+        }`
+      ),
+    conversationEmojis: (conversationId: string): Promise<CustomEmoji[]> =>
+      fetcher.query(`/conversation/${conversationId}/emojis`),
+    reactionUsers: (data: {
+      messageId: string;
+      conversationId: string;
+      emojiName: string;
+    }): Promise<Pagination<ReactionUser>> =>
+      fetcher.query(
+        `/message/${data.messageId}/conversation/${data.conversationId}/reaction/${data.emojiName}/users`
+      ),
+    pinnedMessages: (conversationId: string): Promise<PinnedMessage[]> =>
+      fetcher.query(`/conversation/${conversationId}/pins`),
+    draft: (conversationId: string): Promise<Draft | null> =>
+      fetcher.query(`/conversation/${conversationId}/draft`),
+    scheduledMessages: (conversationId?: string): Promise<ScheduledMessage[]> =>
+      fetcher.query(
+        `/scheduled-messages${
+          conversationId ? `?conversation_id=${conversationId}` : ''
+        }`
+      ),
     searchGif: (query: string): Promise<GifObject[]> =>
       fetcher.query(`/giphy/search?q=${query}`),
   },
@@ -59,6 +123,58 @@ export const wrap = (fetcher: Fetcher) => ({
           method: 'DELETE',
         }
       ),
+    forwardMessage: (data: {
+      messageId: string;
+      conversationId: string;
+      conversationIds: string[];
+    }): Promise<Message[]> =>
+      fetcher.mutate(
+        `/message/${data.messageId}/conversation/${data.conversationId}/forward`,
+        { body: JSON.stringify({ conversationIds: data.conversationIds }) }
+      ),
+    saveDraft: (data: {
+      conversationId: string;
+      content: string;
+    }): Promise<Draft | null> =>
+      fetcher.mutate(`/conversation/${data.conversationId}/draft`, {
+        method: 'PUT',
+        body: JSON.stringify({ content: data.content }),
+      }),
+    deleteDraft: (conversationId: string): Promise<boolean> =>
+      fetcher.mutate(`/conversation/${conversationId}/draft`, {
+        method: 'DELETE',
+      }),
+    scheduleMessage: (
+      input: CreateMessageInput & { send_at: string }
+    ): Promise<ScheduledMessage> =>
+      fetcher.mutate('/scheduled-messages', { body: JSON.stringify(input) }),
+    editScheduledMessage: (data: {
+      id: string;
+      content?: string;
+      send_at?: string;
+    }): Promise<ScheduledMessage> =>
+      fetcher.mutate(`/scheduled-messages/${data.id}`, {
+        method: 'PATCH',
+        body: JSON.stringify({ content: data.content, send_at: data.send_at }),
+      }),
+    cancelScheduledMessage: (id: string): Promise<boolean> =>
+      fetcher.mutate(`/scheduled-messages/${id}`, { method: 'DELETE' }),
+    pinMessage: (data: {
+      messageId: string;
+      conversationId: string;
+    }): Promise<boolean> =>
+      fetcher.mutate(
+        `/message/${data.messageId}/conversation/${data.conversationId}/pin`,
+        { method: 'PUT' }
+      ),
+    unpinMessage: (data: {
+      messageId: string;
+      conversationId: string;
+    }): Promise<boolean> =>
+      fetcher.mutate(
+        `/message/${data.messageId}/conversation/${data.conversationId}/pin`,
+        { method: 'DELETE' }
+      ),
     createReaction: (data: {
       messageId: string;
       conversationId: string;
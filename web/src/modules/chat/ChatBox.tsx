@@ -72,11 +72,13 @@ const ChatBox = () => {
 
   React.useEffect(() => {
     const nextCursor = paginatedMessages?.nextCursor;
+    const nextCursorId = paginatedMessages?.nextCursorId;
     if (inView && nextCursor) {
       wrap(fetcher)
         .query['messages'](conversationId as string, {
           limit: `${25}`,
-          after: nextCursor,
+          before: nextCursor,
+          ...(nextCursorId ? { before_id: nextCursorId } : {}),
         })
         .then((res) => {
           updateQuery(
@@ -84,13 +86,13 @@ const ChatBox = () => {
             (paginatedMessages) => {
               paginatedMessages.items.push(...res.items);
               paginatedMessages.nextCursor = res.nextCursor;
-              paginatedMessages.prevCursor = res.prevCursor;
+              paginatedMessages.nextCursorId = res.nextCursorId;
               return paginatedMessages;
             }
           );
         });
     }
-  }, [inView, paginatedMessages?.nextCursor]);
+  }, [inView, paginatedMessages?.nextCursor, paginatedMessages?.nextCursorId]);
 
   React.useEffect(() => {
     console.log('user map ', userMap);
@@ -128,7 +128,18 @@ export const InputController: React.FC<InputControllerProps> = ({
     if (typeof activeGifIndex === 'number') {
       const gif = queriedGifs[activeGifIndex];
       mutate([
-        { content: gif.url, is_image: true, conversation_id: conversationId },
+        {
+          conversation_id: conversationId,
+          attachments: [
+            {
+              kind: 'gif',
+              url: gif.url,
+              mime_type: 'image/gif',
+              width: gif.width,
+              height: gif.height,
+            },
+          ],
+        },
       ]);
       setActiveGifIndex(undefined);
       clearChatInput();
@@ -439,7 +450,6 @@ export const InputController: React.FC<InputControllerProps> = ({
                         serialize(messageOption.children).trim() +
                         ' ¯\\_(ツ)_/¯',
                       conversation_id: conversationId,
-                      is_image: false,
                     },
                   ]);
                   clearChatInput();
@@ -453,7 +463,6 @@ export const InputController: React.FC<InputControllerProps> = ({
               {
                 content: serializedValue,
                 conversation_id: conversationId,
-                is_image: false,
               },
             ]);
             console.log('value ', serializedValue);
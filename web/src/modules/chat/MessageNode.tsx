@@ -3,7 +3,7 @@ import { Avatar } from '../../components/Avatar';
 import { EmojiAddIcon } from '../../icons/EmojiAddIcons';
 import { BaseUser, Member, Message } from '../../lib/models';
 import { MessageReactions } from './MessageReactions';
-import { MessageText } from './MessageText';
+import { MessageAst, MessageText } from './MessageText';
 import EmojiPicker from '../emojiPicker/EmojiPicker';
 import { emojiData } from '../emojiPicker/emojiData';
 import { useTypeSafeUpdateQuery } from '../../hooks/useTypeSafeUpdateQuery';
@@ -15,6 +15,8 @@ import { SolidTrashIcon } from '../../icons/SolidTrashIcon';
 import { useDeleteMessageModalStore } from './useDeleteMessageModalStore';
 import { ee } from '../../lib/ee';
 import { useContextMenuContext } from './MessageContextMenu';
+import { mediaSrc } from '../../lib/mediaSrc';
+import { applyReaction } from './applyReaction';
 
 interface MessageNodeProps {
   message: Message;
@@ -149,7 +151,10 @@ export const MessageNode: React.FC<MessageNodeProps> = ({
               showNavbar
               emojiData={emojiData}
               onEmojiSelect={(emoji) => {
-                if (message.reactions?.[`${me?.id}:${emoji.name}`]) return;
+                if (
+                  message.reactions?.some((r) => r.emoji === emoji.name && r.me)
+                )
+                  return;
                 createReaction(
                   [
                     {
@@ -167,12 +172,10 @@ export const MessageNode: React.FC<MessageNodeProps> = ({
                             (m) => m.id === message?.id
                           );
                           if (msg) {
-                            if (!msg.reactions?.[`${me?.id}:${emoji.name}`]) {
-                              msg.reactions[`${me?.id}:${emoji.name}`] = {
-                                createdAt: new Date().toISOString(),
-                                name: emoji.name,
-                              };
-                            }
+                            applyReaction(msg, emoji.name, {
+                              created: true,
+                              me: true,
+                            });
                           }
                           return messages;
                         }
@@ -210,22 +213,102 @@ export const MessageNode: React.FC<MessageNodeProps> = ({
           <p className='italic bold'>
             <b>this message has been deleted</b>
           </p>
-        ) : message.isImage ? (
-          <div className='w-full max-w-[360px] rounded-md overflow-hidden mt-1 mb-2'>
-            <img
-              className='object-cover'
-              src={message.content}
-              onLoad={() => {
-                if (message.loading) {
-                  setTimeout(() => {
-                    ee.emit('chatbox_scroll_to_bottom');
-                  }, 100);
-                }
-              }}
-            />
-          </div>
         ) : (
-          <MessageText text={message.content} loading={message.loading} />
+          <>
+            {message.attachments?.map((attachment) =>
+              attachment.kind === 'image' || attachment.kind === 'gif' ? (
+                <div
+                  key={attachment.id}
+                  className='w-full max-w-[360px] rounded-md overflow-hidden mt-1 mb-2'
+                >
+                  <img
+                    className='object-cover'
+                    src={mediaSrc(
+                      attachment.thumbnails?.find((t) => t.width >= 360)
+                        ?.url ?? attachment.url
+                    )}
+                    width={attachment.width ?? undefined}
+                    height={attachment.height ?? undefined}
+                    style={
+                      attachment.width && attachment.height
+                        ? {
+                            aspectRatio: `${attachment.width} / ${attachment.height}`,
+                          }
+                        : undefined
+                    }
+                    alt={attachment.filename ?? ''}
+                    onLoad={() => {
+                      if (message.loading) {
+                        setTimeout(() => {
+                          ee.emit('chatbox_scroll_to_bottom');
+                        }, 100);
+                      }
+                    }}
+                  />
+                </div>
+              ) : attachment.kind === 'video' ? (
+                <video
+                  key={attachment.id}
+                  className='w-full max-w-[360px] rounded-md mt-1 mb-2'
+                  src={mediaSrc(attachment.url)}
+                  controls
+                />
+              ) : attachment.kind === 'audio' ? (
+                <audio
+                  key={attachment.id}
+                  className='mt-1 mb-2'
+                  src={mediaSrc(attachment.url)}
+                  controls
+                />
+              ) : (
+                <a
+                  key={attachment.id}
+                  className='block underline mt-1 mb-2'
+                  href={mediaSrc(attachment.url)}
+                  target='_blank'
+                  rel='noreferrer'
+                >
+                  {attachment.filename ?? attachment.url}
+                </a>
+              )
+            )}
+            {message.content ? (
+              message.contentAst ? (
+                <MessageAst
+                  blocks={message.contentAst}
+                  loading={message.loading}
+                />
+              ) : (
+                <MessageText text={message.content} loading={message.loading} />
+              )
+            ) : null}
+            {message.linkPreviews?.map((preview) => (
+              <a
+                key={preview.url}
+                className='block max-w-[420px] border-l-4 border-blue-500 rounded-md bg-gray-100 p-3 mt-1 mb-2'
+                href={preview.url}
+                target='_blank'
+                rel='noreferrer'
+              >
+                {preview.siteName ? (
+                  <p className='text-xs text-gray-500'>{preview.siteName}</p>
+                ) : null}
+                {preview.title ? (
+                  <p className='font-bold text-blue-600'>{preview.title}</p>
+                ) : null}
+                {preview.description ? (
+                  <p className='text-sm'>{preview.description}</p>
+                ) : null}
+                {preview.imageUrl ? (
+                  <img
+                    className='mt-2 rounded-md max-h-[200px] object-cover'
+                    src={preview.imageUrl}
+                    alt=''
+                  />
+                ) : null}
+              </a>
+            ))}
+          </>
         )}
         {message.reactions ? (
           <MessageReactions
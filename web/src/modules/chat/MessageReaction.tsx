@@ -2,18 +2,14 @@ import React from 'react';
 import { useTypeSafeMutation } from '../../hooks/useTypeSafeMutation';
 import { useTypeSafeQuery } from '../../hooks/useTypeSafeQuery';
 import { useTypeSafeUpdateQuery } from '../../hooks/useTypeSafeUpdateQuery';
-import { BaseUser } from '../../lib/models';
+import { ReactionSummary } from '../../lib/models';
 import { emojiMap } from '../emojiPicker/emojiData';
-
-interface ReactionData {
-  me: boolean;
-  users: BaseUser[];
-  latest: string;
-}
+import { applyReaction } from './applyReaction';
+import { mediaSrc } from '../../lib/mediaSrc';
 
 interface MessageReactionProps {
   emojiName: string;
-  d: ReactionData;
+  d: ReactionSummary;
   conversationId: string;
   messageId: string;
 }
@@ -24,12 +20,27 @@ export const MessageReaction: React.FC<MessageReactionProps> = ({
   d,
   messageId,
 }) => {
-  const { data: me } = useTypeSafeQuery('me');
   const { mutate: deleteReaction } = useTypeSafeMutation('deleteReaction');
   const { mutate: createReaction } = useTypeSafeMutation('createReaction');
   const updateQuery = useTypeSafeUpdateQuery();
   const [showTooltip, setShowTooltip] = React.useState(false);
   const [tooltipPos, setTooltipPos] = React.useState<'top' | 'bottom'>('top');
+  const { data: customEmojis } = useTypeSafeQuery(
+    ['conversationEmojis', conversationId],
+    { enabled: !emojiMap[emojiName], staleTime: 60_000 },
+    [conversationId]
+  );
+  const customEmoji = customEmojis?.find((e) => e.name === emojiName);
+  const emojiSrc = emojiMap[emojiName]
+    ? `/twemoji.svg#${emojiMap[emojiName].unicode}`
+    : customEmoji
+    ? mediaSrc(customEmoji.url)
+    : undefined;
+  const { data: reactors } = useTypeSafeQuery(
+    ['reactionUsers', messageId, emojiName, d.count],
+    { enabled: showTooltip },
+    [{ messageId, conversationId, emojiName }]
+  );
 
   return (
     <div
@@ -54,9 +65,10 @@ export const MessageReaction: React.FC<MessageReactionProps> = ({
                     (m) => m.id === messageId
                   );
                   if (message) {
-                    if (message.reactions?.[`${me?.id}:${emojiName}`]) {
-                      delete message.reactions?.[`${me?.id}:${emojiName}`];
-                    }
+                    applyReaction(message, emojiName, {
+                      created: false,
+                      me: false,
+                    });
                   }
                   return messages;
                 });
@@ -79,10 +91,10 @@ export const MessageReaction: React.FC<MessageReactionProps> = ({
                     (m) => m.id === messageId
                   );
                   if (message) {
-                    message.reactions[`${me?.id}:${emojiName}`] = {
-                      createdAt: new Date().toISOString(),
-                      name: emojiName,
-                    };
+                    applyReaction(message, emojiName, {
+                      created: true,
+                      me: true,
+                    });
                   }
                   return messages;
                 });
@@ -104,12 +116,16 @@ export const MessageReaction: React.FC<MessageReactionProps> = ({
             <span className='w-12 h-12 inline-block mr-1 flex-shrink-0'>
               <img
                 className='m-0 w-12 h-12 object-cover'
-                src={`/twemoji.svg#${emojiMap[emojiName].unicode}`}
+                src={emojiSrc}
+                alt={`:${emojiName}:`}
               />
             </span>
             <div className='w-50'>
               <p className='text-sm font-semibold text-gray-600'>
-                {d.users.map((u) => u.username).join(', ')} reacted with :
+                {(reactors?.items ?? [])
+                  .map((u) => u.identity?.displayName ?? 'Unknown user')
+                  .join(', ')}
+                {reactors?.nextCursor ? ' and others' : ''} reacted with :
                 {emojiName}:
               </p>
             </div>
@@ -120,10 +136,11 @@ export const MessageReaction: React.FC<MessageReactionProps> = ({
       <span className='w-5 h-5 inline-block mr-1'>
         <img
           className='m-0 w-5 h-5 object-cover'
-          src={`/twemoji.svg#${emojiMap[emojiName].unicode}`}
+          src={emojiSrc}
+          alt={`:${emojiName}:`}
         />
       </span>
-      <span>{d.users.length}</span>
+      <span>{d.count}</span>
     </div>
   );
 };
@@ -1,7 +1,9 @@
 import React from 'react';
+import { Block, Inline } from '../../lib/models';
 import { emojiMap } from '../emojiPicker/emojiData';
 import { parseText } from '../slateStuff/parseText';
 import { useUserMapStore } from './useUserMapStore';
+import { mediaSrc } from '../../lib/mediaSrc';
 
 interface MessageTextProps {
   text: string;
@@ -100,7 +102,91 @@ export const MessageText: React.FC<MessageTextProps> = ({ text, loading }) => {
   );
 };
 
-const Spoiler = ({ text }: { text: string }) => {
+const renderInlines = (nodes: Inline[]): React.ReactNode[] =>
+  nodes.map((node, index) => {
+    switch (node.type) {
+      case 'text':
+        return (
+          <span className='break-all' key={index}>
+            {node.text}
+          </span>
+        );
+      case 'bold':
+        return <b key={index}>{renderInlines(node.children)}</b>;
+      case 'italic':
+        return <i key={index}>{renderInlines(node.children)}</i>;
+      case 'spoiler':
+        return <Spoiler key={index}>{renderInlines(node.children)}</Spoiler>;
+      case 'code':
+        return <code key={index}>{node.code}</code>;
+      case 'link':
+        return (
+          <a
+            key={index}
+            href={node.url}
+            target='_blank'
+            rel='noreferrer'
+            className='text-blue-500 underline'
+          >
+            {renderInlines(node.children)}
+          </a>
+        );
+      case 'customEmoji':
+        return (
+          <img
+            key={index}
+            className='inline-block m-0 w-5 h-5 object-contain'
+            src={mediaSrc(node.url)}
+            alt={`:${node.name}:`}
+            title={`:${node.name}:`}
+          />
+        );
+      case 'lineBreak':
+        return <br key={index} />;
+    }
+  });
+
+const renderBlocks = (blocks: Block[]): React.ReactNode[] =>
+  blocks.map((block, index) => {
+    switch (block.type) {
+      case 'paragraph':
+        return <p key={index}>{renderInlines(block.children)}</p>;
+      case 'codeBlock':
+        return (
+          <pre key={index} data-language={block.language ?? undefined}>
+            <code>{block.code}</code>
+          </pre>
+        );
+      case 'quote':
+        return (
+          <blockquote key={index}>{renderBlocks(block.children)}</blockquote>
+        );
+    }
+  });
+
+// Renders the formatting the server parsed, so every client shows the same thing.
+export const MessageAst: React.FC<{ blocks: Block[]; loading?: boolean }> = ({
+  blocks,
+  loading,
+}) => {
+  return (
+    <div
+      className={`prose max-w-full mr-4 text-[15px] md:mr-8 lg:mr-12 ${
+        loading ? 'opacity-40' : ''
+      }`}
+    >
+      {renderBlocks(blocks)}
+    </div>
+  );
+};
+
+const Spoiler = ({
+  text,
+  children,
+}: {
+  text?: string;
+  children?: React.ReactNode;
+}) => {
   const [show, setShow] = React.useState(false);
   return (
     <span
@@ -113,7 +199,7 @@ const Spoiler = ({ text }: { text: string }) => {
         setShow(true);
       }}
     >
-      {text}
+      {children ?? text}
     </span>
   );
 };
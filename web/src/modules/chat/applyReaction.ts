@@ -0,0 +1,26 @@
+import { Message } from '../../lib/models';
+
+// Updates the reaction summary of a cached message in place.
+// `count` is the server's total when known; otherwise it's adjusted by one.
+export const applyReaction = (
+  message: Message,
+  emojiName: string,
+  update: { created: boolean; me?: boolean; count?: number }
+) => {
+  if (!message.reactions) {
+    message.reactions = [];
+  }
+  let summary = message.reactions.find((r) => r.emoji === emojiName);
+  if (!summary) {
+    if (!update.created) {
+      return;
+    }
+    summary = { emoji: emojiName, count: 0, me: false };
+    message.reactions.push(summary);
+  }
+  summary.count = update.count ?? summary.count + (update.created ? 1 : -1);
+  if (update.me !== undefined) {
+    summary.me = update.me;
+  }
+  message.reactions = message.reactions.filter((r) => r.count > 0);
+};
@@ -23,12 +23,29 @@ export const useCreateMessage = () => {
         paginatedMessages.items.unshift({
           id: fakeId,
           authorId: me!.id,
-          content: newMessage.content,
+          content: newMessage.content ?? '',
           conversationId: newMessage.conversation_id,
           createdAt: new Date().toISOString(),
           isDeleted: false,
-          reactions: {},
-          isImage: newMessage.is_image,
+          reactions: [],
+          attachments: (newMessage.attachments ?? []).map(
+            (attachment, position) => ({
+              id: `${fakeId}-${position}`,
+              messageId: fakeId,
+              kind: attachment.kind,
+              url: attachment.url,
+              mimeType: attachment.mime_type ?? null,
+              size: attachment.size ?? null,
+              width: attachment.width ?? null,
+              height: attachment.height ?? null,
+              filename: attachment.filename ?? null,
+              position,
+              createdAt: new Date().toISOString(),
+              uploadId: attachment.upload_id ?? null,
+              blurhash: null,
+              thumbnails: [],
+            })
+          ),
           updatedAt: null,
           loading: true,
         });
@@ -54,6 +71,7 @@ export const useCreateMessage = () => {
           userId: me?.id as any,
           createdAt: message.createdAt,
           userName: me?.username as any,
+          attachments: message.attachments,
         };
         return conversations;
       });
@@ -30,7 +30,10 @@ export const ConversationItem: React.FC<ConversationItemProps> = ({
       <div className='ml-3 w-[200px]'>
         <h3 className='text-[15px]'>{withs?.[0]?.username}</h3>
         <p className='text-[12px] h-[20px] text-[#65676b] truncate max-w-[200px]'>
-          {conversation.lastMessageDisplay.content}
+          {conversation.lastMessageDisplay.content ??
+            (conversation.lastMessageDisplay.attachments?.length
+              ? `sent ${conversation.lastMessageDisplay.attachments[0].kind}`
+              : null)}
         </p>
       </div>
     </div>
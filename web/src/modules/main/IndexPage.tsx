@@ -13,6 +13,7 @@ import { useDropzone } from 'react-dropzone';
 import { useCreateMessage } from '../chat/useCreateMessage';
 import { useQueryClient } from 'react-query';
 import { WebSocketContext } from '../ws/WebSocketProvider';
+import { Upload } from '../../lib/models';
 
 let VITE_API_URL = import.meta.env.VITE_API_URL;
 
@@ -26,22 +27,9 @@ export const IndexPage = () => {
   const [isOpen, setOpen] = React.useState(false);
   const menuRef = React.useRef<HTMLDivElement>(null);
   const menuTrans = useTransition(isOpen, 300);
-  const [timestamp, setTimestamp] = React.useState<number>();
-  const [apiKey, setApiKey] = React.useState<string>();
-  const [signature, setSignature] = React.useState<string>();
   const queryClient = useQueryClient();
   const { socket, setSocket } = useContext(WebSocketContext);
 
-  React.useEffect(() => {
-    fetch(`${VITE_API_URL}/image/signed-signature`)
-      .then((res) => res.json())
-      .then((data) => {
-        setTimestamp(data.timestamp);
-        setApiKey(data.apiKey);
-        setSignature(data.signature);
-      });
-  }, []);
-
   useOnClickOutside(menuRef, () => {
     setOpen(false);
   });
@@ -65,24 +53,31 @@ export const IndexPage = () => {
       if (!conversationId) return;
       const file = files?.[0];
       if (!file) return;
-      const formData = new FormData();
-      formData.append('file', file);
-      formData.append('api_key', apiKey!);
-      formData.append('folder', 'tinychat');
-      formData.append('timestamp', timestamp!.toString());
-      formData.append('signature', signature!);
-
-      fetch('https://api.cloudinary.com/v1_1/dx1jwn9cz/image/upload', {
-        method: 'POST',
-        body: formData,
-      })
+      fetch(
+        `${VITE_API_URL}/media/upload?filename=${encodeURIComponent(
+          file.name
+        )}`,
+        {
+          method: 'POST',
+          body: file,
+          headers: {
+            'X-Access-Token': useTokenStore.getState().accessToken,
+            'Content-Type': file.type,
+          },
+        }
+      )
         .then((res) => res.json())
-        .then((data) => {
+        .then((upload: Upload) => {
           createMessage([
             {
               conversation_id: conversationId,
-              content: data.secure_url,
-              is_image: true,
+              attachments: [
+                {
+                  kind: upload.mimeType === 'image/gif' ? 'gif' : 'image',
+                  url: upload.mediaUrl,
+                  upload_id: upload.id,
+                },
+              ],
             },
           ]);
         });
@@ -4,8 +4,16 @@ import { HashLoader } from '../../components/HashLoader';
 import { useTypeSafeQuery } from '../../hooks/useTypeSafeQuery';
 import { useTypeSafeUpdateQuery } from '../../hooks/useTypeSafeUpdateQuery';
 import { fetcher } from '../../lib/fetcher';
-import { BaseUser, Conversation, Message } from '../../lib/models';
+import {
+  Attachment,
+  BaseUser,
+  Conversation,
+  Draft,
+  LinkPreview,
+  Message,
+} from '../../lib/models';
 import { useRefreshToken } from '../../lib/useRefreshToken';
+import { applyReaction } from '../chat/applyReaction';
 import { wrap } from '../../lib/wrapper';
 import { useWsHandlerStore } from './useWsHandlerStore';
 import { WebSocketContext } from './WebSocketProvider';
@@ -35,7 +43,11 @@ export const useMainWsHandler = () => {
           ]);
           if (messages) {
             updateQuery(['messages', d.message.conversationId], (messages) => {
-              messages.items.unshift(d.message);
+              messages.items.unshift({
+                ...d.message,
+                attachments: d.attachments,
+                forwardedFrom: d.forwardedFrom,
+              });
               return messages;
             });
           }
@@ -49,6 +61,7 @@ export const useMainWsHandler = () => {
                 userName: d.author.username,
                 createdAt: d.message.createdAt,
                 userId: d.message.authorId,
+                attachments: d.attachments,
               };
               return conversations;
             });
@@ -136,6 +149,7 @@ export const useMainWsHandler = () => {
           conversationId: string;
           messageId: string;
           emojiName: string;
+          count: number;
           updateType: 'CREATED' | 'DELETED';
           userId: string;
         }) => {
@@ -144,16 +158,10 @@ export const useMainWsHandler = () => {
             if (!message) {
               return messages;
             }
-            if (d.updateType === 'DELETED') {
-              if (message?.reactions?.[`${d.userId}:${d.emojiName}`]) {
-                delete message.reactions[`${d.userId}:${d.emojiName}`];
-              }
-            } else if (d.updateType === 'CREATED') {
-              message.reactions[`${d.userId}:${d.emojiName}`] = {
-                createdAt: new Date().toISOString(),
-                name: d.emojiName,
-              };
-            }
+            applyReaction(message, d.emojiName, {
+              created: d.updateType === 'CREATED',
+              count: d.count,
+            });
             return messages;
           });
         }
@@ -169,6 +177,61 @@ export const useMainWsHandler = () => {
             }
             return messages;
           });
+          queryClient.invalidateQueries(['pinnedMessages', d.conversationId]);
+        }
+      ),
+      addListener(
+        'scheduled_message_sent',
+        (d: { message: Message; attachments: Attachment[] }) => {
+          if (queryClient.getQueryData(['messages', d.message.conversationId])) {
+            updateQuery(['messages', d.message.conversationId], (messages) => {
+              messages.items.unshift({
+                ...d.message,
+                attachments: d.attachments,
+              });
+              return messages;
+            });
+          }
+          queryClient.invalidateQueries('conversations');
+          queryClient.invalidateQueries('scheduledMessages');
+        }
+      ),
+      addListener('scheduled_message_dropped', () => {
+        queryClient.invalidateQueries('scheduledMessages');
+      }),
+      addListener(
+        'draft_updated',
+        (d: { conversationId: string; draft: Draft | null }) => {
+          queryClient.setQueryData(['draft', d.conversationId], d.draft);
+          if (queryClient.getQueryData('conversations')) {
+            updateQuery('conversations', (conversations) => {
+              const conversation = conversations?.find(
+                (c) => c.id === d.conversationId
+              );
+              if (conversation) {
+                conversation.hasDraft = !!d.draft;
+              }
+              return conversations;
+            });
+          }
+        }
+      ),
+      addListener('message_pinned', (d: { conversationId: string }) => {
+        queryClient.invalidateQueries(['pinnedMessages', d.conversationId]);
+      }),
+      addListener('message_unpinned', (d: { conversationId: string }) => {
+        queryClient.invalidateQueries(['pinnedMessages', d.conversationId]);
+      }),
+      addListener(
+        'message_updated',
+        (d: { message: Message; linkPreviews: LinkPreview[] }) => {
+          updateQuery(['messages', d.message.conversationId], (messages) => {
+            const message = messages.items.find((m) => m.id === d.message.id);
+            if (message) {
+              message.linkPreviews = d.linkPreviews;
+            }
+            return messages;
+          });
         }
       ),
       addListener('new_user', (d: { user: BaseUser }) => {